@@ -0,0 +1,98 @@
+use super::{Move, Square};
+
+/// Colours of board annotations, as in PGN `[%csl]`/`[%cal]` commands.
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Copy)]
+pub enum AnnotationColor {
+    Green,
+    Red,
+    Blue,
+    Yellow,
+}
+
+impl AnnotationColor {
+    pub fn pgn_letter(&self) -> char {
+        match self {
+            AnnotationColor::Green => 'G',
+            AnnotationColor::Red => 'R',
+            AnnotationColor::Blue => 'B',
+            AnnotationColor::Yellow => 'Y',
+        }
+    }
+
+    pub fn from_pgn_letter(letter: char) -> Option<AnnotationColor> {
+        match letter {
+            'G' => Some(AnnotationColor::Green),
+            'R' => Some(AnnotationColor::Red),
+            'B' => Some(AnnotationColor::Blue),
+            'Y' => Some(AnnotationColor::Yellow),
+            _ => None,
+        }
+    }
+}
+
+/// Arrows and marked squares drawn on a position.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Annotations {
+    pub marks: Vec<(Square, AnnotationColor)>,
+    pub arrows: Vec<(Move, AnnotationColor)>,
+}
+
+impl Annotations {
+    pub fn is_empty(&self) -> bool {
+        self.marks.is_empty() && self.arrows.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.marks.clear();
+        self.arrows.clear();
+    }
+
+    /// Marks `square`, recolours its mark, or removes it when the colour is the same.
+    pub fn toggle_mark(&mut self, square: Square, color: AnnotationColor) {
+        toggle(&mut self.marks, square, color);
+    }
+
+    /// Same as `toggle_mark`, for the arrow along `arrow`.
+    pub fn toggle_arrow(&mut self, arrow: Move, color: AnnotationColor) {
+        toggle(&mut self.arrows, arrow, color);
+    }
+
+    /// `[%csl ...][%cal ...]` commands for a PGN comment, empty without annotations.
+    pub fn to_pgn_commands(&self) -> String {
+        let mut commands = String::new();
+        if !self.marks.is_empty() {
+            let marks: Vec<String> = self
+                .marks
+                .iter()
+                .map(|(square, color)| format!("{}{}", color.pgn_letter(), square))
+                .collect();
+            commands.push_str(&format!("[%csl {}]", marks.join(",")));
+        }
+        if !self.arrows.is_empty() {
+            let arrows: Vec<String> = self
+                .arrows
+                .iter()
+                .map(|(arrow, color)| format!("{}{}", color.pgn_letter(), arrow))
+                .collect();
+            commands.push_str(&format!("[%cal {}]", arrows.join(",")));
+        }
+        commands
+    }
+}
+
+fn toggle<T: PartialEq>(
+    annotations: &mut Vec<(T, AnnotationColor)>,
+    target: T,
+    color: AnnotationColor,
+) {
+    match annotations
+        .iter()
+        .position(|(existing, _)| *existing == target)
+    {
+        Some(index) if annotations[index].1 == color => {
+            annotations.remove(index);
+        }
+        Some(index) => annotations[index].1 = color,
+        None => annotations.push((target, color)),
+    }
+}
@@ -0,0 +1,427 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use super::{Board, Color, PieceKind, Square};
+
+/// Time a player gets for each move on top of the main time.
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Copy)]
+pub enum TimeBonus {
+    None,
+    /// Fischer increment, added after every move.
+    Increment(Duration),
+    /// Bronstein delay: the time a move took is given back, up to this much.
+    Bronstein(Duration),
+    /// Simple delay: the clock waits this long before counting down.
+    Delay(Duration),
+}
+
+/// One period of a time control, like the first 40 moves in 90 minutes.
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Copy)]
+pub struct TimeControlStage {
+    /// Moves to make in this stage, `None` for the rest of the game. The
+    /// last stage starts over when it has a move count.
+    pub moves: Option<u32>,
+    pub time: Duration,
+    pub bonus: TimeBonus,
+}
+
+/// Stages of a time control, in order. Written like the PGN `TimeControl`
+/// tag: `300+2`, or `40/5400+30:1800+30` for several stages. Delays have no
+/// PGN notation and are written `300d3` (simple) and `300b3` (Bronstein).
+#[derive(Eq, Hash, PartialEq, Debug, Clone)]
+pub struct TimeControl {
+    /// Never empty.
+    stages: Vec<TimeControlStage>,
+}
+
+impl TimeControl {
+    /// A time control of `stages` in order, `None` without any.
+    pub fn new(stages: Vec<TimeControlStage>) -> Option<TimeControl> {
+        (!stages.is_empty()).then_some(TimeControl { stages })
+    }
+
+    pub fn stages(&self) -> &[TimeControlStage] {
+        &self.stages
+    }
+
+    pub fn sudden_death(time: Duration) -> TimeControl {
+        TimeControl::single(time, TimeBonus::None)
+    }
+
+    pub fn fischer(time: Duration, increment: Duration) -> TimeControl {
+        TimeControl::single(time, TimeBonus::Increment(increment))
+    }
+
+    pub fn bronstein(time: Duration, delay: Duration) -> TimeControl {
+        TimeControl::single(time, TimeBonus::Bronstein(delay))
+    }
+
+    pub fn simple_delay(time: Duration, delay: Duration) -> TimeControl {
+        TimeControl::single(time, TimeBonus::Delay(delay))
+    }
+
+    fn single(time: Duration, bonus: TimeBonus) -> TimeControl {
+        TimeControl {
+            stages: vec![TimeControlStage {
+                moves: None,
+                time,
+                bonus,
+            }],
+        }
+    }
+
+    /// Reads the notation in `Display`, seconds throughout.
+    pub fn parse(text: &str) -> Option<TimeControl> {
+        let stages = text
+            .split(':')
+            .map(|stage| {
+                let (moves, rest) = match stage.split_once('/') {
+                    Some((moves, rest)) => (Some(moves.parse().ok()?), rest),
+                    None => (None, stage),
+                };
+                let bonus_start = rest.find(['+', 'd', 'b']).unwrap_or(rest.len());
+                let time = Duration::from_secs(rest[..bonus_start].parse().ok()?);
+                let bonus = match rest[bonus_start..].split_at_checked(1) {
+                    None | Some(("", _)) => TimeBonus::None,
+                    Some((kind, seconds)) => {
+                        let seconds = Duration::from_secs(seconds.parse().ok()?);
+                        match kind {
+                            "+" => TimeBonus::Increment(seconds),
+                            "d" => TimeBonus::Delay(seconds),
+                            _ => TimeBonus::Bronstein(seconds),
+                        }
+                    }
+                };
+                Some(TimeControlStage { moves, time, bonus })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        TimeControl::new(stages)
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, stage) in self.stages.iter().enumerate() {
+            if index > 0 {
+                write!(f, ":")?;
+            }
+            if let Some(moves) = stage.moves {
+                write!(f, "{}/", moves)?;
+            }
+            write!(f, "{}", stage.time.as_secs())?;
+            match stage.bonus {
+                TimeBonus::None => (),
+                TimeBonus::Increment(seconds) => write!(f, "+{}", seconds.as_secs())?,
+                TimeBonus::Delay(seconds) => write!(f, "d{}", seconds.as_secs())?,
+                TimeBonus::Bronstein(seconds) => write!(f, "b{}", seconds.as_secs())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Both players' clocks under one time control. Times are read against the
+/// `Instant`s passed in, so a test or a server can drive the clock.
+#[derive(Clone, Debug)]
+pub struct ChessClock {
+    control: TimeControl,
+    remaining: [Duration; 2],
+    /// Stage index and moves made in it, per side.
+    stages: [(usize, u32); 2],
+    /// Side whose time is running and since when.
+    running: Option<(Color, Instant)>,
+    flagged: Option<Color>,
+}
+
+impl ChessClock {
+    /// Stopped clocks with the time of the first stage.
+    pub fn new(control: TimeControl) -> ChessClock {
+        let time = control.stages[0].time;
+        ChessClock {
+            control,
+            remaining: [time; 2],
+            stages: [(0, 0); 2],
+            running: None,
+            flagged: None,
+        }
+    }
+
+    pub fn control(&self) -> &TimeControl {
+        &self.control
+    }
+
+    pub fn running_color(&self) -> Option<Color> {
+        self.running.map(|(color, _)| color)
+    }
+
+    /// Runs the clock of `color` from `now`, charging whoever ran before but
+    /// without any move bonus, e.g. to start the game or after an undo.
+    pub fn start(&mut self, color: Color, now: Instant) {
+        self.stop(now);
+        if self.flagged.is_none() {
+            self.running = Some((color, now));
+        }
+    }
+
+    /// Charges the running side up to `now` and stops both clocks.
+    pub fn stop(&mut self, now: Instant) {
+        if let Some((color, started_at)) = self.running.take() {
+            let spent = self.spent(color, started_at, now);
+            self.charge(color, spent);
+        }
+    }
+
+    /// The running side made its move at `now`: its time is charged, its
+    /// bonus and any new stage added, and the opponent's clock starts.
+    /// Returns the mover's time left, as written in `[%clk]`, or `None` if
+    /// the flag fell before the move.
+    pub fn press(&mut self, now: Instant) -> Option<Duration> {
+        let (color, started_at) = self.running.take()?;
+        let elapsed = now.saturating_duration_since(started_at);
+        if !self.charge(color, self.spent(color, started_at, now)) {
+            return None;
+        }
+
+        let (stage_index, moves_made) = &mut self.stages[color_index(color)];
+        let stage = self.control.stages[*stage_index];
+        let remaining = &mut self.remaining[color_index(color)];
+        *remaining += match stage.bonus {
+            TimeBonus::Increment(increment) => increment,
+            TimeBonus::Bronstein(delay) => elapsed.min(delay),
+            TimeBonus::None | TimeBonus::Delay(_) => Duration::ZERO,
+        };
+        *moves_made += 1;
+        if stage.moves == Some(*moves_made) {
+            if *stage_index + 1 < self.control.stages.len() {
+                *stage_index += 1;
+            }
+            *moves_made = 0;
+            *remaining += self.control.stages[*stage_index].time;
+        }
+        let remaining = *remaining;
+
+        self.running = Some((color.opposite(), now));
+        Some(remaining)
+    }
+
+    /// Sets `color` back to `remaining` after `moves` moves, with the stage
+    /// those moves lead to, when moves are taken back or replayed. Meant for
+    /// stopped clocks.
+    pub fn rewind(&mut self, color: Color, moves: u32, remaining: Duration) {
+        let mut stage_index = 0;
+        let mut moves_made = moves;
+        while let Some(stage_moves) = self.control.stages[stage_index]
+            .moves
+            .filter(|stage_moves| *stage_moves > 0 && moves_made >= *stage_moves)
+        {
+            moves_made -= stage_moves;
+            if stage_index + 1 < self.control.stages.len() {
+                stage_index += 1;
+            }
+        }
+        self.stages[color_index(color)] = (stage_index, moves_made);
+        self.remaining[color_index(color)] = remaining;
+    }
+
+    /// Time left for `color` at `now`.
+    pub fn remaining(&self, color: Color, now: Instant) -> Duration {
+        let remaining = self.remaining[color_index(color)];
+        match self.running {
+            Some((running_color, started_at)) if running_color == color => {
+                remaining.saturating_sub(self.spent(color, started_at, now))
+            }
+            _ => remaining,
+        }
+    }
+
+    /// The side whose time has run out by `now`, which stops the clock.
+    pub fn flag(&mut self, now: Instant) -> Option<Color> {
+        if let Some((color, started_at)) = self.running {
+            if self.spent(color, started_at, now) >= self.remaining[color_index(color)] {
+                self.stop(now);
+            }
+        }
+        self.flagged
+    }
+
+    /// Time counted against `color` since `started_at`, after any delay.
+    fn spent(&self, color: Color, started_at: Instant, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(started_at);
+        let (stage_index, _) = self.stages[color_index(color)];
+        match self.control.stages[stage_index].bonus {
+            TimeBonus::Delay(delay) => elapsed.saturating_sub(delay),
+            _ => elapsed,
+        }
+    }
+
+    /// Takes `spent` off the time of `color`. Returns false and flags it
+    /// when nothing is left.
+    fn charge(&mut self, color: Color, spent: Duration) -> bool {
+        let remaining = &mut self.remaining[color_index(color)];
+        if spent >= *remaining {
+            *remaining = Duration::ZERO;
+            self.flagged = Some(color);
+            self.running = None;
+            false
+        } else {
+            *remaining -= spent;
+            true
+        }
+    }
+}
+
+/// Whether `color` could still mate by some series of legal moves. A lone
+/// king cannot, and neither can a single minor piece against a lone king, nor
+/// any number of bishops that all stand on squares of one colour unless the
+/// opponent has something other than such bishops to get in its king's way.
+pub fn has_mating_material(board: &Board, color: Color) -> bool {
+    let mut knights = 0;
+    let mut bishops = [0, 0];
+    for (square, piece) in board.pieces().filter(|(_, piece)| piece.color == color) {
+        match piece.kind {
+            PieceKind::Pawn | PieceKind::Rook | PieceKind::Queen => return true,
+            PieceKind::Knight => knights += 1,
+            PieceKind::Bishop => bishops[square_shade(square)] += 1,
+            PieceKind::King => (),
+        }
+    }
+    let bishop_shades = bishops.iter().filter(|count| **count > 0).count();
+    if knights >= 2 || bishop_shades == 2 || (knights == 1 && bishop_shades == 1) {
+        return true;
+    }
+    let mut opponent_pieces = board
+        .pieces()
+        .filter(|(_, piece)| piece.color != color && piece.kind != PieceKind::King);
+    if knights == 1 {
+        return opponent_pieces.next().is_some();
+    }
+    if bishop_shades == 0 {
+        return false;
+    }
+    // Bishops of one shade never attack the other, so a mate needs an
+    // opponent piece that is not a bishop of that same shade
+    let shade = if bishops[0] > 0 { 0 } else { 1 };
+    opponent_pieces
+        .any(|(square, piece)| piece.kind != PieceKind::Bishop || square_shade(square) != shade)
+}
+
+/// A flag fall is a loss for `flagged`, unless the opponent has no way to
+/// mate, which makes it a draw.
+pub fn is_flag_fall_draw(board: &Board, flagged: Color) -> bool {
+    !has_mating_material(board, flagged.opposite())
+}
+
+fn square_shade(square: Square) -> usize {
+    (square.file + square.rank) % 2
+}
+
+fn color_index(color: Color) -> usize {
+    match color {
+        Color::Light => 0,
+        Color::Dark => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_control_notation_round_trips() {
+        for text in [
+            "300",
+            "300+2",
+            "300d3",
+            "300b3",
+            "40/5400+30:1800+30",
+            "40/7200:20/3600:900",
+        ] {
+            let control = TimeControl::parse(text).unwrap();
+            assert_eq!(control.to_string(), text);
+        }
+        assert_eq!(
+            TimeControl::parse("300+2"),
+            Some(TimeControl::fischer(
+                Duration::from_secs(300),
+                Duration::from_secs(2)
+            ))
+        );
+        for text in ["", "abc", "300+", "40/", ":300", "300x2"] {
+            assert_eq!(TimeControl::parse(text), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn time_control_needs_a_stage() {
+        assert_eq!(TimeControl::new(Vec::new()), None);
+        let stage = TimeControlStage {
+            moves: None,
+            time: Duration::from_secs(60),
+            bonus: TimeBonus::None,
+        };
+        assert_eq!(TimeControl::new(vec![stage]).unwrap().stages(), [stage]);
+    }
+
+    #[test]
+    fn clock_adds_increments_and_next_stages() {
+        let control = TimeControl::parse("2/100+1:50").unwrap();
+        let mut clock = ChessClock::new(control);
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+        clock.start(Color::Light, start);
+        // 100 - 10 + 1
+        assert_eq!(clock.press(at(10)), Some(Duration::from_secs(91)));
+        assert_eq!(clock.running_color(), Some(Color::Dark));
+        assert_eq!(
+            clock.remaining(Color::Dark, at(15)),
+            Duration::from_secs(95)
+        );
+        clock.press(at(20));
+        // Second move of the first stage brings the second stage's time
+        assert_eq!(
+            clock.press(at(30)),
+            Some(Duration::from_secs(91 - 10 + 1 + 50))
+        );
+        assert_eq!(clock.flag(at(30)), None);
+    }
+
+    #[test]
+    fn clock_flags_the_side_out_of_time() {
+        let mut clock = ChessClock::new(TimeControl::simple_delay(
+            Duration::from_secs(10),
+            Duration::from_secs(5),
+        ));
+        let start = Instant::now();
+        clock.start(Color::Light, start);
+        // The delay is not counted
+        assert_eq!(
+            clock.remaining(Color::Light, start + Duration::from_secs(12)),
+            Duration::from_secs(3)
+        );
+        assert_eq!(clock.flag(start + Duration::from_secs(14)), None);
+        assert_eq!(
+            clock.flag(start + Duration::from_secs(15)),
+            Some(Color::Light)
+        );
+        assert_eq!(clock.press(start + Duration::from_secs(16)), None);
+    }
+
+    #[test]
+    fn minor_pieces_mate_only_when_they_can_cover_both_shades() {
+        let mates = |fen| has_mating_material(&Board::from_fen(fen).unwrap(), Color::Light);
+        // Lone king, and a single minor piece against a lone king
+        assert!(!mates("7k/8/8/8/8/8/8/K7 w - - 0 1"));
+        assert!(!mates("7k/8/8/8/8/8/8/KB6 w - - 0 1"));
+        assert!(!mates("7k/8/8/8/8/8/8/KN6 w - - 0 1"));
+        // Bishops on squares of one colour, c1 and e3
+        assert!(!mates("7k/8/8/8/8/4B3/8/K1B5 w - - 0 1"));
+        assert!(!mates("7k/8/8/8/8/4B3/8/K1B3b1 w - - 0 1"));
+        assert!(mates("7k/8/8/8/8/4B3/8/K1B4b w - - 0 1"));
+        assert!(mates("7k/8/8/8/8/4B3/8/K1B3n1 w - - 0 1"));
+        // Bishops on squares of both colours, c1 and f1
+        assert!(mates("7k/8/8/8/8/8/8/K1B2B2 w - - 0 1"));
+        assert!(mates("7k/8/8/8/8/8/8/K1N1N3 w - - 0 1"));
+        assert!(mates("7k/8/8/8/8/8/8/K1B1N3 w - - 0 1"));
+        assert!(mates("7k/8/8/8/8/8/8/KN5b w - - 0 1"));
+    }
+}
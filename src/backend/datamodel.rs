@@ -14,6 +14,28 @@ pub enum PieceKind {
     King,
 }
 
+impl Color {
+    pub const ALL: [Color; 2] = [Color::Light, Color::Dark];
+
+    pub fn opposite(self) -> Color {
+        match self {
+            Color::Light => Color::Dark,
+            Color::Dark => Color::Light,
+        }
+    }
+}
+
+impl PieceKind {
+    pub const ALL: [PieceKind; 6] = [
+        PieceKind::Pawn,
+        PieceKind::Rook,
+        PieceKind::Bishop,
+        PieceKind::Knight,
+        PieceKind::Queen,
+        PieceKind::King,
+    ];
+}
+
 #[derive(Eq, Hash, PartialEq, Copy, Clone)]
 pub struct Piece {
     pub color: Color,
@@ -42,4 +64,8 @@ pub struct Board {
     pub turn: Color,
     pub color_of_king_under_attack: Option<Color>,
     pub number_of_moves: u16,
+    /// Half-moves since the last capture or pawn move, for the fifty-move
+    /// rule.
+    pub halfmove_clock: u16,
+    pub castling_rights: super::CastlingRights,
 }
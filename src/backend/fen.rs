@@ -0,0 +1,347 @@
+use std::fmt;
+
+use super::{Board, CastlingRights, Color, Piece, PieceKind, PositionProblem, Square};
+
+/// Standard starting position.
+pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum FenError {
+    /// Fewer than the four fields up to the en passant square.
+    MissingField(&'static str),
+    Placement(String),
+    SideToMove(String),
+    Castling(String),
+    EnPassant(String),
+    HalfmoveClock(String),
+    MoveNumber(String),
+    /// Well-formed, but the position cannot come about in a game.
+    Position(Vec<PositionProblem>),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "FEN has no {} field", field),
+            FenError::Placement(placement) => {
+                write!(f, "invalid FEN piece placement `{}`", placement)
+            }
+            FenError::SideToMove(side) => write!(f, "invalid FEN side to move `{}`", side),
+            FenError::Castling(castling) => write!(f, "invalid FEN castling `{}`", castling),
+            FenError::EnPassant(square) => {
+                write!(f, "invalid FEN en passant square `{}`", square)
+            }
+            FenError::HalfmoveClock(clock) => {
+                write!(f, "invalid FEN halfmove clock `{}`", clock)
+            }
+            FenError::MoveNumber(number) => write!(f, "invalid FEN move number `{}`", number),
+            FenError::Position(problems) => {
+                let problems: Vec<String> = problems.iter().map(ToString::to_string).collect();
+                write!(f, "invalid FEN position: {}", problems.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+impl Board {
+    /// Forsyth-Edwards notation of the position.
+    pub fn to_fen(&self) -> String {
+        let mut ranks = Vec::with_capacity(8);
+        for rank in (0..8).rev() {
+            let mut rank_text = String::new();
+            let mut empty_squares = 0;
+            for file in 0..8 {
+                match self.piece_at_square(Square { file, rank }) {
+                    Some(piece) => {
+                        if empty_squares > 0 {
+                            rank_text.push_str(&empty_squares.to_string());
+                            empty_squares = 0;
+                        }
+                        rank_text.push(piece_char(&piece));
+                    }
+                    None => empty_squares += 1,
+                }
+            }
+            if empty_squares > 0 {
+                rank_text.push_str(&empty_squares.to_string());
+            }
+            ranks.push(rank_text);
+        }
+
+        let castling_rights = self.castling_rights;
+        let mut castling: String = [
+            (castling_rights.light_king_side, 'K'),
+            (castling_rights.light_queen_side, 'Q'),
+            (castling_rights.dark_king_side, 'k'),
+            (castling_rights.dark_queen_side, 'q'),
+        ]
+        .iter()
+        .filter(|(has_right, _)| *has_right)
+        .map(|(_, letter)| letter)
+        .collect();
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        format!(
+            "{} {} {} {} {} {}",
+            ranks.join("/"),
+            match self.turn {
+                Color::Light => 'w',
+                Color::Dark => 'b',
+            },
+            castling,
+            self.en_passant_square()
+                .map_or("-".to_owned(), |square| square.to_string()),
+            self.halfmove_clock,
+            self.number_of_moves / 2 + 1
+        )
+    }
+
+    /// Reads a FEN position, drawn with Light at the bottom. The halfmove
+    /// clock and move number may be left out. Positions that do not pass
+    /// `Board::validate` are rejected.
+    pub fn from_fen(fen: &str) -> Result<Board, FenError> {
+        let mut fields = fen.split_whitespace();
+        let placement = fields
+            .next()
+            .ok_or(FenError::MissingField("piece placement"))?;
+        let side_to_move = fields
+            .next()
+            .ok_or(FenError::MissingField("side to move"))?;
+        let castling = fields.next().ok_or(FenError::MissingField("castling"))?;
+        let en_passant = fields.next().ok_or(FenError::MissingField("en passant"))?;
+        let halfmove_clock = fields.next().unwrap_or("0");
+        let move_number = fields.next().unwrap_or("1");
+
+        let mut board = Board::empty();
+        let placement_error = || FenError::Placement(placement.to_owned());
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(placement_error());
+        }
+        for (rank_index, rank_text) in ranks.iter().enumerate() {
+            let rank = 7 - rank_index;
+            let mut file = 0;
+            for letter in rank_text.chars() {
+                if let Some(empty_squares) = letter.to_digit(10) {
+                    file += empty_squares as usize;
+                } else {
+                    let piece = piece_from_char(letter).ok_or_else(placement_error)?;
+                    let square = Square::new(file, rank).ok_or_else(placement_error)?;
+                    board.put_piece_at_cell(&board.square_to_cell(square), piece);
+                    file += 1;
+                }
+            }
+            if file != 8 {
+                return Err(placement_error());
+            }
+        }
+
+        board.turn = match side_to_move {
+            "w" => Color::Light,
+            "b" => Color::Dark,
+            _ => return Err(FenError::SideToMove(side_to_move.to_owned())),
+        };
+
+        board.castling_rights = CastlingRights::NONE;
+        if castling != "-" {
+            for letter in castling.chars() {
+                let has_right = match letter {
+                    'K' => &mut board.castling_rights.light_king_side,
+                    'Q' => &mut board.castling_rights.light_queen_side,
+                    'k' => &mut board.castling_rights.dark_king_side,
+                    'q' => &mut board.castling_rights.dark_queen_side,
+                    _ => return Err(FenError::Castling(castling.to_owned())),
+                };
+                *has_right = true;
+            }
+        }
+
+        board.halfmove_clock = halfmove_clock
+            .parse()
+            .map_err(|_| FenError::HalfmoveClock(halfmove_clock.to_owned()))?;
+
+        let turn_offset = match board.turn {
+            Color::Light => 0,
+            Color::Dark => 1,
+        };
+        board.number_of_moves = move_number
+            .parse::<u16>()
+            .ok()
+            .and_then(|number| {
+                number
+                    .checked_sub(1)?
+                    .checked_mul(2)?
+                    .checked_add(turn_offset)
+            })
+            .ok_or_else(|| FenError::MoveNumber(move_number.to_owned()))?;
+
+        if en_passant != "-" {
+            let square = Square::parse(en_passant)
+                .ok_or_else(|| FenError::EnPassant(en_passant.to_owned()))?;
+            if !board.set_en_passant_square(Some(square)) {
+                return Err(FenError::EnPassant(en_passant.to_owned()));
+            }
+        }
+
+        let problems = board.validate();
+        if !problems.is_empty() {
+            return Err(FenError::Position(problems));
+        }
+        Ok(board)
+    }
+}
+
+fn piece_char(piece: &Piece) -> char {
+    let letter = match piece.kind {
+        PieceKind::Pawn => 'p',
+        PieceKind::Rook => 'r',
+        PieceKind::Bishop => 'b',
+        PieceKind::Knight => 'n',
+        PieceKind::Queen => 'q',
+        PieceKind::King => 'k',
+    };
+    match piece.color {
+        Color::Light => letter.to_ascii_uppercase(),
+        Color::Dark => letter,
+    }
+}
+
+fn piece_from_char(letter: char) -> Option<Piece> {
+    let kind = match letter.to_ascii_lowercase() {
+        'p' => PieceKind::Pawn,
+        'r' => PieceKind::Rook,
+        'b' => PieceKind::Bishop,
+        'n' => PieceKind::Knight,
+        'q' => PieceKind::Queen,
+        'k' => PieceKind::King,
+        _ => return None,
+    };
+    let color = if letter.is_ascii_uppercase() {
+        Color::Light
+    } else {
+        Color::Dark
+    };
+    Some(Piece::new(color, kind))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Move;
+
+    #[test]
+    fn positions_read_back_as_written() {
+        for fen in [
+            STARTING_FEN,
+            // Castling rights in part, Black to move
+            "r3k2r/pppq1ppp/2n2n2/3pp3/3PP3/2N2N2/PPPQ1PPP/R3K2R b Kq - 4 9",
+            // En passant on either side
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 2",
+            "rnbqkbnr/pppp1ppp/8/3Pp3/8/8/PPP1PPPP/RNBQKBNR w KQkq e6 0 3",
+            "8/5k2/8/8/8/8/2K5/8 w - - 37 61",
+        ] {
+            assert_eq!(Board::from_fen(fen).unwrap().to_fen(), fen);
+        }
+        // The clock and move number may be left out
+        assert_eq!(
+            Board::from_fen("4k3/8/8/8/8/8/8/4K3 b - -")
+                .unwrap()
+                .to_fen(),
+            "4k3/8/8/8/8/8/8/4K3 b - - 0 1"
+        );
+    }
+
+    #[test]
+    fn moves_keep_the_halfmove_clock() {
+        let mut board = Board::from_fen(STARTING_FEN).unwrap();
+        for (name, fen) in [
+            (
+                "g1f3",
+                "rnbqkbnr/pppppppp/8/8/8/5N2/PPPPPPPP/RNBQKB1R b KQkq - 1 1",
+            ),
+            (
+                "b8c6",
+                "r1bqkbnr/pppppppp/2n5/8/8/5N2/PPPPPPPP/RNBQKB1R w KQkq - 2 2",
+            ),
+            (
+                "e2e4",
+                "r1bqkbnr/pppppppp/2n5/8/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq e3 0 2",
+            ),
+            (
+                "c6e5",
+                "r1bqkbnr/pppppppp/8/4n3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 1 3",
+            ),
+            (
+                "f3e5",
+                "r1bqkbnr/pppppppp/8/4N3/4P3/8/PPPP1PPP/RNBQKB1R b KQkq - 0 3",
+            ),
+        ] {
+            board.try_make_move(Move::parse(name).unwrap()).unwrap();
+            assert_eq!(board.to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn each_fen_error_is_reported() {
+        let error = |fen: &str| Board::from_fen(fen).err();
+        assert_eq!(error(""), Some(FenError::MissingField("piece placement")));
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3"),
+            Some(FenError::MissingField("side to move"))
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w"),
+            Some(FenError::MissingField("castling"))
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w -"),
+            Some(FenError::MissingField("en passant"))
+        );
+        for placement in [
+            "4k3/8/8/8/8/8/4K3",
+            "4k3/8/8/8/8/8/8/4K4",
+            "4k3/8/8/8/8/8/8/4K2",
+            "4k3/8/8/8/8/8/8/4X3",
+        ] {
+            assert_eq!(
+                error(&format!("{} w - - 0 1", placement)),
+                Some(FenError::Placement(placement.to_owned()))
+            );
+        }
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 x - - 0 1"),
+            Some(FenError::SideToMove("x".to_owned()))
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w KX - 0 1"),
+            Some(FenError::Castling("KX".to_owned()))
+        );
+        // Not a square, and a square with no pawn in front
+        for square in ["e9", "e6"] {
+            assert_eq!(
+                error(&format!("4k3/8/8/8/8/8/8/4K3 w - {} 0 1", square)),
+                Some(FenError::EnPassant(square.to_owned()))
+            );
+        }
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w - - x 1"),
+            Some(FenError::HalfmoveClock("x".to_owned()))
+        );
+        for number in ["0", "x"] {
+            assert_eq!(
+                error(&format!("4k3/8/8/8/8/8/8/4K3 w - - 0 {}", number)),
+                Some(FenError::MoveNumber(number.to_owned()))
+            );
+        }
+        assert_eq!(
+            error("8/8/8/8/8/8/8/4K3 w - - 0 1"),
+            Some(FenError::Position(vec![PositionProblem::MissingKing(
+                Color::Dark
+            )]))
+        );
+    }
+}
@@ -0,0 +1,1110 @@
+use std::fmt;
+use std::time::Instant;
+
+use super::pgn::{GameRecord, RecordedMove};
+use super::{
+    has_legal_move, has_mating_material, is_flag_fall_draw, is_king_in_check, is_legal_move,
+    is_premove_pattern, Board, ChessClock, Color, Move, MoveError, PieceKind, Square, TimeControl,
+};
+
+/// How a game ended.
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Copy)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    Resignation,
+    /// Both players agreed to a draw.
+    Agreement,
+    /// The same position came up for the third time and a player claimed it.
+    Repetition,
+    /// Fifty moves each without a capture or pawn move, and a claim.
+    FiftyMoves,
+    /// Neither side has the pieces left to mate.
+    InsufficientMaterial,
+    TimeForfeit,
+}
+
+/// The result of a finished game.
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Copy)]
+pub struct Outcome {
+    /// `None` for a draw.
+    pub winner: Option<Color>,
+    pub termination: Termination,
+}
+
+impl Outcome {
+    /// Value of the PGN `Result` tag.
+    pub fn result(&self) -> &'static str {
+        match self.winner {
+            Some(Color::Light) => "1-0",
+            Some(Color::Dark) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+
+    /// Value of the PGN `Termination` tag, which only tells a time forfeit
+    /// apart from a normal end; `Display` has the details.
+    pub fn pgn_termination(&self) -> &'static str {
+        match self.termination {
+            Termination::TimeForfeit => "time forfeit",
+            _ => "normal",
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let winner = self.winner.map_or("", color_label);
+        let loser = self
+            .winner
+            .map_or("", |winner| color_label(winner.opposite()));
+        match self.termination {
+            Termination::Checkmate => write!(f, "{} wins by checkmate", winner),
+            Termination::Resignation => write!(f, "{} resigns", loser),
+            Termination::TimeForfeit if self.winner.is_some() => {
+                write!(f, "{} wins on time", winner)
+            }
+            Termination::TimeForfeit => {
+                write!(f, "Draw, time ran out against insufficient material")
+            }
+            Termination::Stalemate => write!(f, "Draw by stalemate"),
+            Termination::Agreement => write!(f, "Draw by agreement"),
+            Termination::Repetition => write!(f, "Draw by threefold repetition"),
+            Termination::FiftyMoves => write!(f, "Draw by the fifty-move rule"),
+            Termination::InsufficientMaterial => write!(f, "Draw by insufficient material"),
+        }
+    }
+}
+
+/// Why `GameController` refused an action.
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Copy)]
+pub enum GameError {
+    GameOver,
+    IllegalMove(MoveError),
+    /// There is no draw offer from the opponent to answer.
+    NoDrawOffer,
+    /// Neither a threefold repetition nor the fifty-move rule applies.
+    NoDrawToClaim,
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::GameOver => write!(f, "the game is over"),
+            GameError::IllegalMove(error) => write!(f, "illegal move: {}", error),
+            GameError::NoDrawOffer => write!(f, "there is no draw offer to answer"),
+            GameError::NoDrawToClaim => write!(f, "there is no draw to claim"),
+        }
+    }
+}
+
+impl std::error::Error for GameError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GameError::IllegalMove(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// What a front-end asks of a `GameController`, see `GameController::handle`.
+#[derive(Clone)]
+pub enum GameEvent {
+    /// Picks up the piece on the square: one of the side to move, or of the
+    /// other side to premove. A square the picked piece can go to plays the
+    /// move instead, and an empty one cancels the premoves.
+    SelectSquare(Square),
+    /// Puts the picked up piece down, or off the board for `None`. Pawns put
+    /// on the last rank become queens.
+    DropOnSquare(Option<Square>),
+    /// A whole move for the side to move, e.g. typed in or from an engine.
+    Move(Move),
+    Undo,
+    Redo,
+    NewGame {
+        start: Box<Board>,
+        time_control: Option<TimeControl>,
+    },
+    Resign(Color),
+    OfferDraw(Color),
+    AcceptDraw(Color),
+    DeclineDraw(Color),
+    ClaimDraw,
+    /// Time passed: flags fall and due premoves are played. Sent every frame
+    /// or so.
+    Tick,
+}
+
+/// What changed after a `GameEvent`, for the front-end to show.
+#[derive(Clone)]
+pub enum GameChange {
+    /// A piece was picked up; `GameController::targets` has where it can go.
+    Selected(Square),
+    SelectionCleared,
+    PremovesChanged,
+    /// A move was played, premoves included, from `previous`.
+    Played {
+        chess_move: Move,
+        previous: Board,
+    },
+    /// The position changed other than by a move: undo, redo or a new game.
+    PositionChanged {
+        previous: Board,
+    },
+    DrawOffered(Color),
+    DrawDeclined(Color),
+    Ended(Outcome),
+    /// The event was refused and nothing changed.
+    Refused(GameError),
+}
+
+/// A piece picked up to move or premove.
+#[derive(Clone)]
+struct Selection {
+    square: Square,
+    targets: Vec<Square>,
+    is_premove: bool,
+}
+
+/// One game from its starting position: moves, clock, draw offers and how it
+/// ended, kept in step with its `GameRecord`, along with the piece picked up
+/// and the queued premoves. Any front-end can drive it through `handle`.
+#[derive(Clone)]
+pub struct GameController {
+    initial_board: Board,
+    board: Board,
+    record: GameRecord,
+    /// Undone moves, the next one to redo last.
+    redo_moves: Vec<RecordedMove>,
+    clock: Option<ChessClock>,
+    /// Side with a standing draw offer to the other.
+    draw_offer: Option<Color>,
+    selection: Option<Selection>,
+    /// Moves queued by the side waiting for its turn, played once it comes.
+    premoves: Vec<Move>,
+    premove_side: Color,
+}
+
+impl GameController {
+    /// A game from `start`, on the clock if there is a time control, which
+    /// starts running for the side to move at `now`.
+    pub fn new(start: Board, time_control: Option<TimeControl>, now: Instant) -> GameController {
+        let mut record = GameRecord::from_position(&start);
+        let clock = time_control.map(|time_control| {
+            record.set_tag("TimeControl", time_control.to_string());
+            let mut clock = ChessClock::new(time_control);
+            clock.start(start.turn, now);
+            clock
+        });
+        GameController {
+            initial_board: start,
+            board: start,
+            record,
+            redo_moves: Vec::new(),
+            clock,
+            draw_offer: None,
+            selection: None,
+            premoves: Vec::new(),
+            premove_side: start.turn.opposite(),
+        }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn initial_board(&self) -> &Board {
+        &self.initial_board
+    }
+
+    pub fn record(&self) -> &GameRecord {
+        &self.record
+    }
+
+    /// For tags and annotations; moves go through `play`.
+    pub fn record_mut(&mut self) -> &mut GameRecord {
+        &mut self.record
+    }
+
+    pub fn clock(&self) -> Option<&ChessClock> {
+        self.clock.as_ref()
+    }
+
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.record.outcome
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.record.outcome.is_some()
+    }
+
+    pub fn draw_offer(&self) -> Option<Color> {
+        self.draw_offer
+    }
+
+    /// Square of the picked up piece.
+    pub fn selected_square(&self) -> Option<Square> {
+        self.selection.as_ref().map(|selection| selection.square)
+    }
+
+    /// Where the picked up piece can go, or be premoved to.
+    pub fn targets(&self) -> &[Square] {
+        self.selection
+            .as_ref()
+            .map_or(&[], |selection| &selection.targets)
+    }
+
+    pub fn premoves(&self) -> &[Move] {
+        &self.premoves
+    }
+
+    /// Applies `event` at `now` and lists what changed as a result.
+    pub fn handle(&mut self, event: GameEvent, now: Instant) -> Vec<GameChange> {
+        let mut changes = Vec::new();
+        let was_over = self.is_over();
+        let refusal = |result: Result<(), GameError>| result.err().map(GameChange::Refused);
+        match event {
+            GameEvent::SelectSquare(square) => self.select_square(square, now, &mut changes),
+            GameEvent::DropOnSquare(square) => self.drop_on_square(square, now, &mut changes),
+            GameEvent::Move(chess_move) => self.play_move(chess_move, now, &mut changes),
+            GameEvent::Undo | GameEvent::Redo => {
+                self.clear_premoves(&mut changes);
+                let previous = self.board;
+                let is_done = match event {
+                    GameEvent::Undo => self.undo(now),
+                    _ => self.redo(now),
+                };
+                if is_done {
+                    changes.push(GameChange::PositionChanged { previous });
+                }
+            }
+            GameEvent::NewGame {
+                start,
+                time_control,
+            } => {
+                let previous = self.board;
+                *self = GameController::new(*start, time_control, now);
+                changes.push(GameChange::PositionChanged { previous });
+            }
+            GameEvent::Resign(color) => {
+                changes.extend(refusal(self.resign(color, now).map(|_| ())));
+            }
+            GameEvent::OfferDraw(color) => match self.offer_draw(color, now) {
+                Ok(None) => changes.push(GameChange::DrawOffered(color)),
+                Ok(Some(_)) => (),
+                Err(error) => changes.push(GameChange::Refused(error)),
+            },
+            GameEvent::AcceptDraw(color) => {
+                changes.extend(refusal(self.accept_draw(color, now).map(|_| ())));
+            }
+            GameEvent::DeclineDraw(color) => match self.decline_draw(color, now) {
+                Ok(()) => changes.push(GameChange::DrawDeclined(color)),
+                Err(error) => changes.push(GameChange::Refused(error)),
+            },
+            GameEvent::ClaimDraw => changes.extend(refusal(self.claim_draw(now).map(|_| ()))),
+            GameEvent::Tick => {
+                self.tick(now);
+                self.play_premove(now, &mut changes);
+            }
+        }
+        if let (false, Some(outcome)) = (was_over, self.outcome()) {
+            if self.selection.take().is_some() {
+                changes.push(GameChange::SelectionCleared);
+            }
+            self.clear_premoves(&mut changes);
+            changes.push(GameChange::Ended(outcome));
+        }
+        changes
+    }
+
+    /// Rules on a flag fall by `now`. Front-ends call it every frame or tick,
+    /// and it is called before anything else happens to the game.
+    pub fn tick(&mut self, now: Instant) -> Option<Outcome> {
+        if !self.is_over() {
+            if let Some(flagged) = self.clock.as_mut().and_then(|clock| clock.flag(now)) {
+                let winner = if is_flag_fall_draw(&self.board, flagged) {
+                    None
+                } else {
+                    Some(flagged.opposite())
+                };
+                self.finish(winner, Termination::TimeForfeit, now);
+            }
+        }
+        self.outcome()
+    }
+
+    /// Plays `chess_move` for the side to move and presses its clock. A draw
+    /// offer to the mover lapses, and mate, stalemate or a dead position
+    /// ends the game.
+    pub fn play(&mut self, chess_move: Move, now: Instant) -> Result<(), GameError> {
+        if self.tick(now).is_some() {
+            return Err(GameError::GameOver);
+        }
+        let previous_board = self.board;
+        self.board
+            .try_make_move(chess_move)
+            .map_err(GameError::IllegalMove)?;
+        self.record.push(&previous_board, chess_move);
+        self.redo_moves.clear();
+        if let Some(clock) = &mut self.clock {
+            let remaining = clock.press(now);
+            if let Some(recorded_move) = self.record.moves.last_mut() {
+                recorded_move.clock = remaining;
+            }
+        }
+        if self.draw_offer == Some(self.board.turn) {
+            self.draw_offer = None;
+        }
+        self.rule_on_position(now);
+        Ok(())
+    }
+
+    /// Takes back the last move, reopening the game if it had ended, except
+    /// after a flag fall. Both clocks go back to the times recorded with the
+    /// moves left. Returns whether there was a move to take back.
+    pub fn undo(&mut self, now: Instant) -> bool {
+        if self.has_flag_fallen(now) {
+            return false;
+        }
+        let Some(undone_move) = self.record.moves.pop() else {
+            return false;
+        };
+        match self.record.replay(&self.initial_board) {
+            Ok(board) => {
+                self.board = board;
+                self.redo_moves.push(undone_move);
+                self.rewind_clock(now);
+                self.reopen(now);
+                true
+            }
+            // A record edited into moves that do not follow keeps its last
+            Err(_) => {
+                self.record.moves.push(undone_move);
+                false
+            }
+        }
+    }
+
+    /// Plays the last undone move again, with the clock times it had.
+    /// Returns whether there was one.
+    pub fn redo(&mut self, now: Instant) -> bool {
+        if self.is_over() {
+            return false;
+        }
+        let redone_move = match self.redo_moves.pop() {
+            Some(redone_move) => redone_move,
+            None => return false,
+        };
+        if self.board.try_make_move(redone_move.chess_move).is_err() {
+            self.redo_moves.clear();
+            return false;
+        }
+        self.record.moves.push(redone_move);
+        self.rewind_clock(now);
+        self.reopen(now);
+        self.rule_on_position(now);
+        true
+    }
+
+    pub fn resign(&mut self, color: Color, now: Instant) -> Result<Outcome, GameError> {
+        self.check_open(now)?;
+        Ok(self.finish(Some(color.opposite()), Termination::Resignation, now))
+    }
+
+    /// Offers the opponent of `color` a draw, which stands until they answer
+    /// or make a move. Offering back to a standing offer accepts it.
+    pub fn offer_draw(&mut self, color: Color, now: Instant) -> Result<Option<Outcome>, GameError> {
+        self.check_open(now)?;
+        if self.draw_offer == Some(color.opposite()) {
+            return self.accept_draw(color, now).map(Some);
+        }
+        self.draw_offer = Some(color);
+        Ok(None)
+    }
+
+    /// `color` accepts the draw its opponent offered.
+    pub fn accept_draw(&mut self, color: Color, now: Instant) -> Result<Outcome, GameError> {
+        self.check_open(now)?;
+        if self.draw_offer != Some(color.opposite()) {
+            return Err(GameError::NoDrawOffer);
+        }
+        Ok(self.finish(None, Termination::Agreement, now))
+    }
+
+    /// `color` turns down the draw its opponent offered.
+    pub fn decline_draw(&mut self, color: Color, now: Instant) -> Result<(), GameError> {
+        self.check_open(now)?;
+        if self.draw_offer != Some(color.opposite()) {
+            return Err(GameError::NoDrawOffer);
+        }
+        self.draw_offer = None;
+        Ok(())
+    }
+
+    /// Ends the game in a draw by threefold repetition or the fifty-move
+    /// rule, whichever applies. Either player may claim.
+    pub fn claim_draw(&mut self, now: Instant) -> Result<Outcome, GameError> {
+        self.check_open(now)?;
+        let termination = self.claimable_draw().ok_or(GameError::NoDrawToClaim)?;
+        Ok(self.finish(None, termination, now))
+    }
+
+    /// The draw a player could claim in the current position, if any.
+    pub fn claimable_draw(&self) -> Option<Termination> {
+        let mut board = self.initial_board;
+        let mut positions = vec![position_key(&board)];
+        let mut quiet_plies = self.initial_board.halfmove_clock;
+        for recorded_move in &self.record.moves {
+            let chess_move = recorded_move.chess_move;
+            let is_pawn_move = board
+                .piece_at_square(chess_move.from)
+                .is_some_and(|piece| piece.kind == PieceKind::Pawn);
+            let is_capture = board.piece_at_square(chess_move.to).is_some();
+            quiet_plies = if is_pawn_move || is_capture {
+                0
+            } else {
+                quiet_plies + 1
+            };
+            board.make_move_unchecked(chess_move);
+            positions.push(position_key(&board));
+        }
+        let current_position = position_key(&self.board);
+        if positions
+            .iter()
+            .filter(|position| **position == current_position)
+            .count()
+            >= 3
+        {
+            Some(Termination::Repetition)
+        } else if quiet_plies >= 100 {
+            Some(Termination::FiftyMoves)
+        } else {
+            None
+        }
+    }
+
+    fn select_square(&mut self, square: Square, now: Instant, changes: &mut Vec<GameChange>) {
+        if self.tick(now).is_some() {
+            return;
+        }
+        if self.targets().contains(&square) {
+            self.drop_on_square(Some(square), now, changes);
+            return;
+        }
+        let piece = match self.board.piece_at_square(square) {
+            Some(piece) => piece,
+            None => {
+                if self.selection.take().is_some() {
+                    changes.push(GameChange::SelectionCleared);
+                }
+                self.clear_premoves(changes);
+                return;
+            }
+        };
+        let is_premove = piece.color != self.board.turn;
+        let premove_board = with_premoves(&self.board, &self.premoves);
+        let from_cell_position = self.board.square_to_cell(square);
+        let targets = (0..8)
+            .flat_map(|rank| (0..8).map(move |file| Square { file, rank }))
+            .filter(|target| *target != square)
+            .filter(|target| {
+                let to_cell_position = self.board.square_to_cell(*target);
+                if is_premove {
+                    is_premove_pattern(&premove_board, &from_cell_position, &to_cell_position)
+                } else {
+                    is_legal_move(&self.board, &from_cell_position, &to_cell_position)
+                }
+            })
+            .collect();
+        self.selection = Some(Selection {
+            square,
+            targets,
+            is_premove,
+        });
+        changes.push(GameChange::Selected(square));
+    }
+
+    fn drop_on_square(
+        &mut self,
+        square: Option<Square>,
+        now: Instant,
+        changes: &mut Vec<GameChange>,
+    ) {
+        let selection = match self.selection.take() {
+            Some(selection) => selection,
+            None => return,
+        };
+        changes.push(GameChange::SelectionCleared);
+        let square = match square {
+            Some(square) if selection.targets.contains(&square) => square,
+            _ => return,
+        };
+        let mut chess_move = Move::new(selection.square, square);
+        if self.board.is_promotion(chess_move) {
+            chess_move = chess_move.with_promotion(PieceKind::Queen);
+        }
+        if selection.is_premove {
+            if self.premoves.is_empty() {
+                self.premove_side = self.board.turn.opposite();
+            }
+            self.premoves.push(chess_move);
+            changes.push(GameChange::PremovesChanged);
+        } else {
+            self.play_move(chess_move, now, changes);
+        }
+    }
+
+    fn play_move(&mut self, chess_move: Move, now: Instant, changes: &mut Vec<GameChange>) {
+        let previous = self.board;
+        match self.play(chess_move, now) {
+            Ok(()) => changes.push(GameChange::Played {
+                chess_move,
+                previous,
+            }),
+            Err(error) => changes.push(GameChange::Refused(error)),
+        }
+    }
+
+    /// Plays the next premove once its side is to move and nothing is picked
+    /// up. An illegal one cancels the queue.
+    fn play_premove(&mut self, now: Instant, changes: &mut Vec<GameChange>) {
+        if self.selection.is_some() || self.is_over() || self.board.turn != self.premove_side {
+            return;
+        }
+        if let Some(premove) = self.premoves.first().copied() {
+            let previous = self.board;
+            if self.play(premove, now).is_ok() {
+                self.premoves.remove(0);
+                changes.push(GameChange::PremovesChanged);
+                changes.push(GameChange::Played {
+                    chess_move: premove,
+                    previous,
+                });
+            } else {
+                self.clear_premoves(changes);
+            }
+        }
+    }
+
+    fn clear_premoves(&mut self, changes: &mut Vec<GameChange>) {
+        if !self.premoves.is_empty() {
+            self.premoves.clear();
+            changes.push(GameChange::PremovesChanged);
+        }
+    }
+
+    fn check_open(&mut self, now: Instant) -> Result<(), GameError> {
+        match self.tick(now) {
+            Some(_) => Err(GameError::GameOver),
+            None => Ok(()),
+        }
+    }
+
+    fn has_flag_fallen(&mut self, now: Instant) -> bool {
+        self.clock
+            .as_mut()
+            .is_some_and(|clock| clock.flag(now).is_some())
+    }
+
+    /// Ends the game if the position decides it: mate, stalemate or no
+    /// mating material on either side.
+    fn rule_on_position(&mut self, now: Instant) {
+        if !has_legal_move(&self.board) {
+            if is_king_in_check(&self.board, self.board.turn) {
+                self.finish(
+                    Some(self.board.turn.opposite()),
+                    Termination::Checkmate,
+                    now,
+                );
+            } else {
+                self.finish(None, Termination::Stalemate, now);
+            }
+        } else if Color::ALL
+            .iter()
+            .all(|color| !has_mating_material(&self.board, *color))
+        {
+            self.finish(None, Termination::InsufficientMaterial, now);
+        }
+    }
+
+    fn finish(&mut self, winner: Option<Color>, termination: Termination, now: Instant) -> Outcome {
+        let outcome = Outcome {
+            winner,
+            termination,
+        };
+        self.record.set_outcome(Some(outcome));
+        self.draw_offer = None;
+        if let Some(clock) = &mut self.clock {
+            clock.stop(now);
+        }
+        outcome
+    }
+
+    /// Stops the clock and sets both sides to the time recorded with their
+    /// last move, or the starting time before their first, so time spent
+    /// on moves taken back is given back.
+    fn rewind_clock(&mut self, now: Instant) {
+        let clock = match &mut self.clock {
+            Some(clock) => clock,
+            None => return,
+        };
+        clock.stop(now);
+        let first_mover = self.initial_board.turn;
+        let starting_time = clock.control().stages()[0].time;
+        for color in Color::ALL {
+            let moves: Vec<&RecordedMove> = self
+                .record
+                .moves
+                .iter()
+                .enumerate()
+                .filter(|(ply, _)| (ply % 2 == 0) == (color == first_mover))
+                .map(|(_, recorded_move)| recorded_move)
+                .collect();
+            let remaining = match moves.last() {
+                Some(recorded_move) => recorded_move.clock,
+                None => Some(starting_time),
+            };
+            if let Some(remaining) = remaining {
+                clock.rewind(color, moves.len() as u32, remaining);
+            }
+        }
+    }
+
+    /// After the moves changed, the game goes on with the side to move.
+    fn reopen(&mut self, now: Instant) {
+        self.record.set_outcome(None);
+        self.draw_offer = None;
+        if let Some(clock) = &mut self.clock {
+            clock.start(self.board.turn, now);
+        }
+    }
+}
+
+/// `board` with the queued premoves played, whatever the turn, for checking
+/// further premoves against.
+fn with_premoves(board: &Board, premoves: &[Move]) -> Board {
+    let mut premove_board = *board;
+    for premove in premoves {
+        if premove_board.piece_at_square(premove.from).is_some() {
+            premove_board.make_move_unchecked(*premove);
+        }
+    }
+    premove_board
+}
+
+/// What makes positions the same for repetitions: the FEN without the move
+/// counters.
+fn position_key(board: &Board) -> String {
+    board
+        .to_fen()
+        .split(' ')
+        .take(4)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn color_label(color: Color) -> &'static str {
+    match color {
+        Color::Light => "White",
+        Color::Dark => "Black",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::backend::STARTING_FEN;
+
+    fn square(name: &str) -> Square {
+        Square::parse(name).unwrap()
+    }
+
+    fn chess_move(name: &str) -> Move {
+        Move::parse(name).unwrap()
+    }
+
+    fn new_game(now: Instant) -> GameController {
+        GameController::new(Board::new(Color::Light), None, now)
+    }
+
+    fn play_all(game: &mut GameController, moves: &[&str], now: Instant) {
+        for name in moves {
+            let changes = game.handle(GameEvent::Move(chess_move(name)), now);
+            assert!(
+                matches!(changes.first(), Some(GameChange::Played { .. })),
+                "{} was not played",
+                name
+            );
+        }
+    }
+
+    fn ended(changes: &[GameChange]) -> Option<Outcome> {
+        changes.iter().find_map(|change| match change {
+            GameChange::Ended(outcome) => Some(*outcome),
+            _ => None,
+        })
+    }
+
+    fn refusal(changes: &[GameChange]) -> Option<GameError> {
+        match changes {
+            [GameChange::Refused(error)] => Some(*error),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn select_and_drop_plays_a_move() {
+        let now = Instant::now();
+        let mut game = new_game(now);
+        let changes = game.handle(GameEvent::SelectSquare(square("e2")), now);
+        assert!(
+            matches!(changes[..], [GameChange::Selected(selected)] if selected == square("e2"))
+        );
+        assert_eq!(game.targets(), [square("e3"), square("e4")]);
+
+        let changes = game.handle(GameEvent::DropOnSquare(Some(square("e4"))), now);
+        assert!(matches!(
+            changes[..],
+            [GameChange::SelectionCleared, GameChange::Played { chess_move: played, .. }]
+                if played == chess_move("e2e4")
+        ));
+        assert_eq!(game.board().turn, Color::Dark);
+        assert_eq!(game.record().moves[0].san, "e4");
+
+        // Clicking a target square plays the move as well
+        game.handle(GameEvent::SelectSquare(square("g8")), now);
+        let changes = game.handle(GameEvent::SelectSquare(square("f6")), now);
+        assert!(matches!(changes.last(), Some(GameChange::Played { .. })));
+        assert_eq!(game.board().turn, Color::Light);
+    }
+
+    #[test]
+    fn dropping_elsewhere_keeps_the_position() {
+        let now = Instant::now();
+        let mut game = new_game(now);
+        for target in [None, Some(square("b4"))] {
+            game.handle(GameEvent::SelectSquare(square("b1")), now);
+            let changes = game.handle(GameEvent::DropOnSquare(target), now);
+            assert!(matches!(changes[..], [GameChange::SelectionCleared]));
+        }
+        game.handle(GameEvent::SelectSquare(square("b1")), now);
+        assert!(game.record().moves.is_empty());
+        // An empty square puts the piece back
+        let changes = game.handle(GameEvent::SelectSquare(square("e4")), now);
+        assert!(matches!(changes[..], [GameChange::SelectionCleared]));
+        assert_eq!(game.selected_square(), None);
+    }
+
+    #[test]
+    fn illegal_moves_are_refused() {
+        let now = Instant::now();
+        let mut game = new_game(now);
+        let changes = game.handle(GameEvent::Move(chess_move("e2e5")), now);
+        assert!(matches!(refusal(&changes), Some(GameError::IllegalMove(_))));
+        let changes = game.handle(GameEvent::Move(chess_move("e7e5")), now);
+        assert!(matches!(refusal(&changes), Some(GameError::IllegalMove(_))));
+        assert_eq!(game.board().to_fen(), STARTING_FEN);
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let now = Instant::now();
+        let mut game = new_game(now);
+        play_all(&mut game, &["e2e4", "e7e5"], now);
+        let after_two_moves = *game.board();
+
+        let changes = game.handle(GameEvent::Undo, now);
+        assert!(
+            matches!(changes[..], [GameChange::PositionChanged { previous }] if previous.to_fen() == after_two_moves.to_fen())
+        );
+        assert_eq!(game.record().moves.len(), 1);
+        assert_eq!(game.board().turn, Color::Dark);
+
+        game.handle(GameEvent::Redo, now);
+        assert_eq!(game.board().to_fen(), after_two_moves.to_fen());
+        assert!(game.handle(GameEvent::Redo, now).is_empty());
+
+        // A new move drops the moves left to redo
+        game.handle(GameEvent::Undo, now);
+        play_all(&mut game, &["c7c5"], now);
+        assert!(game.handle(GameEvent::Redo, now).is_empty());
+        game.handle(GameEvent::Undo, now);
+        game.handle(GameEvent::Undo, now);
+        assert!(game.handle(GameEvent::Undo, now).is_empty());
+        assert_eq!(game.board().to_fen(), STARTING_FEN);
+    }
+
+    #[test]
+    fn undo_reopens_a_finished_game() {
+        let now = Instant::now();
+        let mut game = new_game(now);
+        play_all(&mut game, &["f2f3", "e7e5", "g2g4"], now);
+        let changes = game.handle(GameEvent::Move(chess_move("d8h4")), now);
+        assert_eq!(
+            ended(&changes),
+            Some(Outcome {
+                winner: Some(Color::Dark),
+                termination: Termination::Checkmate
+            })
+        );
+        assert_eq!(game.record().tag("Result"), Some("0-1"));
+        game.handle(GameEvent::Undo, now);
+        assert!(!game.is_over());
+        assert_eq!(game.record().tag("Result"), Some("*"));
+    }
+
+    #[test]
+    fn new_game_starts_over() {
+        let now = Instant::now();
+        let mut game = new_game(now);
+        play_all(&mut game, &["d2d4"], now);
+        let start = Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1").unwrap();
+        let time_control = TimeControl::parse("60+1");
+        let changes = game.handle(
+            GameEvent::NewGame {
+                start: Box::new(start),
+                time_control: time_control.clone(),
+            },
+            now,
+        );
+        assert!(matches!(changes[..], [GameChange::PositionChanged { .. }]));
+        assert_eq!(game.board().to_fen(), start.to_fen());
+        assert!(game.record().moves.is_empty());
+        assert_eq!(game.clock().map(ChessClock::control), time_control.as_ref());
+        assert_eq!(
+            game.clock().and_then(ChessClock::running_color),
+            Some(Color::Dark)
+        );
+        assert_eq!(game.record().tag("TimeControl"), Some("60+1"));
+    }
+
+    #[test]
+    fn resigning_ends_the_game() {
+        let now = Instant::now();
+        let mut game = new_game(now);
+        let changes = game.handle(GameEvent::Resign(Color::Light), now);
+        assert_eq!(
+            ended(&changes),
+            Some(Outcome {
+                winner: Some(Color::Dark),
+                termination: Termination::Resignation
+            })
+        );
+        let changes = game.handle(GameEvent::Move(chess_move("e2e4")), now);
+        assert_eq!(refusal(&changes), Some(GameError::GameOver));
+        let changes = game.handle(GameEvent::Resign(Color::Dark), now);
+        assert_eq!(refusal(&changes), Some(GameError::GameOver));
+    }
+
+    #[test]
+    fn draw_offers_are_answered_or_lapse() {
+        let now = Instant::now();
+        let mut game = new_game(now);
+        let changes = game.handle(GameEvent::OfferDraw(Color::Light), now);
+        assert!(matches!(
+            changes[..],
+            [GameChange::DrawOffered(Color::Light)]
+        ));
+        // Only the opponent answers
+        let changes = game.handle(GameEvent::AcceptDraw(Color::Light), now);
+        assert_eq!(refusal(&changes), Some(GameError::NoDrawOffer));
+        let changes = game.handle(GameEvent::DeclineDraw(Color::Dark), now);
+        assert!(matches!(
+            changes[..],
+            [GameChange::DrawDeclined(Color::Dark)]
+        ));
+        assert_eq!(game.draw_offer(), None);
+        let changes = game.handle(GameEvent::DeclineDraw(Color::Dark), now);
+        assert_eq!(refusal(&changes), Some(GameError::NoDrawOffer));
+
+        // An offer lapses when its opponent moves instead
+        play_all(&mut game, &["e2e4"], now);
+        game.handle(GameEvent::OfferDraw(Color::Light), now);
+        play_all(&mut game, &["e7e5"], now);
+        assert_eq!(game.draw_offer(), None);
+
+        game.handle(GameEvent::OfferDraw(Color::Dark), now);
+        let changes = game.handle(GameEvent::AcceptDraw(Color::Light), now);
+        assert_eq!(
+            ended(&changes),
+            Some(Outcome {
+                winner: None,
+                termination: Termination::Agreement
+            })
+        );
+    }
+
+    #[test]
+    fn offering_back_accepts() {
+        let now = Instant::now();
+        let mut game = new_game(now);
+        game.handle(GameEvent::OfferDraw(Color::Light), now);
+        let changes = game.handle(GameEvent::OfferDraw(Color::Dark), now);
+        assert_eq!(
+            ended(&changes).map(|outcome| outcome.termination),
+            Some(Termination::Agreement)
+        );
+    }
+
+    #[test]
+    fn threefold_repetition_can_be_claimed() {
+        let now = Instant::now();
+        let mut game = new_game(now);
+        let changes = game.handle(GameEvent::ClaimDraw, now);
+        assert_eq!(refusal(&changes), Some(GameError::NoDrawToClaim));
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        play_all(&mut game, &shuffle, now);
+        assert_eq!(game.claimable_draw(), None);
+        play_all(&mut game, &shuffle, now);
+        assert_eq!(game.claimable_draw(), Some(Termination::Repetition));
+        let changes = game.handle(GameEvent::ClaimDraw, now);
+        assert_eq!(
+            ended(&changes),
+            Some(Outcome {
+                winner: None,
+                termination: Termination::Repetition
+            })
+        );
+        assert_eq!(game.record().tag("Result"), Some("1/2-1/2"));
+    }
+
+    #[test]
+    fn fifty_move_rule_can_be_claimed() {
+        let now = Instant::now();
+        let start = Board::from_fen("r3k3/8/8/8/8/8/8/4K2R w - - 0 1").unwrap();
+        let mut game = GameController::new(start, None, now);
+        // Quiet moves, each to a position seen at most once before, so no
+        // threefold repetition comes up
+        let mut seen = vec![position_key(game.board())];
+        for ply in 0..100 {
+            assert_eq!(game.claimable_draw(), None, "ply {}", ply);
+            let board = *game.board();
+            let next_move = board
+                .legal_moves()
+                .into_iter()
+                .filter(|candidate| board.piece_at_square(candidate.to).is_none())
+                .filter_map(|candidate| {
+                    let mut next = board;
+                    next.make_move_unchecked(candidate);
+                    let key = position_key(&next);
+                    let count = seen.iter().filter(|position| **position == key).count();
+                    (count < 2 && has_legal_move(&next)).then_some((count, candidate))
+                })
+                .min_by_key(|(count, _)| *count)
+                .map(|(_, candidate)| candidate)
+                .expect("a quiet move");
+            game.play(next_move, now).unwrap();
+            seen.push(position_key(game.board()));
+        }
+        assert_eq!(game.claimable_draw(), Some(Termination::FiftyMoves));
+        let changes = game.handle(GameEvent::ClaimDraw, now);
+        assert_eq!(
+            ended(&changes).map(|outcome| outcome.termination),
+            Some(Termination::FiftyMoves)
+        );
+    }
+
+    #[test]
+    fn premoves_are_played_on_tick() {
+        let now = Instant::now();
+        let mut game = new_game(now);
+        play_all(&mut game, &["e2e4"], now);
+        // White queues two moves while Black thinks
+        for (from, to) in [("d2", "d4"), ("g1", "f3")] {
+            let changes = game.handle(GameEvent::SelectSquare(square(from)), now);
+            assert!(matches!(changes[..], [GameChange::Selected(_)]));
+            let changes = game.handle(GameEvent::DropOnSquare(Some(square(to))), now);
+            assert!(matches!(
+                changes[..],
+                [GameChange::SelectionCleared, GameChange::PremovesChanged]
+            ));
+        }
+        assert_eq!(game.premoves(), [chess_move("d2d4"), chess_move("g1f3")]);
+        assert!(game.handle(GameEvent::Tick, now).is_empty());
+
+        play_all(&mut game, &["e7e5"], now);
+        let changes = game.handle(GameEvent::Tick, now);
+        assert!(matches!(
+            changes[..],
+            [GameChange::PremovesChanged, GameChange::Played { chess_move: played, .. }]
+                if played == chess_move("d2d4")
+        ));
+        assert_eq!(game.premoves(), [chess_move("g1f3")]);
+
+        play_all(&mut game, &["b8c6"], now);
+        let changes = game.handle(GameEvent::Tick, now);
+        assert!(matches!(
+            changes[..],
+            [GameChange::PremovesChanged, GameChange::Played { chess_move: played, .. }]
+                if played == chess_move("g1f3")
+        ));
+        assert!(game.premoves().is_empty());
+    }
+
+    #[test]
+    fn illegal_premove_clears_the_queue() {
+        let now = Instant::now();
+        let mut game = new_game(now);
+        play_all(&mut game, &["e2e4"], now);
+        // A capture on d5 in case Black answers d5
+        game.handle(GameEvent::SelectSquare(square("e4")), now);
+        game.handle(GameEvent::DropOnSquare(Some(square("d5"))), now);
+        assert_eq!(game.premoves(), [chess_move("e4d5")]);
+        play_all(&mut game, &["d7d6"], now);
+        let changes = game.handle(GameEvent::Tick, now);
+        assert!(matches!(changes[..], [GameChange::PremovesChanged]));
+        assert!(game.premoves().is_empty());
+        assert_eq!(game.board().turn, Color::Light);
+    }
+
+    #[test]
+    fn undo_gives_back_the_clock_times() {
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+        let mut game =
+            GameController::new(Board::new(Color::Light), TimeControl::parse("300+5"), start);
+        game.handle(GameEvent::Move(chess_move("e2e4")), at(10));
+        game.handle(GameEvent::Move(chess_move("e7e5")), at(30));
+        let clock = |game: &GameController, color, seconds| {
+            game.clock().unwrap().remaining(color, at(seconds))
+        };
+        assert_eq!(clock(&game, Color::Light, 30), Duration::from_secs(295));
+        assert_eq!(clock(&game, Color::Dark, 30), Duration::from_secs(285));
+
+        game.handle(GameEvent::Undo, at(50));
+        assert_eq!(clock(&game, Color::Light, 50), Duration::from_secs(295));
+        assert_eq!(clock(&game, Color::Dark, 50), Duration::from_secs(300));
+        assert_eq!(game.clock().unwrap().running_color(), Some(Color::Dark));
+        game.handle(GameEvent::Undo, at(60));
+        assert_eq!(clock(&game, Color::Light, 60), Duration::from_secs(300));
+        assert_eq!(clock(&game, Color::Dark, 60), Duration::from_secs(300));
+
+        game.handle(GameEvent::Redo, at(70));
+        assert_eq!(clock(&game, Color::Light, 70), Duration::from_secs(295));
+        assert_eq!(clock(&game, Color::Dark, 75), Duration::from_secs(295));
+    }
+
+    #[test]
+    fn flag_fall_ends_the_game_on_tick() {
+        let start = Instant::now();
+        let mut game =
+            GameController::new(Board::new(Color::Light), TimeControl::parse("60"), start);
+        assert!(game
+            .handle(GameEvent::Tick, start + Duration::from_secs(59))
+            .is_empty());
+        let changes = game.handle(GameEvent::Tick, start + Duration::from_secs(60));
+        assert_eq!(
+            ended(&changes),
+            Some(Outcome {
+                winner: Some(Color::Dark),
+                termination: Termination::TimeForfeit
+            })
+        );
+        // No taking back a flag fall
+        assert!(game
+            .handle(GameEvent::Undo, start + Duration::from_secs(61))
+            .is_empty());
+    }
+}
@@ -1,10 +1,25 @@
+mod annotation;
+mod clock;
 mod datamodel;
+mod fen;
+mod game;
 mod moves;
+pub mod pgn;
+mod setup;
+mod square;
+pub use annotation::{AnnotationColor, Annotations};
+pub use clock::{
+    has_mating_material, is_flag_fall_draw, ChessClock, TimeBonus, TimeControl, TimeControlStage,
+};
 pub use datamodel::{Board, Color, Piece, PieceKind};
+pub use fen::{FenError, STARTING_FEN};
+pub use game::{GameChange, GameController, GameError, GameEvent, Outcome, Termination};
 use moves::{
     can_move_bishop, can_move_king, can_move_knight, can_move_pawn, can_move_queen, can_move_rook,
     is_en_passant_move,
 };
+pub use setup::{CastlingRights, PositionProblem};
+pub use square::{Move, MoveError, Square};
 
 impl Board {
     pub fn new(player_side: Color) -> Board {
@@ -66,16 +81,16 @@ impl Board {
                 Some(Piece::new(player_side, PieceKind::Rook)),
             ],
         ];
-        for x_pos in 0..8 {
-            initial_board_state[1][x_pos] = Some(Piece::new(other_side, PieceKind::Pawn));
-            initial_board_state[6][x_pos] = Some(Piece::new(player_side, PieceKind::Pawn));
-        }
+        initial_board_state[1] = [Some(Piece::new(other_side, PieceKind::Pawn)); 8];
+        initial_board_state[6] = [Some(Piece::new(player_side, PieceKind::Pawn)); 8];
         Board {
             state: initial_board_state,
             is_reversed,
             turn: Color::Light,
             color_of_king_under_attack: None,
             number_of_moves: 0,
+            halfmove_clock: 0,
+            castling_rights: CastlingRights::ALL,
         }
     }
     pub fn clear_cell(&mut self, cell_position: &(usize, usize)) {
@@ -89,16 +104,21 @@ impl Board {
         from_cell_position: &(usize, usize),
         to_cell_position: &(usize, usize),
     ) {
-        if self.state[7 - to_cell_position.1][to_cell_position.0].is_some() {
+        let is_capture = self.state[7 - to_cell_position.1][to_cell_position.0].is_some();
+        if is_capture {
             Self::clear_cell(self, to_cell_position);
         }
 
-        let mut piece_to_move_in = self.state[7 - from_cell_position.1][from_cell_position.0]
-            .unwrap()
-            .clone();
+        let mut piece_to_move_in =
+            self.state[7 - from_cell_position.1][from_cell_position.0].unwrap();
         piece_to_move_in.made_n_moves += 1;
         piece_to_move_in.last_moved_at_move_number = self.number_of_moves;
 
+        self.halfmove_clock = if is_capture || piece_to_move_in.kind == PieceKind::Pawn {
+            0
+        } else {
+            self.halfmove_clock.saturating_add(1)
+        };
         if piece_to_move_in.kind == PieceKind::Pawn {
             let vertical_diff = (from_cell_position.1 as i32 - to_cell_position.1 as i32).abs();
             if vertical_diff == 2 {
@@ -111,29 +131,26 @@ impl Board {
         }
         Self::put_piece_at_cell(self, to_cell_position, piece_to_move_in);
         Self::clear_cell(self, from_cell_position);
+        self.castling_rights
+            .forget_square(self.cell_to_square(from_cell_position));
+        self.castling_rights
+            .forget_square(self.cell_to_square(to_cell_position));
 
         self.number_of_moves += 1;
-        self.turn = match self.turn {
-            Color::Light => Color::Dark,
-            Color::Dark => Color::Light,
-        };
+        self.turn = self.turn.opposite();
     }
-    pub fn get_king_position(&self, color: Color) -> (usize, usize) {
-        // Assumes there is always king exists
+    /// Cell of the king of `color`, `None` in positions without one.
+    pub fn get_king_position(&self, color: Color) -> Option<(usize, usize)> {
         for x_pos in 0..8 {
             for y_pos in 0..8 {
-                let piece = self.state[7 - y_pos][x_pos];
-                match piece {
-                    Some(piece) => {
-                        if piece.color == color && piece.kind == PieceKind::King {
-                            return (x_pos, y_pos);
-                        }
+                if let Some(piece) = self.state[7 - y_pos][x_pos] {
+                    if piece.color == color && piece.kind == PieceKind::King {
+                        return Some((x_pos, y_pos));
                     }
-                    None => (),
                 }
             }
         }
-        (0, 0)
+        None
     }
 }
 
@@ -150,13 +167,8 @@ pub fn can_move_piece(
     }
     let piece = piece.unwrap();
 
-    match piece_to_take {
-        Some(piece_to_take) => {
-            if piece_to_take.color == piece.color {
-                return false;
-            }
-        }
-        None => (),
+    if piece_to_take.is_some_and(|piece_to_take| piece_to_take.color == piece.color) {
+        return false;
     }
 
     let is_reversed = (board.is_reversed & (piece.color == Color::Light))
@@ -168,7 +180,7 @@ pub fn can_move_piece(
         vertical_diff *= -1;
     }
 
-    let is_valid_move = match piece.kind {
+    match piece.kind {
         PieceKind::Pawn => can_move_pawn(
             board,
             vertical_diff,
@@ -199,8 +211,87 @@ pub fn can_move_piece(
             to_position,
         ),
         PieceKind::King => can_move_king(vertical_diff, horizontal_diff),
+    }
+}
+
+/// Whether the side to move may play from `from_position` to `to_position`:
+/// its own piece, a valid pattern and no check to its own king afterwards.
+pub fn is_legal_move(
+    board: &Board,
+    from_position: &(usize, usize),
+    to_position: &(usize, usize),
+) -> bool {
+    let piece = match board.state[7 - from_position.1][from_position.0] {
+        Some(piece) if piece.color == board.turn => piece,
+        _ => return false,
     };
-    is_valid_move
+    if from_position == to_position || !can_move_piece(board, from_position, to_position) {
+        return false;
+    }
+    let opponent_color = piece.color.opposite();
+    // Copy the board and make move to check if there is a check after
+    let mut tmp_board = *board;
+    if piece.kind == PieceKind::Pawn && is_en_passant_move(board, from_position, to_position) {
+        // The pawn taken en passant leaves its rank too
+        tmp_board.clear_cell(&(to_position.0, from_position.1));
+    }
+    tmp_board.put_piece_at_cell(to_position, piece);
+    tmp_board.clear_cell(from_position);
+    // Without a king there is nothing to leave in check
+    tmp_board
+        .get_king_position(piece.color)
+        .is_none_or(|this_side_king_position| {
+            !is_any_piece_attacks_position(&tmp_board, opponent_color, &this_side_king_position)
+        })
+}
+
+/// Whether the piece at `from_position` could go to `to_position` once its
+/// side is to move, not looking at checks: a valid pattern now, or a pawn
+/// capture onto a square that is still empty.
+pub fn is_premove_pattern(
+    board: &Board,
+    from_position: &(usize, usize),
+    to_position: &(usize, usize),
+) -> bool {
+    let piece = match board.state[7 - from_position.1][from_position.0] {
+        Some(piece) => piece,
+        None => return false,
+    };
+    if from_position == to_position {
+        return false;
+    }
+    if can_move_piece(board, from_position, to_position) {
+        return true;
+    }
+    let is_reversed = (board.is_reversed & (piece.color == Color::Light))
+        || (!board.is_reversed & (piece.color == Color::Dark));
+    let mut vertical_diff = to_position.1 as i8 - from_position.1 as i8;
+    if is_reversed {
+        vertical_diff *= -1;
+    }
+    let horizontal_diff = (to_position.0 as i8 - from_position.0 as i8).abs();
+    let piece_to_take = board.state[7 - to_position.1][to_position.0];
+    piece.kind == PieceKind::Pawn
+        && vertical_diff == 1
+        && horizontal_diff == 1
+        && piece_to_take.is_none_or(|piece_to_take| piece_to_take.color != piece.color)
+}
+
+/// Whether the side to move has any legal move at all.
+pub fn has_legal_move(board: &Board) -> bool {
+    let cell_positions = || (0..8).flat_map(|pos_x| (0..8).map(move |pos_y| (pos_x, pos_y)));
+    cell_positions().any(|from_position| {
+        cell_positions().any(|to_position| is_legal_move(board, &from_position, &to_position))
+    })
+}
+
+/// Whether the king of `color` is attacked. Positions without that king are
+/// never in check.
+pub fn is_king_in_check(board: &Board, color: Color) -> bool {
+    let opponent_color = color.opposite();
+    board.get_king_position(color).is_some_and(|king_position| {
+        is_any_piece_attacks_position(board, opponent_color, &king_position)
+    })
 }
 
 pub fn is_any_piece_attacks_position(
@@ -211,39 +302,12 @@ pub fn is_any_piece_attacks_position(
     for x_pos in 0..8 {
         for y_pos in 0..8 {
             let piece = board.state[7 - y_pos][x_pos];
-            if piece.is_some() {
-                if piece.unwrap().color == color {
-                    if can_move_piece(board, &(x_pos, y_pos), position) {
-                        return true;
-                    }
-                }
+            if piece.is_some_and(|piece| piece.color == color)
+                && can_move_piece(board, &(x_pos, y_pos), position)
+            {
+                return true;
             }
         }
     }
     false
 }
-
-pub fn encode_valid_cells_to_integers(valid_cells_to_move: &[[bool; 8]; 8]) -> (i32, i32) {
-    let mut result_0: i32 = 0;
-    let mut result_1: i32 = 0;
-    for x_pos in 0..8 {
-        for y_pos in 0..4 {
-            if valid_cells_to_move[x_pos][y_pos] {
-                result_0 += 1;
-            }
-            if x_pos != 7 || y_pos != 3 {
-                result_0 <<= 1;
-            }
-        }
-
-        for y_pos in 4..8 {
-            if valid_cells_to_move[x_pos][y_pos] {
-                result_1 += 1;
-            }
-            if x_pos != 7 || y_pos != 7 {
-                result_1 <<= 1;
-            }
-        }
-    }
-    (result_0, result_1)
-}
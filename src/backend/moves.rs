@@ -1,4 +1,4 @@
-use super::{is_any_piece_attacks_position, Board, Color, PieceKind};
+use super::{is_any_piece_attacks_position, Board, PieceKind};
 
 fn _is_king_move_leads_to_check(
     board: &Board,
@@ -8,10 +8,7 @@ fn _is_king_move_leads_to_check(
     let king_color = board.state[7 - from_position.1][from_position.0]
         .unwrap()
         .color;
-    let oppenent_color = match king_color {
-        Color::Light => Color::Dark,
-        Color::Dark => Color::Light,
-    };
+    let oppenent_color = king_color.opposite();
     is_any_piece_attacks_position(board, oppenent_color, to_position)
 }
 
@@ -160,7 +157,7 @@ pub fn is_en_passant_move(
             piece.kind == PieceKind::Pawn
                 && piece.color != pawn_to_move.color
                 && piece.made_n_moves == 1
-                && piece.last_moved_at_move_number == board.number_of_moves - 1
+                && Some(piece.last_moved_at_move_number) == board.number_of_moves.checked_sub(1)
                 && piece.has_pawn_made_leap
                 && piece_at_destination.is_none()
         }
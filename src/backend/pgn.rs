@@ -0,0 +1,748 @@
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{
+    game::Outcome, has_legal_move, has_mating_material, is_king_in_check, is_legal_move,
+    AnnotationColor, Annotations, Board, Color, FenError, Move, PieceKind, Square, Termination,
+    STARTING_FEN,
+};
+
+/// PGN lines are wrapped before this many characters.
+const MAX_LINE_LENGTH: usize = 80;
+
+/// A move as played, with what was drawn on the position after it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedMove {
+    pub chess_move: Move,
+    /// Standard algebraic notation, e.g. `Nbd7` or `exd5+`.
+    pub san: String,
+    pub annotations: Annotations,
+    /// Time the mover had left after the move, for `[%clk]`.
+    pub clock: Option<Duration>,
+    /// When the move was made, in seconds since the Unix epoch, for
+    /// `[%timestamp]` in correspondence games.
+    pub timestamp: Option<u64>,
+}
+
+/// The moves of a game and its tags, written out as PGN.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameRecord {
+    /// Tag pairs in output order, starting with the Seven Tag Roster.
+    pub tags: Vec<(String, String)>,
+    /// Annotations on the starting position.
+    pub initial_annotations: Annotations,
+    pub moves: Vec<RecordedMove>,
+    /// Half-moves before the first recorded one, odd when Black moves first.
+    pub first_ply: u16,
+    /// How the game ended, kept in step with the `Result` and `Termination`
+    /// tags by `set_outcome`.
+    pub outcome: Option<Outcome>,
+}
+
+impl Default for GameRecord {
+    fn default() -> Self {
+        GameRecord::new()
+    }
+}
+
+impl GameRecord {
+    pub fn new() -> GameRecord {
+        let tags = [
+            ("Event", "Casual game"),
+            ("Site", "?"),
+            ("Date", "????.??.??"),
+            ("Round", "-"),
+            ("White", "?"),
+            ("Black", "?"),
+            ("Result", "*"),
+        ];
+        GameRecord {
+            tags: tags
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            initial_annotations: Annotations::default(),
+            moves: Vec::new(),
+            first_ply: 0,
+            outcome: None,
+        }
+    }
+
+    /// A record of a game played from `board`. Any position but the standard
+    /// starting one goes into the `SetUp` and `FEN` tags.
+    pub fn from_position(board: &Board) -> GameRecord {
+        let mut record = GameRecord::new();
+        let fen = board.to_fen();
+        if fen != STARTING_FEN {
+            record.set_tag("SetUp", "1".to_owned());
+            record.set_tag("FEN", fen);
+        }
+        record.first_ply = board.number_of_moves / 2 * 2
+            + match board.turn {
+                Color::Light => 0,
+                Color::Dark => 1,
+            };
+        record
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag_name, _)| tag_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Replaces the value of tag `name`, or appends the tag.
+    pub fn set_tag(&mut self, name: &str, value: String) {
+        match self.tags.iter_mut().find(|(tag_name, _)| tag_name == name) {
+            Some((_, tag_value)) => *tag_value = value,
+            None => self.tags.push((name.to_owned(), value)),
+        }
+    }
+
+    /// Ends the game with `outcome`, or marks it as still going for `None`.
+    pub fn set_outcome(&mut self, outcome: Option<Outcome>) {
+        self.outcome = outcome;
+        match outcome {
+            Some(outcome) => {
+                self.set_tag("Result", outcome.result().to_owned());
+                self.set_tag("Termination", outcome.pgn_termination().to_owned());
+            }
+            None => {
+                self.set_tag("Result", "*".to_owned());
+                self.tags.retain(|(name, _)| name != "Termination");
+            }
+        }
+    }
+
+    /// Records `chess_move`, which is about to be played on `board`. The new
+    /// position starts without annotations.
+    pub fn push(&mut self, board: &Board, chess_move: Move) {
+        self.moves.push(RecordedMove {
+            chess_move,
+            san: san(board, chess_move),
+            annotations: Annotations::default(),
+            clock: None,
+            timestamp: None,
+        });
+    }
+
+    /// The position the game starts from: the one in the `FEN` tag if
+    /// `SetUp` is 1, checked like any FEN, otherwise the standard one.
+    pub fn start_position(&self) -> Result<Board, FenError> {
+        match (self.tag("SetUp"), self.tag("FEN")) {
+            (Some("1"), Some(fen)) => Board::from_fen(fen),
+            _ => Ok(Board::new(Color::Light)),
+        }
+    }
+
+    /// The position after all recorded moves, played from `start`, or the
+    /// first move that is not legal there.
+    pub fn replay(&self, start: &Board) -> Result<Board, PgnError> {
+        let mut board = *start;
+        for (index, recorded_move) in self.moves.iter().enumerate() {
+            board
+                .try_make_move(recorded_move.chess_move)
+                .map_err(|_| PgnError::IllegalMove {
+                    ply: self.first_ply + index as u16,
+                    san: recorded_move.san.clone(),
+                })?;
+        }
+        Ok(board)
+    }
+
+    /// Annotations of the current position, the one after the last move.
+    pub fn annotations(&self) -> &Annotations {
+        match self.moves.last() {
+            Some(last_move) => &last_move.annotations,
+            None => &self.initial_annotations,
+        }
+    }
+
+    pub fn annotations_mut(&mut self) -> &mut Annotations {
+        match self.moves.last_mut() {
+            Some(last_move) => &mut last_move.annotations,
+            None => &mut self.initial_annotations,
+        }
+    }
+
+    /// Full PGN text. Clock times, timestamps and annotations go into
+    /// comments after their move, and how the game ended into one before the result.
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+        for (name, value) in &self.tags {
+            pgn.push_str(&format!(
+                "[{} \"{}\"]\n",
+                name,
+                value.replace('\\', "\\\\").replace('"', "\\\"")
+            ));
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        if !self.initial_annotations.is_empty() {
+            tokens.push(format!(
+                "{{ {} }}",
+                self.initial_annotations.to_pgn_commands()
+            ));
+        }
+        let mut needs_move_number = true;
+        for (index, recorded_move) in self.moves.iter().enumerate() {
+            let ply = self.first_ply as usize + index;
+            let move_number = ply / 2 + 1;
+            if ply.is_multiple_of(2) {
+                tokens.push(format!("{}.", move_number));
+            } else if needs_move_number {
+                tokens.push(format!("{}...", move_number));
+            }
+            tokens.push(recorded_move.san.clone());
+            let mut commands = String::new();
+            if let Some(clock) = recorded_move.clock {
+                commands.push_str(&clock_command(clock));
+            }
+            if let Some(timestamp) = recorded_move.timestamp {
+                commands.push_str(&format!("[%timestamp {}]", timestamp));
+            }
+            commands.push_str(&recorded_move.annotations.to_pgn_commands());
+            needs_move_number = !commands.is_empty();
+            if needs_move_number {
+                tokens.push(format!("{{ {} }}", commands));
+            }
+        }
+        if let Some(outcome) = self.outcome {
+            tokens.push(format!("{{ {}. }}", outcome));
+        }
+        tokens.push(self.tag("Result").unwrap_or("*").to_owned());
+
+        let mut line_length = 0;
+        for token in tokens {
+            if line_length > 0 && line_length + 1 + token.len() > MAX_LINE_LENGTH {
+                pgn.push('\n');
+                line_length = 0;
+            } else if line_length > 0 {
+                pgn.push(' ');
+                line_length += 1;
+            }
+            line_length += token.len();
+            pgn.push_str(&token);
+        }
+        pgn.push('\n');
+        pgn
+    }
+
+    /// Reads the first game of PGN text, as `to_pgn` writes it or as other
+    /// programs do. Every move is checked against the rules; variations and
+    /// numeric annotation glyphs are skipped. Comments give the clock times,
+    /// timestamps and annotations back, and the one before the result how
+    /// the game ended.
+    pub fn from_pgn(text: &str) -> Result<GameRecord, PgnError> {
+        let mut record = GameRecord::new();
+        let mut rest = text.trim_start_matches('\u{feff}').trim_start();
+        while let Some(tag_pair) = rest.strip_prefix('[') {
+            let (name, value, after) = parse_tag_pair(tag_pair)
+                .ok_or_else(|| PgnError::Syntax(first_line(rest).to_owned()))?;
+            record.set_tag(name, value);
+            rest = after.trim_start();
+        }
+        let start = record.start_position().map_err(PgnError::Fen)?;
+        record.first_ply = GameRecord::from_position(&start).first_ply;
+
+        let mut board = start;
+        // The comment since the last move, which says how the game ended if
+        // the result follows it
+        let mut last_comment = String::new();
+        let mut chars = rest.char_indices().peekable();
+        while let Some((index, character)) = chars.next() {
+            match character {
+                _ if character.is_whitespace() => continue,
+                '{' => {
+                    let comment: String = chars
+                        .by_ref()
+                        .map(|(_, character)| character)
+                        .take_while(|character| *character != '}')
+                        .collect();
+                    last_comment = read_comment(&mut record, &comment);
+                }
+                ';' => while chars.next_if(|(_, character)| *character != '\n').is_some() {},
+                '(' => {
+                    let mut depth = 1;
+                    for (_, character) in chars.by_ref() {
+                        match character {
+                            '(' => depth += 1,
+                            ')' => depth -= 1,
+                            _ => (),
+                        }
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                }
+                _ => {
+                    let mut end = index + character.len_utf8();
+                    while let Some((next_index, next)) = chars.next_if(|(_, next)| {
+                        !next.is_whitespace() && !matches!(next, '{' | ';' | '(' | ')')
+                    }) {
+                        end = next_index + next.len_utf8();
+                    }
+                    let token = &rest[index..end];
+                    if matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*") {
+                        record.set_tag("Result", token.to_owned());
+                        break;
+                    }
+                    if token.starts_with('$') {
+                        continue;
+                    }
+                    // Move numbers, `12.` and `12...`, may be stuck to the move
+                    let san_text = token
+                        .trim_start_matches(|character: char| character.is_ascii_digit())
+                        .trim_start_matches('.');
+                    if san_text.is_empty() {
+                        continue;
+                    }
+                    let ply = record.first_ply + record.moves.len() as u16;
+                    let chess_move =
+                        parse_san(&board, san_text).ok_or_else(|| PgnError::IllegalMove {
+                            ply,
+                            san: san_text.to_owned(),
+                        })?;
+                    record.push(&board, chess_move);
+                    board.make_move_unchecked(chess_move);
+                    last_comment.clear();
+                }
+            }
+        }
+
+        if let Some(result) = record.tag("Result").filter(|result| *result != "*") {
+            let outcome = all_outcomes()
+                .find(|outcome| {
+                    outcome.result() == result
+                        && last_comment.trim_end_matches('.') == outcome.to_string()
+                })
+                .or_else(|| implied_outcome(&board, result, record.tag("Termination")));
+            record.set_outcome(outcome);
+        }
+        Ok(record)
+    }
+}
+
+/// Standard algebraic notation of a legal `chess_move` on `board`.
+pub fn san(board: &Board, chess_move: Move) -> String {
+    let from_cell_position = board.square_to_cell(chess_move.from);
+    let to_cell_position = board.square_to_cell(chess_move.to);
+    let piece = match board.piece_at_square(chess_move.from) {
+        Some(piece) => piece,
+        None => return chess_move.to_string(),
+    };
+    let is_capture = board.piece_at_square(chess_move.to).is_some()
+        || (piece.kind == PieceKind::Pawn && chess_move.from.file != chess_move.to.file);
+
+    let mut notation = String::new();
+    if piece.kind == PieceKind::Pawn {
+        if is_capture {
+            notation.push(chess_move.from.file_char());
+        }
+    } else {
+        notation.push(piece_letter(piece.kind));
+        // Other pieces of the same kind that could go to the same square
+        let rivals: Vec<_> = (0..8)
+            .flat_map(|pos_x| (0..8).map(move |pos_y| (pos_x, pos_y)))
+            .filter(|cell_position| *cell_position != from_cell_position)
+            .filter(|cell_position| {
+                board.state[7 - cell_position.1][cell_position.0]
+                    .is_some_and(|other| other.kind == piece.kind && other.color == piece.color)
+            })
+            .filter(|cell_position| is_legal_move(board, cell_position, &to_cell_position))
+            .map(|cell_position| board.cell_to_square(&cell_position))
+            .collect();
+        if !rivals.is_empty() {
+            if rivals
+                .iter()
+                .all(|rival| rival.file != chess_move.from.file)
+            {
+                notation.push(chess_move.from.file_char());
+            } else if rivals
+                .iter()
+                .all(|rival| rival.rank != chess_move.from.rank)
+            {
+                notation.push(chess_move.from.rank_char());
+            } else {
+                notation.push_str(&chess_move.from.to_string());
+            }
+        }
+    }
+    if is_capture {
+        notation.push('x');
+    }
+    notation.push_str(&chess_move.to.to_string());
+    if let Some(kind) = chess_move.promotion {
+        notation.push('=');
+        notation.push(piece_letter(kind));
+    }
+
+    let mut board_after = *board;
+    board_after.make_move_unchecked(chess_move);
+    if is_king_in_check(&board_after, board_after.turn) {
+        notation.push(if has_legal_move(&board_after) {
+            '+'
+        } else {
+            '#'
+        });
+    }
+    notation
+}
+
+/// The legal move on `board` written `text` in standard algebraic notation.
+/// Check marks and annotations like `!?` are optional, castling may be
+/// written with zeros.
+pub fn parse_san(board: &Board, text: &str) -> Option<Move> {
+    let bare = |text: &str| {
+        text.trim_end_matches(['+', '#', '!', '?'])
+            .replace('0', "O")
+    };
+    let wanted = bare(text);
+    board
+        .legal_moves()
+        .into_iter()
+        .find(|chess_move| bare(&san(board, *chess_move)) == wanted)
+}
+
+fn piece_letter(kind: PieceKind) -> char {
+    match kind {
+        PieceKind::Pawn => 'P',
+        PieceKind::Rook => 'R',
+        PieceKind::Bishop => 'B',
+        PieceKind::Knight => 'N',
+        PieceKind::Queen => 'Q',
+        PieceKind::King => 'K',
+    }
+}
+
+/// `[%clk H:MM:SS]`, whole seconds rounded down.
+fn clock_command(remaining: Duration) -> String {
+    let seconds = remaining.as_secs();
+    format!(
+        "[%clk {}:{:02}:{:02}]",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Seconds since the Unix epoch, now, as timestamps and `Date` tags take it.
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// `YYYY.MM.DD` for the UTC day of a Unix timestamp, as in the `Date` tag.
+pub fn pgn_date(unix_seconds: u64) -> String {
+    // Civil-from-days over the proleptic Gregorian calendar.
+    let days = (unix_seconds / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+/// Why PGN text could not be read.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum PgnError {
+    /// Text that is no PGN, from where it starts.
+    Syntax(String),
+    /// The `FEN` tag does not hold a valid position.
+    Fen(FenError),
+    /// The move written `san` is not legal in the position at `ply`.
+    IllegalMove { ply: u16, san: String },
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgnError::Syntax(text) => write!(f, "invalid PGN at `{}`", text),
+            PgnError::Fen(error) => write!(f, "{}", error),
+            PgnError::IllegalMove { ply, san } => write!(
+                f,
+                "illegal move {}{} {}",
+                ply / 2 + 1,
+                if ply % 2 == 0 { "." } else { "..." },
+                san
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PgnError::Fen(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Name, unescaped value and what follows of a tag pair after its `[`.
+fn parse_tag_pair(text: &str) -> Option<(&str, String, &str)> {
+    let text = text.trim_start();
+    let name_end = text.find(|character: char| character.is_whitespace() || character == '"')?;
+    let (name, rest) = text.split_at(name_end);
+    let quoted = rest.trim_start().strip_prefix('"')?;
+    let mut chars = quoted.char_indices();
+    let mut value = String::new();
+    loop {
+        match chars.next()? {
+            (_, '\\') => value.push(chars.next()?.1),
+            (index, '"') => {
+                let after = quoted[index + 1..].trim_start().strip_prefix(']')?;
+                return (!name.is_empty()).then_some((name, value, after));
+            }
+            (_, character) => value.push(character),
+        }
+    }
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or(text)
+}
+
+/// Takes the `[%clk]`, `[%timestamp]`, `[%csl]` and `[%cal]` commands of a
+/// comment for the last move, or the starting position before any move.
+/// Returns the rest of the comment.
+fn read_comment(record: &mut GameRecord, comment: &str) -> String {
+    let mut text = String::new();
+    let mut rest = comment;
+    while let Some(start) = rest.find("[%") {
+        text.push_str(&rest[..start]);
+        let Some(length) = rest[start..].find(']') else {
+            rest = &rest[start..];
+            break;
+        };
+        let command = &rest[start + 2..start + length];
+        rest = &rest[start + length + 1..];
+        let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+        let argument = argument.trim();
+        match name {
+            "clk" => {
+                if let (Some(last_move), Some(clock)) =
+                    (record.moves.last_mut(), parse_clock(argument))
+                {
+                    last_move.clock = Some(clock);
+                }
+            }
+            "timestamp" => {
+                if let (Some(last_move), Ok(timestamp)) =
+                    (record.moves.last_mut(), argument.parse())
+                {
+                    last_move.timestamp = Some(timestamp);
+                }
+            }
+            "csl" => {
+                for (color, text) in annotation_items(argument) {
+                    if let Some(square) = Square::parse(text) {
+                        record.annotations_mut().marks.push((square, color));
+                    }
+                }
+            }
+            "cal" => {
+                for (color, text) in annotation_items(argument) {
+                    if let Some(arrow) = Move::parse(text) {
+                        record.annotations_mut().arrows.push((arrow, color));
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    text.push_str(rest);
+    text.trim().to_owned()
+}
+
+/// `Ge4,Rd5` as colours and what follows each letter.
+fn annotation_items(argument: &str) -> impl Iterator<Item = (AnnotationColor, &str)> {
+    argument.split(',').filter_map(|item| {
+        let item = item.trim();
+        let color = AnnotationColor::from_pgn_letter(item.chars().next()?)?;
+        Some((color, &item[1..]))
+    })
+}
+
+/// `H:MM:SS`, with fractions of a second or without.
+fn parse_clock(text: &str) -> Option<Duration> {
+    let mut seconds = 0.0;
+    for part in text.split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+fn all_outcomes() -> impl Iterator<Item = Outcome> {
+    let terminations = [
+        Termination::Checkmate,
+        Termination::Stalemate,
+        Termination::Resignation,
+        Termination::Agreement,
+        Termination::Repetition,
+        Termination::FiftyMoves,
+        Termination::InsufficientMaterial,
+        Termination::TimeForfeit,
+    ];
+    [Some(Color::Light), Some(Color::Dark), None]
+        .into_iter()
+        .flat_map(move |winner| {
+            terminations.map(|termination| Outcome {
+                winner,
+                termination,
+            })
+        })
+}
+
+/// How a game with `result` ended, going by the final position and the
+/// `Termination` tag when no comment says.
+fn implied_outcome(board: &Board, result: &str, termination: Option<&str>) -> Option<Outcome> {
+    let winner = match result {
+        "1-0" => Some(Color::Light),
+        "0-1" => Some(Color::Dark),
+        "1/2-1/2" => None,
+        _ => return None,
+    };
+    let termination = if termination == Some("time forfeit") {
+        Termination::TimeForfeit
+    } else if !has_legal_move(board) {
+        if is_king_in_check(board, board.turn) {
+            Termination::Checkmate
+        } else {
+            Termination::Stalemate
+        }
+    } else if Color::ALL
+        .iter()
+        .all(|color| !has_mating_material(board, *color))
+    {
+        Termination::InsufficientMaterial
+    } else if winner.is_some() {
+        Termination::Resignation
+    } else {
+        Termination::Agreement
+    };
+    Some(Outcome {
+        winner,
+        termination,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::AnnotationColor;
+
+    fn chess_move(name: &str) -> Move {
+        Move::parse(name).unwrap()
+    }
+
+    /// A record of `moves` played from `start`.
+    fn record_of(start: &Board, moves: &[&str]) -> GameRecord {
+        let mut record = GameRecord::from_position(start);
+        let mut board = *start;
+        for name in moves {
+            record.push(&board, chess_move(name));
+            board.try_make_move(chess_move(name)).unwrap();
+        }
+        record
+    }
+
+    #[test]
+    fn annotated_games_read_back_as_written() {
+        let start = Board::new(Color::Light);
+        let mut record = record_of(&start, &["e2e4", "c7c5", "g1f3"]);
+        record.set_tag("White", "alice \"the rook\"".to_owned());
+        let square = |name| Square::parse(name).unwrap();
+        record
+            .initial_annotations
+            .toggle_mark(square("e4"), AnnotationColor::Green);
+        record
+            .initial_annotations
+            .toggle_arrow(chess_move("e2e4"), AnnotationColor::Red);
+        record.moves[1]
+            .annotations
+            .toggle_arrow(chess_move("b8c6"), AnnotationColor::Blue);
+        record.moves[2]
+            .annotations
+            .toggle_mark(square("d4"), AnnotationColor::Yellow);
+        record.moves[2]
+            .annotations
+            .toggle_mark(square("c5"), AnnotationColor::Red);
+        record.moves[2].clock = Some(Duration::from_secs(299));
+        let pgn = record.to_pgn();
+        assert!(pgn.contains("{ [%csl Ge4][%cal Re2e4] } 1. e4"));
+        assert!(pgn.contains("[%csl Yd4,Rc5]"));
+        assert_eq!(GameRecord::from_pgn(&pgn), Ok(record));
+
+        // From a set-up position with Black to move, and a finished game
+        let start = Board::from_fen("4k3/8/8/8/8/8/4p3/R3K3 b Q - 0 40").unwrap();
+        let mut record = record_of(&start, &["e8d7", "a1a7", "d7c6"]);
+        record.moves[0]
+            .annotations
+            .toggle_arrow(chess_move("a1a7"), AnnotationColor::Green);
+        record.set_outcome(Some(Outcome {
+            winner: Some(Color::Dark),
+            termination: Termination::Resignation,
+        }));
+        let pgn = record.to_pgn();
+        assert!(pgn.contains("40... Kd7 { [%cal Ga1a7] } 41. Ra7"));
+        assert_eq!(GameRecord::from_pgn(&pgn), Ok(record));
+    }
+
+    #[test]
+    fn replay_checks_every_move() {
+        let start = Board::new(Color::Light);
+        let mut record = record_of(&start, &["e2e4", "e7e5"]);
+        assert_eq!(
+            record.replay(&start).unwrap().to_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2"
+        );
+        record.moves[1].chess_move = chess_move("e7e4");
+        assert_eq!(
+            record.replay(&start).err(),
+            Some(PgnError::IllegalMove {
+                ply: 1,
+                san: "e5".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn bad_pgn_is_rejected() {
+        assert!(matches!(
+            GameRecord::from_pgn("[Event \"unfinished\n\n1. e4 *"),
+            Err(PgnError::Syntax(_))
+        ));
+        assert_eq!(
+            GameRecord::from_pgn("1. e4 e5 2. Ke3 *"),
+            Err(PgnError::IllegalMove {
+                ply: 2,
+                san: "Ke3".to_owned()
+            })
+        );
+        assert_eq!(
+            GameRecord::from_pgn("1. e4 e5 2. Nf3 Nf3 *"),
+            Err(PgnError::IllegalMove {
+                ply: 3,
+                san: "Nf3".to_owned()
+            })
+        );
+        assert!(matches!(
+            GameRecord::from_pgn("[SetUp \"1\"]\n[FEN \"8/8/8/8/8/8/8/8 w - - 0 1\"]\n\n*"),
+            Err(PgnError::Fen(FenError::Position(_)))
+        ));
+    }
+}
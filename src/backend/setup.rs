@@ -0,0 +1,379 @@
+use std::fmt;
+
+use super::{can_move_piece, is_king_in_check, Board, Color, Piece, PieceKind, Square};
+
+/// Which castlings each side may still make, as in the FEN castling field.
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Copy)]
+pub struct CastlingRights {
+    pub light_king_side: bool,
+    pub light_queen_side: bool,
+    pub dark_king_side: bool,
+    pub dark_queen_side: bool,
+}
+
+impl CastlingRights {
+    pub const ALL: CastlingRights = CastlingRights {
+        light_king_side: true,
+        light_queen_side: true,
+        dark_king_side: true,
+        dark_queen_side: true,
+    };
+    pub const NONE: CastlingRights = CastlingRights {
+        light_king_side: false,
+        light_queen_side: false,
+        dark_king_side: false,
+        dark_queen_side: false,
+    };
+
+    /// Drops the rights a move from or to `square` loses: any move of a king
+    /// or rook off its home square, or a capture on a rook's home square.
+    pub fn forget_square(&mut self, square: Square) {
+        match (square.file, square.rank) {
+            (4, 0) => {
+                self.light_king_side = false;
+                self.light_queen_side = false;
+            }
+            (7, 0) => self.light_king_side = false,
+            (0, 0) => self.light_queen_side = false,
+            (4, 7) => {
+                self.dark_king_side = false;
+                self.dark_queen_side = false;
+            }
+            (7, 7) => self.dark_king_side = false,
+            (0, 7) => self.dark_queen_side = false,
+            _ => (),
+        }
+    }
+}
+
+/// Why a position cannot come about in a game, see `Board::validate`.
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Copy)]
+pub enum PositionProblem {
+    MissingKing(Color),
+    ExtraKing(Color),
+    /// More than eight pawns.
+    TooManyPawns(Color),
+    /// More than sixteen pieces.
+    TooManyPieces(Color),
+    PawnOnBackRank(Square),
+    /// The side that just moved left its king attacked.
+    OpponentInCheck,
+    /// The side to move is checked by more than two pieces, which no single
+    /// move can do.
+    TooManyCheckers(usize),
+    /// A castling right with the king or the rook off its home square.
+    CastlingRight {
+        color: Color,
+        is_king_side: bool,
+    },
+    /// The pawn that leapt over the en passant square could not have, as
+    /// the squares it passed are taken.
+    EnPassant(Square),
+}
+
+impl fmt::Display for PositionProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PositionProblem::MissingKing(color) => {
+                write!(f, "{} has no king", color_name(*color))
+            }
+            PositionProblem::ExtraKing(color) => {
+                write!(f, "{} has more than one king", color_name(*color))
+            }
+            PositionProblem::TooManyPawns(color) => {
+                write!(f, "{} has more than 8 pawns", color_name(*color))
+            }
+            PositionProblem::TooManyPieces(color) => {
+                write!(f, "{} has more than 16 pieces", color_name(*color))
+            }
+            PositionProblem::PawnOnBackRank(square) => {
+                write!(f, "pawn on the back rank at {}", square)
+            }
+            PositionProblem::OpponentInCheck => {
+                write!(f, "the side not to move is in check")
+            }
+            PositionProblem::TooManyCheckers(checkers) => {
+                write!(f, "the king to move is checked by {} pieces", checkers)
+            }
+            PositionProblem::CastlingRight {
+                color,
+                is_king_side,
+            } => write!(
+                f,
+                "{} cannot castle {} side: the king or rook has left its square",
+                color_name(*color),
+                if *is_king_side { "king" } else { "queen" }
+            ),
+            PositionProblem::EnPassant(square) => {
+                write!(f, "no pawn can have just leapt over {}", square)
+            }
+        }
+    }
+}
+
+impl Board {
+    /// A board without pieces, Light to move and no castling.
+    pub fn empty() -> Board {
+        Board {
+            state: [[None; 8]; 8],
+            is_reversed: false,
+            turn: Color::Light,
+            color_of_king_under_attack: None,
+            number_of_moves: 0,
+            halfmove_clock: 0,
+            castling_rights: CastlingRights::NONE,
+        }
+    }
+
+    /// Everything that keeps the position from coming about in a game,
+    /// empty for a valid one. Anything building a board from outside input
+    /// should check it, as moves assume a king on each side.
+    pub fn validate(&self) -> Vec<PositionProblem> {
+        let mut problems = Vec::new();
+        for color in Color::ALL {
+            let count = |kind: PieceKind| {
+                self.pieces()
+                    .filter(|(_, piece)| piece.color == color && piece.kind == kind)
+                    .count()
+            };
+            match count(PieceKind::King) {
+                0 => problems.push(PositionProblem::MissingKing(color)),
+                1 => (),
+                _ => problems.push(PositionProblem::ExtraKing(color)),
+            }
+            if count(PieceKind::Pawn) > 8 {
+                problems.push(PositionProblem::TooManyPawns(color));
+            }
+            if self
+                .pieces()
+                .filter(|(_, piece)| piece.color == color)
+                .count()
+                > 16
+            {
+                problems.push(PositionProblem::TooManyPieces(color));
+            }
+        }
+        for (square, piece) in self.pieces() {
+            if piece.kind == PieceKind::Pawn && (square.rank == 0 || square.rank == 7) {
+                problems.push(PositionProblem::PawnOnBackRank(square));
+            }
+        }
+
+        let opponent_color = self.turn.opposite();
+        if is_king_in_check(self, opponent_color) {
+            problems.push(PositionProblem::OpponentInCheck);
+        }
+        if let Some(king_position) = self.get_king_position(self.turn) {
+            let checkers = self
+                .pieces()
+                .filter(|(square, piece)| {
+                    piece.color == opponent_color
+                        && can_move_piece(self, &self.square_to_cell(*square), &king_position)
+                })
+                .count();
+            if checkers > 2 {
+                problems.push(PositionProblem::TooManyCheckers(checkers));
+            }
+        }
+
+        let castling_rights = self.castling_rights;
+        for (has_right, color, is_king_side, rook_file) in [
+            (castling_rights.light_king_side, Color::Light, true, 7),
+            (castling_rights.light_queen_side, Color::Light, false, 0),
+            (castling_rights.dark_king_side, Color::Dark, true, 7),
+            (castling_rights.dark_queen_side, Color::Dark, false, 0),
+        ] {
+            let home_rank = match color {
+                Color::Light => 0,
+                Color::Dark => 7,
+            };
+            let is_at = |file: usize, kind: PieceKind| {
+                self.piece_at_square(Square {
+                    file,
+                    rank: home_rank,
+                })
+                .is_some_and(|piece| piece.color == color && piece.kind == kind)
+            };
+            if has_right && !(is_at(4, PieceKind::King) && is_at(rook_file, PieceKind::Rook)) {
+                problems.push(PositionProblem::CastlingRight {
+                    color,
+                    is_king_side,
+                });
+            }
+        }
+
+        if let Some(square) = self.en_passant_square() {
+            // The square passed over and the one the pawn started from
+            let start_rank = if square.rank == 2 { 1 } else { 6 };
+            let start_square = Square {
+                file: square.file,
+                rank: start_rank,
+            };
+            if self.piece_at_square(square).is_some()
+                || self.piece_at_square(start_square).is_some()
+            {
+                problems.push(PositionProblem::EnPassant(square));
+            }
+        }
+        problems
+    }
+
+    /// Pieces with their squares, rank by rank from a1.
+    pub fn pieces(&self) -> impl Iterator<Item = (Square, Piece)> + '_ {
+        (0..8)
+            .flat_map(|rank| (0..8).map(move |file| Square { file, rank }))
+            .filter_map(|square| self.piece_at_square(square).map(|piece| (square, piece)))
+    }
+
+    /// The square a pawn may be taken on en passant, behind a pawn of the
+    /// side not to move that leapt on the last move.
+    pub fn en_passant_square(&self) -> Option<Square> {
+        let last_move_number = self.number_of_moves.checked_sub(1)?;
+        self.pieces().find_map(|(square, piece)| {
+            let is_leapt_pawn = piece.kind == PieceKind::Pawn
+                && piece.color != self.turn
+                && piece.has_pawn_made_leap
+                && piece.made_n_moves == 1
+                && piece.last_moved_at_move_number == last_move_number;
+            match (is_leapt_pawn, piece.color) {
+                (true, Color::Light) if square.rank == 3 => Square::new(square.file, 2),
+                (true, Color::Dark) if square.rank == 4 => Square::new(square.file, 5),
+                _ => None,
+            }
+        })
+    }
+
+    /// Makes the pawn in front of `square` the one that just leapt over it,
+    /// or forgets any leap for `None`. Returns false when there is no pawn
+    /// of the side not to move to mark, leaving no en passant square.
+    pub fn set_en_passant_square(&mut self, square: Option<Square>) -> bool {
+        for piece in self.state.iter_mut().flatten().flatten() {
+            piece.has_pawn_made_leap = false;
+        }
+        let square = match square {
+            Some(square) => square,
+            None => return true,
+        };
+        let pawn_square = match (self.turn, square.rank) {
+            (Color::Light, 5) => Square {
+                file: square.file,
+                rank: 4,
+            },
+            (Color::Dark, 2) => Square {
+                file: square.file,
+                rank: 3,
+            },
+            _ => return false,
+        };
+        let cell_position = self.square_to_cell(pawn_square);
+        let turn = self.turn;
+        // The pawn moved on the previous move, so there has to be one
+        self.number_of_moves = self.number_of_moves.max(1);
+        let last_move_number = self.number_of_moves - 1;
+        match &mut self.state[7 - cell_position.1][cell_position.0] {
+            Some(piece) if piece.kind == PieceKind::Pawn && piece.color != turn => {
+                piece.has_pawn_made_leap = true;
+                piece.made_n_moves = 1;
+                piece.last_moved_at_move_number = last_move_number;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::Light => "white",
+        Color::Dark => "black",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{FenError, STARTING_FEN};
+
+    /// What `validate` finds wrong with the position of `fen`.
+    fn problems(fen: &str) -> Vec<PositionProblem> {
+        match Board::from_fen(fen) {
+            Ok(board) => board.validate(),
+            Err(FenError::Position(problems)) => problems,
+            Err(error) => panic!("{}: {}", fen, error),
+        }
+    }
+
+    fn square(name: &str) -> Square {
+        Square::parse(name).unwrap()
+    }
+
+    #[test]
+    fn valid_positions_have_no_problems() {
+        for fen in [
+            STARTING_FEN,
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+            "r3k3/8/8/8/8/8/8/4K3 w q - 0 1",
+            "4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1",
+            // Checked by two pieces at once, as a discovered check can
+            "4k3/8/3N4/8/8/8/8/K3R3 b - - 0 1",
+        ] {
+            assert_eq!(problems(fen), Vec::new(), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn each_problem_is_found() {
+        assert_eq!(
+            problems("8/8/8/8/8/8/8/4K3 w - - 0 1"),
+            vec![PositionProblem::MissingKing(Color::Dark)]
+        );
+        assert_eq!(
+            problems("4k3/8/8/8/8/8/8/K3K3 w - - 0 1"),
+            vec![PositionProblem::ExtraKing(Color::Light)]
+        );
+        assert_eq!(
+            problems("4k3/8/8/8/8/PPPPPPPP/P7/4K3 w - - 0 1"),
+            vec![PositionProblem::TooManyPawns(Color::Light)]
+        );
+        assert!(problems("4k3/8/8/nnnnnnnn/nnnnnnnn/8/8/K7 b - - 0 1")
+            .contains(&PositionProblem::TooManyPieces(Color::Dark)));
+        assert_eq!(
+            problems("P3k3/8/8/8/8/8/8/4K2p w - - 0 1"),
+            vec![
+                PositionProblem::PawnOnBackRank(square("h1")),
+                PositionProblem::PawnOnBackRank(square("a8")),
+            ]
+        );
+        assert_eq!(
+            problems("4k3/8/8/8/8/8/8/4RK2 w - - 0 1"),
+            vec![PositionProblem::OpponentInCheck]
+        );
+        assert_eq!(
+            problems("4k3/8/3N1N2/8/8/8/8/K3R3 b - - 0 1"),
+            vec![PositionProblem::TooManyCheckers(3)]
+        );
+        assert_eq!(
+            problems("4k3/8/8/8/8/8/8/4K3 w K - 0 1"),
+            vec![PositionProblem::CastlingRight {
+                color: Color::Light,
+                is_king_side: true,
+            }]
+        );
+        assert_eq!(
+            problems("1r2k3/8/8/8/8/8/8/4K3 w q - 0 1"),
+            vec![PositionProblem::CastlingRight {
+                color: Color::Dark,
+                is_king_side: false,
+            }]
+        );
+        // The square passed over, and the one the pawn came from, are taken
+        assert_eq!(
+            problems("4k3/8/8/8/4P3/4N3/8/4K3 b - e3 0 1"),
+            vec![PositionProblem::EnPassant(square("e3"))]
+        );
+        assert_eq!(
+            problems("4k3/8/8/8/4P3/8/4N3/4K3 b - e3 0 1"),
+            vec![PositionProblem::EnPassant(square("e3"))]
+        );
+    }
+}
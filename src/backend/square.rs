@@ -0,0 +1,371 @@
+use std::fmt;
+
+use super::{can_move_piece, is_legal_move, Board, Color, PieceKind};
+
+/// A square in standard notation terms: file 0 is `a`, rank 0 is `1`,
+/// whatever the board orientation.
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Copy)]
+pub struct Square {
+    pub file: usize,
+    pub rank: usize,
+}
+
+impl Square {
+    pub fn new(file: usize, rank: usize) -> Option<Square> {
+        if file < 8 && rank < 8 {
+            Some(Square { file, rank })
+        } else {
+            None
+        }
+    }
+
+    /// Parses names like `e4`.
+    pub fn parse(name: &str) -> Option<Square> {
+        let mut chars = name.chars();
+        let file = chars.next()?;
+        let rank = chars.next()?;
+        if chars.next().is_some() || !('a'..='h').contains(&file) || !('1'..='8').contains(&rank) {
+            return None;
+        }
+        Square::new(file as usize - 'a' as usize, rank as usize - '1' as usize)
+    }
+
+    pub fn file_char(&self) -> char {
+        (b'a' + self.file as u8) as char
+    }
+
+    pub fn rank_char(&self) -> char {
+        (b'1' + self.rank as u8) as char
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.file_char(), self.rank_char())
+    }
+}
+
+/// A move from one square to another, written like `e2e4`, or `e7e8q` with
+/// a promotion.
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Copy)]
+pub struct Move {
+    pub from: Square,
+    pub to: Square,
+    /// What a pawn reaching the last rank becomes.
+    pub promotion: Option<PieceKind>,
+}
+
+impl Move {
+    pub fn new(from: Square, to: Square) -> Move {
+        Move {
+            from,
+            to,
+            promotion: None,
+        }
+    }
+
+    pub fn with_promotion(self, kind: PieceKind) -> Move {
+        Move {
+            promotion: Some(kind),
+            ..self
+        }
+    }
+
+    /// Parses names like `e2e4` or `e7e8q`.
+    pub fn parse(name: &str) -> Option<Move> {
+        if !name.is_ascii() || !(4..=5).contains(&name.len()) {
+            return None;
+        }
+        let promotion = match name[4..].chars().next() {
+            None => None,
+            Some('q') => Some(PieceKind::Queen),
+            Some('r') => Some(PieceKind::Rook),
+            Some('b') => Some(PieceKind::Bishop),
+            Some('n') => Some(PieceKind::Knight),
+            Some(_) => return None,
+        };
+        Some(Move {
+            from: Square::parse(&name[..2])?,
+            to: Square::parse(&name[2..4])?,
+            promotion,
+        })
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.from, self.to)?;
+        match self.promotion {
+            Some(PieceKind::Queen) => write!(f, "q"),
+            Some(PieceKind::Rook) => write!(f, "r"),
+            Some(PieceKind::Bishop) => write!(f, "b"),
+            Some(PieceKind::Knight) => write!(f, "n"),
+            Some(PieceKind::Pawn) => write!(f, "p"),
+            Some(PieceKind::King) => write!(f, "k"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Why `Board::try_make_move` refused a move.
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Copy)]
+pub enum MoveError {
+    NoPiece(Square),
+    /// The piece belongs to the side not to move.
+    WrongSide(Square),
+    /// The piece does not move like that, or the way is blocked.
+    IllegalPattern(Move),
+    LeavesKingInCheck(Move),
+    /// A pawn reaches the last rank without saying what it becomes.
+    MissingPromotion(Move),
+    /// A promotion to a pawn or king, or on a move that does not promote.
+    InvalidPromotion(Move),
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveError::NoPiece(square) => write!(f, "no piece on {}", square),
+            MoveError::WrongSide(square) => {
+                write!(f, "the piece on {} belongs to the side not to move", square)
+            }
+            MoveError::IllegalPattern(chess_move) => {
+                write!(f, "the piece cannot move {}", chess_move)
+            }
+            MoveError::LeavesKingInCheck(chess_move) => {
+                write!(f, "{} leaves the king in check", chess_move)
+            }
+            MoveError::MissingPromotion(chess_move) => {
+                write!(f, "{} needs a piece to promote to", chess_move)
+            }
+            MoveError::InvalidPromotion(chess_move) => {
+                write!(f, "{} is not a valid promotion", chess_move)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+impl Board {
+    /// Cell positions are `(x, y)` from the lower-left corner of the board as
+    /// drawn, so they depend on `is_reversed`.
+    pub fn cell_to_square(&self, cell_position: &(usize, usize)) -> Square {
+        if self.is_reversed {
+            Square {
+                file: 7 - cell_position.0,
+                rank: 7 - cell_position.1,
+            }
+        } else {
+            Square {
+                file: cell_position.0,
+                rank: cell_position.1,
+            }
+        }
+    }
+
+    pub fn square_to_cell(&self, square: Square) -> (usize, usize) {
+        if self.is_reversed {
+            (7 - square.file, 7 - square.rank)
+        } else {
+            (square.file, square.rank)
+        }
+    }
+
+    pub fn piece_at_square(&self, square: Square) -> Option<super::Piece> {
+        let cell_position = self.square_to_cell(square);
+        self.state[7 - cell_position.1][cell_position.0]
+    }
+
+    /// Plays `chess_move` if it is legal for the side to move, see
+    /// `try_make_move`. Returns whether the move was made.
+    pub fn make_square_move(&mut self, chess_move: Move) -> bool {
+        self.try_make_move(chess_move).is_ok()
+    }
+
+    /// Plays `chess_move` after checking it in full, leaving the board as it
+    /// was on error. Safe for moves from untrusted input.
+    pub fn try_make_move(&mut self, chess_move: Move) -> Result<(), MoveError> {
+        let from_cell_position = self.square_to_cell(chess_move.from);
+        let to_cell_position = self.square_to_cell(chess_move.to);
+        let piece = self
+            .piece_at_square(chess_move.from)
+            .ok_or(MoveError::NoPiece(chess_move.from))?;
+        if piece.color != self.turn {
+            return Err(MoveError::WrongSide(chess_move.from));
+        }
+        if chess_move.from == chess_move.to
+            || !can_move_piece(self, &from_cell_position, &to_cell_position)
+        {
+            return Err(MoveError::IllegalPattern(chess_move));
+        }
+        if !is_legal_move(self, &from_cell_position, &to_cell_position) {
+            return Err(MoveError::LeavesKingInCheck(chess_move));
+        }
+        match (self.is_promotion(chess_move), chess_move.promotion) {
+            (true, None) => return Err(MoveError::MissingPromotion(chess_move)),
+            (
+                true,
+                Some(PieceKind::Queen | PieceKind::Rook | PieceKind::Bishop | PieceKind::Knight),
+            )
+            | (false, None) => (),
+            _ => return Err(MoveError::InvalidPromotion(chess_move)),
+        }
+        self.make_move_unchecked(chess_move);
+        Ok(())
+    }
+
+    /// Plays `chess_move` with no checks at all, promoting if it says so.
+    /// For moves already known to be legal; there has to be a piece to move.
+    pub fn make_move_unchecked(&mut self, chess_move: Move) {
+        let to_cell_position = self.square_to_cell(chess_move.to);
+        self.make_a_move(&self.square_to_cell(chess_move.from), &to_cell_position);
+        if let (Some(kind), Some(piece)) = (
+            chess_move.promotion,
+            &mut self.state[7 - to_cell_position.1][to_cell_position.0],
+        ) {
+            piece.kind = kind;
+        }
+    }
+
+    /// Every legal move of the side to move, with one move per promotion
+    /// piece.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        for (from, piece) in self.pieces().filter(|(_, piece)| piece.color == self.turn) {
+            let from_cell_position = self.square_to_cell(from);
+            for to in (0..8).flat_map(|rank| (0..8).map(move |file| Square { file, rank })) {
+                if !is_legal_move(self, &from_cell_position, &self.square_to_cell(to)) {
+                    continue;
+                }
+                let chess_move = Move::new(from, to);
+                if piece.kind == PieceKind::Pawn && (to.rank == 0 || to.rank == 7) {
+                    for kind in [
+                        PieceKind::Queen,
+                        PieceKind::Rook,
+                        PieceKind::Bishop,
+                        PieceKind::Knight,
+                    ] {
+                        moves.push(chess_move.with_promotion(kind));
+                    }
+                } else {
+                    moves.push(chess_move);
+                }
+            }
+        }
+        moves
+    }
+
+    /// Whether `chess_move` takes a pawn to the last rank.
+    pub fn is_promotion(&self, chess_move: Move) -> bool {
+        self.piece_at_square(chess_move.from).is_some_and(|piece| {
+            piece.kind == PieceKind::Pawn
+                && chess_move.to.rank
+                    == match piece.color {
+                        Color::Light => 7,
+                        Color::Dark => 0,
+                    }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chess_move(name: &str) -> Move {
+        Move::parse(name).unwrap()
+    }
+
+    /// Tries `name` on the position of `fen`, checking that a refused move
+    /// leaves the board as it was.
+    fn try_move(fen: &str, name: &str) -> Result<Board, MoveError> {
+        let mut board = Board::from_fen(fen).unwrap();
+        let result = board.try_make_move(chess_move(name));
+        if result.is_err() {
+            assert_eq!(board.to_fen(), fen);
+        }
+        result.map(|()| board)
+    }
+
+    const START: &str = crate::backend::STARTING_FEN;
+    const PROMOTION: &str = "4k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+
+    #[test]
+    fn each_refused_move_says_why() {
+        let square = |name| Square::parse(name).unwrap();
+        assert_eq!(
+            try_move(START, "e3e4").err(),
+            Some(MoveError::NoPiece(square("e3")))
+        );
+        assert_eq!(
+            try_move(START, "e7e5").err(),
+            Some(MoveError::WrongSide(square("e7")))
+        );
+        for name in ["e2e5", "a1a3", "e2e2"] {
+            assert_eq!(
+                try_move(START, name).err(),
+                Some(MoveError::IllegalPattern(chess_move(name)))
+            );
+        }
+        // The bishop is pinned to its king
+        assert_eq!(
+            try_move("4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1", "e2d3").err(),
+            Some(MoveError::LeavesKingInCheck(chess_move("e2d3")))
+        );
+        assert_eq!(
+            try_move(PROMOTION, "a7a8").err(),
+            Some(MoveError::MissingPromotion(chess_move("a7a8")))
+        );
+        for invalid in [
+            chess_move("a7a8").with_promotion(PieceKind::King),
+            chess_move("a7a8").with_promotion(PieceKind::Pawn),
+            chess_move("e1e2q"),
+        ] {
+            let mut board = Board::from_fen(PROMOTION).unwrap();
+            assert_eq!(
+                board.try_make_move(invalid),
+                Err(MoveError::InvalidPromotion(invalid))
+            );
+            assert_eq!(board.to_fen(), PROMOTION);
+        }
+    }
+
+    #[test]
+    fn a_pawn_becomes_each_promotion_piece() {
+        for (letter, kind) in [
+            ('q', PieceKind::Queen),
+            ('r', PieceKind::Rook),
+            ('b', PieceKind::Bishop),
+            ('n', PieceKind::Knight),
+        ] {
+            let board = try_move(PROMOTION, &format!("a7a8{}", letter)).unwrap();
+            let piece = board.piece_at_square(Square::parse("a8").unwrap()).unwrap();
+            assert_eq!(piece.kind, kind);
+            assert_eq!(piece.color, Color::Light);
+            assert!(board
+                .piece_at_square(Square::parse("a7").unwrap())
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn an_en_passant_capture_may_not_uncover_a_rank_attack() {
+        // Both pawns leave the fifth rank, opening it to the rook
+        let mut board = Board::from_fen("7k/2p5/8/KP5r/8/8/8/8 b - - 0 1").unwrap();
+        board.try_make_move(chess_move("c7c5")).unwrap();
+        assert_eq!(
+            board.try_make_move(chess_move("b5c6")),
+            Err(MoveError::LeavesKingInCheck(chess_move("b5c6")))
+        );
+        assert!(!board.legal_moves().contains(&chess_move("b5c6")));
+
+        // Without the rook the same capture is fine and takes the pawn
+        let mut board = Board::from_fen("7k/2p5/8/KP6/8/8/8/8 b - - 0 1").unwrap();
+        board.try_make_move(chess_move("c7c5")).unwrap();
+        board.try_make_move(chess_move("b5c6")).unwrap();
+        assert!(board
+            .piece_at_square(Square::parse("c5").unwrap())
+            .is_none());
+    }
+}
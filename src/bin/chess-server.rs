@@ -0,0 +1,42 @@
+use std::path::PathBuf;
+
+use chess::server::Server;
+
+const USAGE: &str =
+    "usage: chess-server [<port> | <address>:<port>] [--pgn <file>] [--websocket <port>]";
+const DEFAULT_PORT: &str = "7879";
+
+fn main() {
+    let mut address = None;
+    let mut pgn_path = None;
+    let mut websocket_address = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--pgn" => pgn_path = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--websocket" => websocket_address = Some(args.next().unwrap_or_else(|| usage())),
+            _ if !arg.starts_with('-') && address.is_none() => address = Some(arg),
+            _ => usage(),
+        }
+    }
+    let result = Server::bind(address.as_deref().unwrap_or(DEFAULT_PORT)).and_then(|mut server| {
+        if let Some(path) = pgn_path {
+            server.save_games_to(path);
+        }
+        println!("chess-server: listening on {}", server.local_addr()?);
+        if let Some(address) = websocket_address {
+            let address = server.listen_websocket(&address)?;
+            println!("chess-server: WebSocket clients on ws://{}", address);
+        }
+        server.run()
+    });
+    if let Err(error) = result {
+        eprintln!("chess-server: {}", error);
+        std::process::exit(1);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(1);
+}
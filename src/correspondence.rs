@@ -0,0 +1,718 @@
+//! Correspondence games kept in a file, for slow games over a shared folder
+//! without any server. The file is plain PGN: besides the usual tags it has
+//! a `GameId`, every move carries a `[%timestamp]` comment with when it was
+//! made, and a `Checksum` tag seals the moves and the result:
+//!
+//! ```text
+//! [Event "Correspondence game"]
+//! [White "alice"]
+//! [Black "bob"]
+//! [Result "*"]
+//! [GameId "3f9a0c7d12e4b865"]
+//! [Checksum "6d1e…"]
+//!
+//! 1. e4 { [%timestamp 1760882602] } 1... c5 { [%timestamp 1760958114] } *
+//! ```
+//!
+//! Opening a file replays it move by move, so an illegal history, a result
+//! the moves do not lead to, timestamps out of order or a checksum that does
+//! not match are all refused. Anyone can compute a checksum though, so each
+//! player also keeps a journal of the histories they have seen, by default
+//! in `~/.chess/correspondence`; a file that no longer starts with moves seen
+//! before is refused too.
+
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+use sha2::{Digest, Sha256};
+
+use crate::backend::pgn::{pgn_date, unix_timestamp, GameRecord, PgnError};
+use crate::backend::{
+    Board, Color, FenError, GameController, GameError, Move, Outcome, Termination, STARTING_FEN,
+};
+
+const ID_TAG: &str = "GameId";
+const CHECKSUM_TAG: &str = "Checksum";
+
+/// A correspondence game as read from its file, written back after every
+/// move.
+pub struct CorrespondenceGame {
+    path: PathBuf,
+    /// Where the histories seen are kept, if anywhere.
+    journal: Option<PathBuf>,
+    game: GameController,
+    /// The checksum the file had when read or last written, to notice
+    /// changes made in the meantime.
+    checksum: String,
+}
+
+impl CorrespondenceGame {
+    /// Starts a game from the standard position in a new file at `path`,
+    /// noting its history in the `journal` given. Names still unknown can be
+    /// `?`, they are filled in with the first move of each side.
+    pub fn create(
+        path: &Path,
+        journal: Option<&Path>,
+        white: &str,
+        black: &str,
+    ) -> Result<CorrespondenceGame, CorrespondenceError> {
+        let mut game = GameController::new(Board::new(Color::Light), None, Instant::now());
+        let record = game.record_mut();
+        record.set_tag("Event", "Correspondence game".to_owned());
+        record.set_tag("Date", pgn_date(unix_timestamp()));
+        record.set_tag("White", white.to_owned());
+        record.set_tag("Black", black.to_owned());
+        record.set_tag(ID_TAG, new_game_id());
+        let mut correspondence_game = CorrespondenceGame {
+            path: path.to_owned(),
+            journal: journal.map(Path::to_owned),
+            game,
+            checksum: String::new(),
+        };
+        correspondence_game.save(true)?;
+        Ok(correspondence_game)
+    }
+
+    /// Reads the game in `path`, checking its history as described above
+    /// against the `journal` given.
+    pub fn open(
+        path: &Path,
+        journal: Option<&Path>,
+    ) -> Result<CorrespondenceGame, CorrespondenceError> {
+        let record = GameRecord::from_pgn(&fs::read_to_string(path)?)?;
+        let (Some(id), Some(checksum)) = (record.tag(ID_TAG), record.tag(CHECKSUM_TAG)) else {
+            return Err(CorrespondenceError::Tampered(
+                "no game id or checksum, not a correspondence game".to_owned(),
+            ));
+        };
+        if checksum != seal(&record) {
+            return Err(CorrespondenceError::Tampered(
+                "the checksum does not match the moves".to_owned(),
+            ));
+        }
+        let mut previous_timestamp = 0;
+        for recorded_move in &record.moves {
+            match recorded_move.timestamp {
+                Some(timestamp) if timestamp >= previous_timestamp => {
+                    previous_timestamp = timestamp
+                }
+                _ => {
+                    return Err(CorrespondenceError::Tampered(format!(
+                        "the timestamp of {} is missing or out of order",
+                        recorded_move.san
+                    )))
+                }
+            }
+        }
+        if let Some((plies, digest)) = journal.and_then(|journal| journal_entry(journal, id)) {
+            if plies > record.moves.len() || history_digest(&record, plies) != digest {
+                return Err(CorrespondenceError::Tampered(format!(
+                    "the first {} moves differ from the ones seen before",
+                    plies
+                )));
+            }
+        }
+
+        // Moves were checked while reading, the result is checked here
+        let now = Instant::now();
+        let mut game = GameController::new(record.start_position()?, None, now);
+        for recorded_move in &record.moves {
+            game.play(recorded_move.chess_move, now)?;
+            if let Some(last_move) = game.record_mut().moves.last_mut() {
+                last_move.timestamp = recorded_move.timestamp;
+            }
+        }
+        if let Some(outcome) = record.outcome.filter(|_| !game.is_over()) {
+            end_as_recorded(&mut game, outcome, now);
+        }
+        if game.outcome() != record.outcome || game.record().tag("Result") != record.tag("Result") {
+            return Err(CorrespondenceError::Tampered(
+                "the result does not follow from the moves".to_owned(),
+            ));
+        }
+        for (name, value) in &record.tags {
+            if name != "Result" && name != "Termination" {
+                game.record_mut().set_tag(name, value.clone());
+            }
+        }
+        if let Some(journal) = journal {
+            remember(journal, game.record());
+        }
+        Ok(CorrespondenceGame {
+            path: path.to_owned(),
+            journal: journal.map(Path::to_owned),
+            checksum: checksum.to_owned(),
+            game,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn journal(&self) -> Option<&Path> {
+        self.journal.as_deref()
+    }
+
+    /// The game as in the file, to show or to read the moves from.
+    pub fn game(&self) -> &GameController {
+        &self.game
+    }
+
+    /// Plays `chess_move` for the side to move, whose player is `name`, and
+    /// writes the file with the move timestamped at `timestamp`, in seconds
+    /// since the Unix epoch. The timestamp cannot go back before the
+    /// previous move's.
+    pub fn play(
+        &mut self,
+        chess_move: Move,
+        name: &str,
+        timestamp: u64,
+    ) -> Result<(), CorrespondenceError> {
+        let color = self.game.board().turn;
+        self.check_player(color, name)?;
+        if let Some(previous) = self
+            .game
+            .record()
+            .moves
+            .last()
+            .and_then(|recorded_move| recorded_move.timestamp)
+            .filter(|previous| *previous > timestamp)
+        {
+            return Err(CorrespondenceError::TimestampBefore(previous));
+        }
+        self.check_unchanged()?;
+        self.game.play(chess_move, Instant::now())?;
+        if let Some(last_move) = self.game.record_mut().moves.last_mut() {
+            last_move.timestamp = Some(timestamp);
+        }
+        self.sign(color, name);
+        self.save(false)
+    }
+
+    /// Ends the game with `color` resigning and writes the file.
+    pub fn resign(&mut self, color: Color, name: &str) -> Result<(), CorrespondenceError> {
+        self.check_player(color, name)?;
+        self.check_unchanged()?;
+        self.game.resign(color, Instant::now())?;
+        self.sign(color, name);
+        self.save(false)
+    }
+
+    /// Refuses `name` acting for `color` when someone else signed for it.
+    fn check_player(&self, color: Color, name: &str) -> Result<(), CorrespondenceError> {
+        match self.game.record().tag(player_tag(color)) {
+            Some(signed) if !is_unsigned(signed) && signed != name => {
+                Err(CorrespondenceError::NotYourSide(signed.to_owned()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Puts `name` into the tag of `color` if nobody signed for it yet.
+    fn sign(&mut self, color: Color, name: &str) {
+        let tag = player_tag(color);
+        let record = self.game.record_mut();
+        if record.tag(tag).is_none_or(is_unsigned) && !name.is_empty() {
+            record.set_tag(tag, name.to_owned());
+        }
+    }
+
+    /// Refuses to write over a file someone else wrote since it was read.
+    fn check_unchanged(&self) -> Result<(), CorrespondenceError> {
+        let record = GameRecord::from_pgn(&fs::read_to_string(&self.path)?)?;
+        if record.tag(CHECKSUM_TAG) == Some(self.checksum.as_str()) {
+            Ok(())
+        } else {
+            Err(CorrespondenceError::ChangedOnDisk)
+        }
+    }
+
+    /// Seals the record and writes it, through a file next to the game that
+    /// replaces it at once, so the other side never reads half a game.
+    fn save(&mut self, is_new: bool) -> Result<(), CorrespondenceError> {
+        self.checksum = seal(self.game.record());
+        let checksum = self.checksum.clone();
+        self.game.record_mut().set_tag(CHECKSUM_TAG, checksum);
+        let pgn = self.game.record().to_pgn();
+        if is_new {
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&self.path)?
+                .write_all(pgn.as_bytes())?;
+        } else {
+            let mut temporary = self.path.clone().into_os_string();
+            temporary.push(".tmp");
+            fs::write(&temporary, pgn)?;
+            fs::rename(&temporary, &self.path)?;
+        }
+        if let Some(journal) = &self.journal {
+            remember(journal, self.game.record());
+        }
+        Ok(())
+    }
+}
+
+/// Why a correspondence game could not be read or played.
+#[derive(Debug)]
+pub enum CorrespondenceError {
+    Io(io::Error),
+    /// The file is no PGN, or has an illegal move.
+    Pgn(PgnError),
+    /// The history was changed, for this reason.
+    Tampered(String),
+    /// The move or resignation is not allowed.
+    Game(GameError),
+    /// The file was written by someone else since it was opened.
+    ChangedOnDisk,
+    /// The side is signed for by this other player.
+    NotYourSide(String),
+    /// The move would be timestamped before the previous one, made at this
+    /// time.
+    TimestampBefore(u64),
+    /// The game on the board went its own way, by undo or a new game.
+    Diverged,
+}
+
+impl fmt::Display for CorrespondenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CorrespondenceError::Io(error) => write!(f, "{}", error),
+            CorrespondenceError::Pgn(error) => write!(f, "{}", error),
+            CorrespondenceError::Tampered(reason) => {
+                write!(f, "the game file was tampered with: {}", reason)
+            }
+            CorrespondenceError::Game(error) => write!(f, "{}", error),
+            CorrespondenceError::ChangedOnDisk => {
+                write!(f, "the game file changed since it was opened")
+            }
+            CorrespondenceError::NotYourSide(name) => {
+                write!(f, "{} plays this side of the game", name)
+            }
+            CorrespondenceError::TimestampBefore(previous) => write!(
+                f,
+                "the move would be timestamped before the previous one, made at {}",
+                previous
+            ),
+            CorrespondenceError::Diverged => {
+                write!(f, "the game on the board no longer follows the file")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CorrespondenceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CorrespondenceError::Io(error) => Some(error),
+            CorrespondenceError::Pgn(error) => Some(error),
+            CorrespondenceError::Game(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CorrespondenceError {
+    fn from(error: io::Error) -> Self {
+        CorrespondenceError::Io(error)
+    }
+}
+
+impl From<PgnError> for CorrespondenceError {
+    fn from(error: PgnError) -> Self {
+        CorrespondenceError::Pgn(error)
+    }
+}
+
+impl From<FenError> for CorrespondenceError {
+    fn from(error: FenError) -> Self {
+        CorrespondenceError::Pgn(PgnError::Fen(error))
+    }
+}
+
+impl From<GameError> for CorrespondenceError {
+    fn from(error: GameError) -> Self {
+        CorrespondenceError::Game(error)
+    }
+}
+
+/// Ends `game` with `outcome` as read from its file, where the rules allow
+/// it. Mate, stalemate and a lack of material end a game by themselves, and
+/// with no clock nobody loses on time; what is refused leaves the game going.
+fn end_as_recorded(game: &mut GameController, outcome: Outcome, now: Instant) {
+    let _ = match (outcome.termination, outcome.winner) {
+        (Termination::Resignation, Some(winner)) => game.resign(winner.opposite(), now),
+        (Termination::Agreement, None) => {
+            let turn = game.board().turn;
+            game.offer_draw(turn, now)
+                .and_then(|_| game.accept_draw(turn.opposite(), now))
+        }
+        (Termination::Repetition | Termination::FiftyMoves, None) => game.claim_draw(now),
+        _ => return,
+    };
+}
+
+fn player_tag(color: Color) -> &'static str {
+    match color {
+        Color::Light => "White",
+        Color::Dark => "Black",
+    }
+}
+
+/// Whether a `White` or `Black` tag still waits for a name.
+fn is_unsigned(name: &str) -> bool {
+    name.is_empty() || name == "?"
+}
+
+/// Digest of the game id, the starting position and the first `plies`
+/// moves with their timestamps, hex encoded.
+fn history_digest(record: &GameRecord, plies: usize) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(record.tag(ID_TAG).unwrap_or_default());
+    hasher.update("\n");
+    hasher.update(match record.tag("SetUp") {
+        Some("1") => record.tag("FEN").unwrap_or_default(),
+        _ => STARTING_FEN,
+    });
+    for recorded_move in record.moves.iter().take(plies) {
+        hasher.update(format!(
+            "\n{} {}",
+            recorded_move.chess_move,
+            recorded_move.timestamp.unwrap_or_default()
+        ));
+    }
+    hex(&hasher.finalize())
+}
+
+/// The `Checksum` tag: the whole history, the players and the result.
+fn seal(record: &GameRecord) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(history_digest(record, record.moves.len()));
+    for tag in ["White", "Black", "Result"] {
+        hasher.update("\n");
+        hasher.update(record.tag(tag).unwrap_or_default());
+    }
+    hex(&hasher.finalize())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn new_game_id() -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{:?} {:?}", SystemTime::now(), Instant::now()));
+    hasher.update(std::process::id().to_le_bytes());
+    hex(&hasher.finalize()[..8])
+}
+
+/// `~/.chess/correspondence`, where a player's journal is kept unless told
+/// otherwise. A journal has one line per game: its id, how many moves were
+/// seen and their `history_digest`.
+pub fn default_journal_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    Some(Path::new(&home).join(".chess").join("correspondence"))
+}
+
+fn journal_entry(journal: &Path, id: &str) -> Option<(usize, String)> {
+    let journal = fs::read_to_string(journal).ok()?;
+    journal.lines().find_map(|line| {
+        let mut words = line.split_whitespace();
+        if words.next()? != id {
+            return None;
+        }
+        Some((words.next()?.parse().ok()?, words.next()?.to_owned()))
+    })
+}
+
+/// Notes the history of `record` as seen. A journal that cannot be written
+/// only means less protection, so failures are ignored.
+fn remember(path: &Path, record: &GameRecord) {
+    let Some(id) = record.tag(ID_TAG) else {
+        return;
+    };
+    let plies = record.moves.len();
+    if journal_entry(path, id).is_some_and(|(seen, _)| seen >= plies) {
+        return;
+    }
+    let mut lines: Vec<String> = fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| line.split_whitespace().next() != Some(id))
+        .map(str::to_owned)
+        .collect();
+    lines.push(format!(
+        "{} {} {}",
+        id,
+        plies,
+        history_digest(record, plies)
+    ));
+    if let Some(directory) = path.parent() {
+        let _ = fs::create_dir_all(directory);
+    }
+    let _ = fs::write(path, lines.join("\n") + "\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::PieceKind;
+
+    fn chess_move(name: &str) -> Move {
+        Move::parse(name).unwrap()
+    }
+
+    /// A file of its own in the temporary directory, removed first if a
+    /// previous run left it.
+    fn temporary(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "chess-correspondence-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    /// A new game file with a journal of its own.
+    fn new_game(name: &str, white: &str, black: &str) -> CorrespondenceGame {
+        let path = temporary(&format!("{}.pgn", name));
+        let journal = temporary(&format!("{}.journal", name));
+        CorrespondenceGame::create(&path, Some(&journal), white, black).unwrap()
+    }
+
+    fn reopen(game: &CorrespondenceGame) -> Result<CorrespondenceGame, CorrespondenceError> {
+        CorrespondenceGame::open(game.path(), game.journal())
+    }
+
+    fn remove(game: &CorrespondenceGame) {
+        let _ = fs::remove_file(game.path());
+        let _ = fs::remove_file(game.journal().unwrap());
+    }
+
+    #[test]
+    fn only_the_signed_player_moves_a_side() {
+        let mut game = new_game("signed", "alice", "?");
+        let result = game.play(chess_move("e2e4"), "mallory", 1000);
+        assert!(matches!(&result, Err(CorrespondenceError::NotYourSide(name)) if name == "alice"));
+        assert!(game.game().record().moves.is_empty());
+        game.play(chess_move("e2e4"), "alice", 1000).unwrap();
+
+        // Black is open until its first move signs it
+        game.play(chess_move("e7e5"), "bob", 1001).unwrap();
+        assert_eq!(game.game().record().tag("Black"), Some("bob"));
+        let mut game = reopen(&game).unwrap();
+        game.play(chess_move("g1f3"), "alice", 1002).unwrap();
+        let result = game.play(chess_move("b8c6"), "carol", 1003);
+        assert!(matches!(&result, Err(CorrespondenceError::NotYourSide(name)) if name == "bob"));
+        let result = game.resign(Color::Dark, "carol");
+        assert!(matches!(&result, Err(CorrespondenceError::NotYourSide(name)) if name == "bob"));
+        assert!(!game.game().is_over());
+        remove(&game);
+    }
+
+    #[test]
+    fn timestamps_do_not_go_back() {
+        let mut game = new_game("timestamps", "alice", "bob");
+        game.play(chess_move("e2e4"), "alice", 1000).unwrap();
+        let result = game.play(chess_move("e7e5"), "bob", 999);
+        assert!(matches!(
+            result,
+            Err(CorrespondenceError::TimestampBefore(1000))
+        ));
+        assert_eq!(game.game().record().moves.len(), 1);
+        // The same second is fine
+        game.play(chess_move("e7e5"), "bob", 1000).unwrap();
+        let game = reopen(&game).unwrap();
+        assert_eq!(game.game().record().moves.len(), 2);
+        remove(&game);
+    }
+
+    fn play_all(game: &mut CorrespondenceGame, moves: &str) {
+        let names = [
+            game.game().record().tag("White"),
+            game.game().record().tag("Black"),
+        ]
+        .map(|name| name.unwrap().to_owned());
+        for (ply, name) in moves.split_whitespace().enumerate() {
+            let timestamp = 1000 + ply as u64;
+            game.play(chess_move(name), &names[ply % 2], timestamp)
+                .unwrap();
+        }
+    }
+
+    /// Rewrites the file of `game` as `edit` has it, sealed again as anyone
+    /// could.
+    fn forge(game: &CorrespondenceGame, edit: impl FnOnce(&mut GameRecord)) {
+        let mut record = GameRecord::from_pgn(&fs::read_to_string(game.path()).unwrap()).unwrap();
+        edit(&mut record);
+        let checksum = seal(&record);
+        record.set_tag(CHECKSUM_TAG, checksum);
+        fs::write(game.path(), record.to_pgn()).unwrap();
+    }
+
+    fn is_tampered(result: Result<CorrespondenceGame, CorrespondenceError>, reason: &str) -> bool {
+        matches!(result, Err(CorrespondenceError::Tampered(message)) if message.contains(reason))
+    }
+
+    #[test]
+    fn edited_files_are_refused() {
+        let mut game = new_game("edited", "alice", "bob");
+        play_all(&mut game, "e2e4 e7e5");
+        let pgn = fs::read_to_string(game.path()).unwrap();
+        let edit = |from: &str, to: &str| {
+            assert!(pgn.contains(from));
+            fs::write(game.path(), pgn.replace(from, to)).unwrap();
+            reopen(&game)
+        };
+
+        // A move, the result or the checksum changed by hand
+        assert!(is_tampered(edit("1. e4", "1. d4"), "checksum"));
+        assert!(is_tampered(edit("*", "1-0"), "checksum"));
+        let checksum = game.checksum.clone();
+        assert!(is_tampered(edit(&checksum, &"0".repeat(64)), "checksum"));
+
+        // A result sealed again still has to follow from the moves
+        fs::write(game.path(), &pgn).unwrap();
+        forge(&game, |record| {
+            record.set_outcome(Some(Outcome {
+                winner: Some(Color::Light),
+                termination: Termination::Checkmate,
+            }))
+        });
+        assert!(is_tampered(reopen(&game), "result"));
+        fs::write(game.path(), &pgn).unwrap();
+        assert!(reopen(&game).is_ok());
+        remove(&game);
+    }
+
+    #[test]
+    fn histories_rewritten_against_the_journal_are_refused() {
+        let mut game = new_game("rewritten", "alice", "bob");
+        play_all(&mut game, "e2e4 e7e5 g1f3");
+        let pgn = fs::read_to_string(game.path()).unwrap();
+
+        // Sealed again, so only the journal tells
+        forge(&game, |record| record.moves[0].timestamp = Some(999));
+        assert!(is_tampered(reopen(&game), "seen before"));
+        forge(&game, |record| {
+            record.moves.truncate(2);
+            record.set_outcome(None);
+        });
+        assert!(is_tampered(reopen(&game), "seen before"));
+
+        // Without a journal the checks that remain pass
+        assert!(CorrespondenceGame::open(game.path(), None).is_ok());
+        fs::write(game.path(), &pgn).unwrap();
+        assert!(reopen(&game).is_ok());
+        remove(&game);
+    }
+
+    /// The FEN of `board` without the move counters.
+    fn position(board: &Board) -> String {
+        let fen = board.to_fen();
+        fen.split_whitespace().take(4).collect::<Vec<_>>().join(" ")
+    }
+
+    /// Fills `moves` up to a hundred plies without a pawn move, a capture or
+    /// a position seen before, going back where that runs into a dead end.
+    fn quiet_walk(board: Board, seen: &mut Vec<String>, moves: &mut Vec<Move>) -> bool {
+        if moves.len() == 100 {
+            return true;
+        }
+        seen.push(position(&board));
+        for chess_move in board.legal_moves() {
+            let is_quiet = board.piece_at_square(chess_move.to).is_none()
+                && board
+                    .piece_at_square(chess_move.from)
+                    .is_some_and(|piece| piece.kind != PieceKind::Pawn);
+            let mut next = board;
+            next.make_move_unchecked(chess_move);
+            if is_quiet && !seen.contains(&position(&next)) {
+                moves.push(chess_move);
+                if quiet_walk(next, seen, moves) {
+                    return true;
+                }
+                moves.pop();
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn every_result_is_read_back() {
+        let check = |game: &CorrespondenceGame, termination: Termination, result: &str| {
+            let reopened = reopen(game).unwrap();
+            let outcome = reopened.game().outcome().unwrap();
+            assert_eq!(outcome.termination, termination);
+            assert_eq!(outcome.result(), result);
+            remove(game);
+        };
+
+        let mut game = new_game("checkmate", "alice", "bob");
+        play_all(&mut game, "f2f3 e7e5 g2g4 d8h4");
+        check(&game, Termination::Checkmate, "0-1");
+
+        let mut game = new_game("stalemate", "alice", "bob");
+        play_all(
+            &mut game,
+            "e2e3 a7a5 d1h5 a8a6 h5a5 h7h5 h2h4 a6h6 a5c7 f7f6 c7d7 e8f7 d7b7 d8d3 \
+             b7b8 d3h7 b8c8 f7g6 c8e6",
+        );
+        check(&game, Termination::Stalemate, "1/2-1/2");
+
+        let mut game = new_game("resignation", "alice", "bob");
+        play_all(&mut game, "e2e4");
+        game.resign(Color::Dark, "bob").unwrap();
+        check(&game, Termination::Resignation, "1-0");
+
+        // The rest is written here as a front-end would
+        let now = Instant::now();
+        let mut game = new_game("agreement", "alice", "bob");
+        play_all(&mut game, "e2e4");
+        game.game.offer_draw(Color::Dark, now).unwrap();
+        game.game.accept_draw(Color::Light, now).unwrap();
+        game.save(false).unwrap();
+        check(&game, Termination::Agreement, "1/2-1/2");
+
+        let mut game = new_game("repetition", "alice", "bob");
+        play_all(&mut game, "g1f3 g8f6 f3g1 f6g8 g1f3 g8f6 f3g1 f6g8");
+        game.game.claim_draw(now).unwrap();
+        game.save(false).unwrap();
+        check(&game, Termination::Repetition, "1/2-1/2");
+
+        // Fifty moves each that never repeat a position
+        let mut game = new_game("fifty-moves", "alice", "bob");
+        let mut moves = Vec::new();
+        assert!(quiet_walk(
+            *game.game().board(),
+            &mut Vec::new(),
+            &mut moves
+        ));
+        for (ply, chess_move) in moves.into_iter().enumerate() {
+            game.game.play(chess_move, now).unwrap();
+            game.game.record_mut().moves.last_mut().unwrap().timestamp = Some(1000 + ply as u64);
+        }
+        game.game.claim_draw(now).unwrap();
+        game.save(false).unwrap();
+        check(&game, Termination::FiftyMoves, "1/2-1/2");
+
+        // Ends the rules do not allow are not taken on trust
+        let mut game = new_game("forfeit", "alice", "bob");
+        play_all(&mut game, "e2e4");
+        for termination in [Termination::TimeForfeit, Termination::Repetition] {
+            forge(&game, |record| {
+                record.set_outcome(Some(Outcome {
+                    winner: None,
+                    termination,
+                }))
+            });
+            assert!(is_tampered(reopen(&game), "result"));
+        }
+        remove(&game);
+    }
+}
@@ -0,0 +1,245 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::Duration;
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, RgbaImage};
+
+use super::{load_font, raster, Diagram, ExportError, Highlight, PieceImages, FONT_ASSET, YELLOW};
+use crate::backend::pgn::san;
+use crate::backend::{Board, Color, Move};
+use crate::ui::assets::Assets;
+use crate::ui::theme::Theme;
+
+/// A game replayed move by move as an animated GIF: one frame for the
+/// starting position and one after every move.
+pub struct GameAnimation<'a> {
+    pub start: &'a Board,
+    pub moves: &'a [Move],
+    pub frame_delay: Duration,
+    /// How long the final position stays before the animation loops.
+    pub last_frame_delay: Duration,
+    /// Dark pieces at the bottom.
+    pub flipped: bool,
+    pub show_coordinates: bool,
+    /// Colour of the from and to squares of the move just played, if any.
+    pub last_move_highlight: Option<[u8; 4]>,
+    /// Text on a strip under the board, next to the move just played.
+    pub caption: Option<String>,
+    /// Side of the board in pixels, rounded down to a multiple of 8.
+    pub size: u32,
+}
+
+impl<'a> GameAnimation<'a> {
+    pub fn new(start: &'a Board, moves: &'a [Move]) -> GameAnimation<'a> {
+        GameAnimation {
+            start,
+            moves,
+            frame_delay: Duration::from_millis(800),
+            last_frame_delay: Duration::from_millis(3000),
+            flipped: false,
+            show_coordinates: true,
+            last_move_highlight: Some(YELLOW),
+            caption: None,
+            size: 360,
+        }
+    }
+
+    /// Plays the moves and rasterises every position, each captioned with its
+    /// move in SAN as in the PGN. Fails on the first move that is not legal.
+    pub fn frames(&self, assets: &Assets, theme: &Theme) -> Result<Vec<Frame>, ExportError> {
+        let font_data = assets.read_bytes(FONT_ASSET)?;
+        let font = load_font(&font_data)?;
+        let start_diagram = self.diagram(self.start);
+        let piece_images: PieceImages =
+            theme.load_piece_images(assets, start_diagram.cell_size())?;
+
+        let mut board = *self.start;
+        let mut frames = Vec::with_capacity(self.moves.len() + 1);
+        let board_image = start_diagram.draw(theme, &font, &piece_images);
+        frames.push(self.frame(board_image, None, theme, &font, self.moves.is_empty()));
+        for (index, chess_move) in self.moves.iter().enumerate() {
+            let move_label = format!(
+                "{}{} {}",
+                self.move_number(index),
+                if board.turn == Color::Light {
+                    "."
+                } else {
+                    "..."
+                },
+                san(&board, *chess_move)
+            );
+            board
+                .try_make_move(*chess_move)
+                .map_err(|source| ExportError::IllegalMove {
+                    index,
+                    chess_move: *chess_move,
+                    source,
+                })?;
+            let mut diagram = self.diagram(&board);
+            if let Some(color) = self.last_move_highlight {
+                for square in [chess_move.from, chess_move.to] {
+                    diagram.highlights.push(Highlight { square, color });
+                }
+            }
+            let board_image = diagram.draw(theme, &font, &piece_images);
+            let is_last = index + 1 == self.moves.len();
+            frames.push(self.frame(board_image, Some(&move_label), theme, &font, is_last));
+        }
+        Ok(frames)
+    }
+
+    pub fn save(&self, assets: &Assets, theme: &Theme, path: &Path) -> Result<(), ExportError> {
+        let frames = self.frames(assets, theme)?;
+        let file = File::create(path).map_err(|source| ExportError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+        let image_error = |source| ExportError::Image {
+            path: path.to_owned(),
+            source,
+        };
+        let mut encoder = GifEncoder::new(BufWriter::new(file));
+        encoder.set_repeat(Repeat::Infinite).map_err(image_error)?;
+        encoder.encode_frames(frames).map_err(image_error)
+    }
+
+    fn diagram<'b>(&self, board: &'b Board) -> Diagram<'b> {
+        let mut diagram = Diagram::new(board);
+        diagram.flipped = self.flipped;
+        diagram.show_coordinates = self.show_coordinates;
+        diagram.size = self.size;
+        diagram
+    }
+
+    /// Full move number of the `index`-th move, counting from the start position.
+    fn move_number(&self, index: usize) -> usize {
+        let first_ply = match self.start.turn {
+            Color::Light => 0,
+            Color::Dark => 1,
+        };
+        (index + first_ply) / 2 + 1
+    }
+
+    /// Adds the caption strip, if any, and the frame delay.
+    fn frame(
+        &self,
+        board_image: RgbaImage,
+        move_label: Option<&str>,
+        theme: &Theme,
+        font: &ab_glyph::FontRef,
+        is_last: bool,
+    ) -> Frame {
+        let delay = if is_last {
+            self.last_frame_delay
+        } else {
+            self.frame_delay
+        };
+        let delay = Delay::from_saturating_duration(delay);
+        let caption = match &self.caption {
+            Some(caption) => caption,
+            None => return Frame::from_parts(board_image, 0, 0, delay),
+        };
+
+        let width = board_image.width();
+        let strip_height = (width / 16).max(12);
+        let mut image = RgbaImage::new(width, board_image.height() + strip_height);
+        image::imageops::replace(&mut image, &board_image, 0, 0);
+        raster::fill_rect(
+            &mut image,
+            0,
+            board_image.height() as i64,
+            width,
+            strip_height,
+            raster::to_rgba8(theme.border),
+        );
+
+        let text_height = strip_height as f32 * 0.6;
+        let padding = strip_height as f32 * 0.2;
+        let top = board_image.height() as f32 + (strip_height as f32 - text_height) / 2.0;
+        let text_color = raster::to_rgba8(theme.light_square);
+        raster::draw_text(
+            &mut image,
+            font,
+            caption,
+            (padding, top),
+            text_height,
+            text_color,
+        );
+        if let Some(move_label) = move_label {
+            let left = width as f32 - padding - raster::text_width(font, move_label, text_height);
+            raster::draw_text(
+                &mut image,
+                font,
+                move_label,
+                (left, top),
+                text_height,
+                text_color,
+            );
+        }
+        Frame::from_parts(image, 0, 0, delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use image::codecs::gif::GifDecoder;
+    use image::AnimationDecoder;
+
+    use super::*;
+    use crate::ui::theme::DEFAULT_THEME;
+
+    #[test]
+    fn each_position_is_a_captioned_frame() {
+        let assets = Assets::default();
+        let theme = Theme::load(&assets, DEFAULT_THEME).unwrap();
+        let start = Board::new(Color::Light);
+        let moves: Vec<Move> = ["e2e4", "e7e5", "g1f3"]
+            .iter()
+            .map(|name| Move::parse(name).unwrap())
+            .collect();
+        let mut animation = GameAnimation::new(&start, &moves);
+        animation.size = 240;
+        animation.caption = Some("Test".to_owned());
+        let path = std::env::temp_dir().join(format!("chess-game-{}.gif", std::process::id()));
+        animation.save(&assets, &theme, &path).unwrap();
+        let decoder = GifDecoder::new(fs::File::open(&path).unwrap()).unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(frames.len(), moves.len() + 1);
+
+        // Text shows as pixels off the strip colour: the caption on the left
+        // of every frame, the move on the right of all but the first
+        let strip_color = raster::to_rgba8(theme.border);
+        let has_text = |image: &RgbaImage, columns: std::ops::Range<u32>| {
+            (240..image.height()).any(|y| {
+                columns.clone().any(|x| {
+                    let pixel = image.get_pixel(x, y).0;
+                    (0..3).any(|channel| pixel[channel].abs_diff(strip_color[channel]) > 48)
+                })
+            })
+        };
+        for (index, frame) in frames.iter().enumerate() {
+            let image = frame.buffer();
+            assert_eq!(image.dimensions(), (240, 240 + 15));
+            assert!(has_text(image, 0..60), "frame {}", index);
+            assert_eq!(has_text(image, 180..240), index > 0, "frame {}", index);
+        }
+        // Every move has a label of its own
+        let labels: Vec<Vec<u8>> = frames
+            .iter()
+            .map(|frame| {
+                let image = frame.buffer();
+                image::imageops::crop_imm(image, 180, 240, 60, 15)
+                    .to_image()
+                    .into_raw()
+            })
+            .collect();
+        for (index, label) in labels.iter().enumerate() {
+            assert!(!labels[..index].contains(label), "frame {}", index);
+        }
+    }
+}
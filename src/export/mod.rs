@@ -0,0 +1,429 @@
+//! Board diagrams rendered on the CPU, without OpenGL: PNG (or any format
+//! the `image` crate writes) and SVG.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ab_glyph::FontRef;
+use image::RgbaImage;
+
+use crate::backend::{
+    AnnotationColor, Annotations, Board, Color, Move, MoveError, PieceKind, Square,
+};
+use crate::ui::assets::{AssetError, Assets};
+use crate::ui::theme::Theme;
+
+mod gif;
+pub(crate) mod raster;
+mod svg;
+
+pub use gif::GameAnimation;
+
+pub const FONT_ASSET: &str = "fonts/Roboto-Regular.ttf";
+
+pub const GREEN: [u8; 4] = [21, 120, 27, 170];
+pub const RED: [u8; 4] = [136, 32, 32, 170];
+pub const BLUE: [u8; 4] = [0, 48, 136, 170];
+pub const YELLOW: [u8; 4] = [230, 143, 0, 170];
+
+/// RGBA of an annotation colour, shared with the on-screen annotations.
+pub fn annotation_rgba(color: AnnotationColor) -> [u8; 4] {
+    match color {
+        AnnotationColor::Green => GREEN,
+        AnnotationColor::Red => RED,
+        AnnotationColor::Blue => BLUE,
+        AnnotationColor::Yellow => YELLOW,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Arrow {
+    pub from: Square,
+    pub to: Square,
+    pub color: [u8; 4],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Highlight {
+    pub square: Square,
+    pub color: [u8; 4],
+}
+
+/// A position plus annotations, ready to be written as an image.
+pub struct Diagram<'a> {
+    pub board: &'a Board,
+    pub arrows: Vec<Arrow>,
+    pub highlights: Vec<Highlight>,
+    pub show_coordinates: bool,
+    /// Dark pieces at the bottom.
+    pub flipped: bool,
+    /// Side of the board in pixels, rounded down to a multiple of 8.
+    pub size: u32,
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Asset(AssetError),
+    Font(String),
+    /// Move `index` (from zero) of an animated game cannot be played.
+    IllegalMove {
+        index: usize,
+        chess_move: Move,
+        source: MoveError,
+    },
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Image {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Asset(error) => error.fmt(f),
+            ExportError::Font(message) => write!(f, "failed to load font: {}", message),
+            ExportError::IllegalMove {
+                index,
+                chess_move,
+                source,
+            } => write!(
+                f,
+                "move {} ({}) is not legal: {}",
+                index + 1,
+                chess_move,
+                source
+            ),
+            ExportError::Io { path, source } => {
+                write!(f, "failed to write `{}`: {}", path.display(), source)
+            }
+            ExportError::Image { path, source } => {
+                write!(f, "failed to write `{}`: {}", path.display(), source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExportError::Asset(error) => Some(error),
+            ExportError::Io { source, .. } => Some(source),
+            ExportError::Image { source, .. } => Some(source),
+            ExportError::IllegalMove { source, .. } => Some(source),
+            ExportError::Font(_) => None,
+        }
+    }
+}
+
+impl From<AssetError> for ExportError {
+    fn from(error: AssetError) -> Self {
+        ExportError::Asset(error)
+    }
+}
+
+impl<'a> Diagram<'a> {
+    pub fn new(board: &'a Board) -> Diagram<'a> {
+        Diagram {
+            board,
+            arrows: Vec::new(),
+            highlights: Vec::new(),
+            show_coordinates: true,
+            flipped: false,
+            size: 480,
+        }
+    }
+
+    /// Adds the arrows and marked squares of `annotations`.
+    pub fn annotate(&mut self, annotations: &Annotations) {
+        for (chess_move, color) in &annotations.arrows {
+            self.arrows.push(Arrow {
+                from: chess_move.from,
+                to: chess_move.to,
+                color: annotation_rgba(*color),
+            });
+        }
+        for (square, color) in &annotations.marks {
+            self.highlights.push(Highlight {
+                square: *square,
+                color: annotation_rgba(*color),
+            });
+        }
+    }
+
+    pub fn cell_size(&self) -> u32 {
+        (self.size / 8).max(1)
+    }
+
+    /// Column and row (from the top) a square is drawn at.
+    pub fn square_position(&self, square: Square) -> (u32, u32) {
+        if self.flipped {
+            (7 - square.file as u32, square.rank as u32)
+        } else {
+            (square.file as u32, 7 - square.rank as u32)
+        }
+    }
+
+    pub fn to_image(&self, assets: &Assets, theme: &Theme) -> Result<RgbaImage, ExportError> {
+        let font_data = assets.read_bytes(FONT_ASSET)?;
+        let font = load_font(&font_data)?;
+        let piece_images = theme.load_piece_images(assets, self.cell_size())?;
+        Ok(self.draw(theme, &font, &piece_images))
+    }
+
+    /// Rasterises with already loaded font and piece images, so animations
+    /// load them once for all frames.
+    fn draw(&self, theme: &Theme, font: &FontRef, piece_images: &PieceImages) -> RgbaImage {
+        let cell = self.cell_size();
+        let mut image = RgbaImage::new(cell * 8, cell * 8);
+        self.draw_squares(&mut image, theme, font);
+
+        for square in all_squares() {
+            if let Some(piece) = self.board.piece_at_square(square) {
+                if let Some(piece_image) = piece_images.get(&(piece.kind, piece.color)) {
+                    let piece_image = if piece_image.dimensions() == (cell, cell) {
+                        piece_image.clone()
+                    } else {
+                        image::imageops::resize(
+                            piece_image,
+                            cell,
+                            cell,
+                            image::imageops::FilterType::Lanczos3,
+                        )
+                    };
+                    let (column, row) = self.square_position(square);
+                    image::imageops::overlay(&mut image, &piece_image, column * cell, row * cell);
+                }
+            }
+        }
+
+        for arrow in &self.arrows {
+            let points = self.arrow_outline(arrow);
+            raster::fill_polygon(&mut image, &points, arrow.color);
+        }
+        image
+    }
+
+    pub fn to_svg(&self, assets: &Assets, theme: &Theme) -> Result<String, ExportError> {
+        Ok(svg::render(self, assets, theme)?)
+    }
+
+    /// Writes SVG for `.svg` paths and a raster image otherwise, in the format
+    /// the extension names.
+    pub fn save(&self, assets: &Assets, theme: &Theme, path: &Path) -> Result<(), ExportError> {
+        let is_svg = path
+            .extension()
+            .map(|extension| extension.eq_ignore_ascii_case("svg"))
+            .unwrap_or(false);
+        if is_svg {
+            fs::write(path, self.to_svg(assets, theme)?).map_err(|source| ExportError::Io {
+                path: path.to_owned(),
+                source,
+            })
+        } else {
+            self.to_image(assets, theme)?
+                .save(path)
+                .map_err(|source| ExportError::Image {
+                    path: path.to_owned(),
+                    source,
+                })
+        }
+    }
+
+    /// Squares, highlights and coordinates: everything under the pieces.
+    fn draw_squares(&self, image: &mut RgbaImage, theme: &Theme, font: &FontRef) {
+        let cell = self.cell_size();
+        for square in all_squares() {
+            let (column, row) = self.square_position(square);
+            let (left, top) = ((column * cell) as i64, (row * cell) as i64);
+            raster::fill_rect(image, left, top, cell, cell, square_color(theme, square));
+            for highlight in self.highlights.iter().filter(|h| h.square == square) {
+                raster::fill_rect(image, left, top, cell, cell, highlight.color);
+            }
+        }
+
+        if !self.show_coordinates {
+            return;
+        }
+        let text_height = cell as f32 * 0.22;
+        let padding = cell as f32 * 0.06;
+        for label in self.coordinate_labels() {
+            let (column, row) = self.square_position(label.square);
+            let (left, top) = ((column * cell) as f32, (row * cell) as f32);
+            let position = match label.corner {
+                Corner::TopLeft => (left + padding, top + padding),
+                Corner::BottomRight => (
+                    left + cell as f32
+                        - padding
+                        - raster::text_width(font, &label.text, text_height),
+                    top + cell as f32 - padding - text_height,
+                ),
+            };
+            // Drawn in the other square colour, so it reads on both.
+            let color = square_color(theme, flip_color(label.square));
+            raster::draw_text(image, font, &label.text, position, text_height, color);
+        }
+    }
+
+    fn coordinate_labels(&self) -> Vec<CoordinateLabel> {
+        let bottom_rank = if self.flipped { 7 } else { 0 };
+        let left_file = if self.flipped { 7 } else { 0 };
+        let mut labels = Vec::with_capacity(16);
+        for index in 0..8 {
+            let file_square = Square {
+                file: index,
+                rank: bottom_rank,
+            };
+            labels.push(CoordinateLabel {
+                square: file_square,
+                text: file_square.file_char().to_string(),
+                corner: Corner::BottomRight,
+            });
+            let rank_square = Square {
+                file: left_file,
+                rank: index,
+            };
+            labels.push(CoordinateLabel {
+                square: rank_square,
+                text: rank_square.rank_char().to_string(),
+                corner: Corner::TopLeft,
+            });
+        }
+        labels
+    }
+
+    /// Arrow from square centre to square centre, in pixels.
+    fn arrow_outline(&self, arrow: &Arrow) -> Vec<(f32, f32)> {
+        let cell = self.cell_size() as f32;
+        let centre = |square: Square| {
+            let (column, row) = self.square_position(square);
+            ((column as f32 + 0.5) * cell, (row as f32 + 0.5) * cell)
+        };
+        raster::arrow_polygon(
+            centre(arrow.from),
+            centre(arrow.to),
+            cell * 0.15,
+            cell * 0.4,
+            cell * 0.4,
+        )
+    }
+}
+
+enum Corner {
+    TopLeft,
+    BottomRight,
+}
+
+struct CoordinateLabel {
+    square: Square,
+    text: String,
+    corner: Corner,
+}
+
+type PieceImages = HashMap<(PieceKind, Color), RgbaImage>;
+
+fn load_font(data: &[u8]) -> Result<FontRef<'_>, ExportError> {
+    FontRef::try_from_slice(data).map_err(|error| ExportError::Font(error.to_string()))
+}
+
+fn all_squares() -> impl Iterator<Item = Square> {
+    (0..8).flat_map(|rank| (0..8).map(move |file| Square { file, rank }))
+}
+
+fn is_light_square(square: Square) -> bool {
+    (square.file + square.rank) % 2 == 1
+}
+
+fn square_color(theme: &Theme, square: Square) -> [u8; 4] {
+    if is_light_square(square) {
+        raster::to_rgba8(theme.light_square)
+    } else {
+        raster::to_rgba8(theme.dark_square)
+    }
+}
+
+/// Any square of the opposite colour.
+fn flip_color(square: Square) -> Square {
+    Square {
+        file: square.file ^ 1,
+        rank: square.rank,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::theme::DEFAULT_THEME;
+
+    fn square(name: &str) -> Square {
+        Square::parse(name).unwrap()
+    }
+
+    /// The starting position with a red arrow e2-e4 and d4 marked green.
+    fn annotated_diagram(board: &Board) -> Diagram<'_> {
+        let mut annotations = Annotations::default();
+        annotations.toggle_arrow(Move::parse("e2e4").unwrap(), AnnotationColor::Red);
+        annotations.toggle_mark(square("d4"), AnnotationColor::Green);
+        let mut diagram = Diagram::new(board);
+        diagram.annotate(&annotations);
+        diagram
+    }
+
+    #[test]
+    fn svg_has_the_pieces_arrows_and_highlights_asked_for() {
+        let assets = Assets::default();
+        let theme = Theme::load(&assets, DEFAULT_THEME).unwrap();
+        let board = Board::new(Color::Light);
+        let mut diagram = annotated_diagram(&board);
+        diagram.size = 400;
+        let svg = diagram.to_svg(&assets, &theme).unwrap();
+
+        // Each kind of piece embedded once, used on every square it stands on
+        assert_eq!(svg.matches("<image id=").count(), 12);
+        assert_eq!(svg.matches("<use href=").count(), 32);
+        assert!(svg.contains(r##"<use href="#light-king" x="200" y="350"/>"##));
+        assert!(svg.contains(r##"<use href="#dark-queen" x="150" y="0"/>"##));
+        assert!(svg.contains(
+            r#"<rect x="150" y="200" width="50" height="50" fill="rgba(21,120,27,0.667)"/>"#
+        ));
+        assert_eq!(svg.matches("<polygon ").count(), 1);
+        assert!(svg.contains(r#"fill="rgba(136,32,32,0.667)"/>"#));
+        assert_eq!(svg.matches("<text ").count(), 16);
+
+        diagram.flipped = true;
+        diagram.show_coordinates = false;
+        let svg = diagram.to_svg(&assets, &theme).unwrap();
+        assert!(svg.contains(r##"<use href="#light-king" x="150" y="0"/>"##));
+        assert!(svg.contains(r#"<rect x="200" y="150" width="50" height="50""#));
+        assert_eq!(svg.matches("<text ").count(), 0);
+    }
+
+    #[test]
+    fn png_has_the_size_asked_for() {
+        let assets = Assets::default();
+        let theme = Theme::load(&assets, DEFAULT_THEME).unwrap();
+        let board = Board::new(Color::Light);
+        let mut diagram = annotated_diagram(&board);
+        // Rounded down to whole squares
+        diagram.size = 100;
+        let path = std::env::temp_dir().join(format!("chess-diagram-{}.png", std::process::id()));
+        diagram.save(&assets, &theme, &path).unwrap();
+        let image = image::open(&path).unwrap().to_rgba8();
+        let _ = fs::remove_file(&path);
+        assert_eq!(image.dimensions(), (96, 96));
+
+        // d4 is marked, f4 of the same colour is not
+        let centre = |name| {
+            let (column, row) = diagram.square_position(square(name));
+            *image.get_pixel(column * 12 + 6, row * 12 + 6)
+        };
+        assert_ne!(centre("d4"), centre("f4"));
+        assert_eq!(centre("b4"), centre("f4"));
+    }
+}
@@ -0,0 +1,161 @@
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use image::RgbaImage;
+
+/// Source-over blend of `color`, scaled by `coverage` in [0, 1].
+pub fn blend_pixel(image: &mut RgbaImage, x: i64, y: i64, color: [u8; 4], coverage: f32) {
+    if x < 0 || y < 0 || x >= image.width() as i64 || y >= image.height() as i64 {
+        return;
+    }
+    let alpha = color[3] as f32 / 255.0 * coverage.clamp(0.0, 1.0);
+    if alpha <= 0.0 {
+        return;
+    }
+    let pixel = image.get_pixel_mut(x as u32, y as u32);
+    let destination_alpha = pixel[3] as f32 / 255.0;
+    let out_alpha = alpha + destination_alpha * (1.0 - alpha);
+    for channel in 0..3 {
+        let blended = (color[channel] as f32 * alpha
+            + pixel[channel] as f32 * destination_alpha * (1.0 - alpha))
+            / out_alpha;
+        pixel[channel] = blended.round() as u8;
+    }
+    pixel[3] = (out_alpha * 255.0).round() as u8;
+}
+
+pub fn fill_rect(
+    image: &mut RgbaImage,
+    left: i64,
+    top: i64,
+    width: u32,
+    height: u32,
+    color: [u8; 4],
+) {
+    for y in top..top + height as i64 {
+        for x in left..left + width as i64 {
+            blend_pixel(image, x, y, color, 1.0);
+        }
+    }
+}
+
+/// Fills a polygon, anti-aliased with 4x4 supersampling. Points are pixel
+/// coordinates, pixel centres at +0.5.
+pub fn fill_polygon(image: &mut RgbaImage, points: &[(f32, f32)], color: [u8; 4]) {
+    if points.len() < 3 {
+        return;
+    }
+    let min_x = points.iter().map(|p| p.0).fold(f32::MAX, f32::min).floor() as i64;
+    let max_x = points.iter().map(|p| p.0).fold(f32::MIN, f32::max).ceil() as i64;
+    let min_y = points.iter().map(|p| p.1).fold(f32::MAX, f32::min).floor() as i64;
+    let max_y = points.iter().map(|p| p.1).fold(f32::MIN, f32::max).ceil() as i64;
+
+    const SAMPLES: usize = 4;
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            let mut inside = 0;
+            for sample_y in 0..SAMPLES {
+                for sample_x in 0..SAMPLES {
+                    let sample = (
+                        x as f32 + (sample_x as f32 + 0.5) / SAMPLES as f32,
+                        y as f32 + (sample_y as f32 + 0.5) / SAMPLES as f32,
+                    );
+                    if is_inside_polygon(points, sample) {
+                        inside += 1;
+                    }
+                }
+            }
+            if inside > 0 {
+                let coverage = inside as f32 / (SAMPLES * SAMPLES) as f32;
+                blend_pixel(image, x, y, color, coverage);
+            }
+        }
+    }
+}
+
+/// Outline of an arrow from `from` to `to` (tip), in pixels.
+pub fn arrow_polygon(
+    from: (f32, f32),
+    to: (f32, f32),
+    shaft_width: f32,
+    head_width: f32,
+    head_length: f32,
+) -> Vec<(f32, f32)> {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length = (dx * dx + dy * dy).sqrt().max(f32::EPSILON);
+    let (ux, uy) = (dx / length, dy / length);
+    let (nx, ny) = (-uy, ux);
+    let head_length = head_length.min(length);
+    let neck = (to.0 - ux * head_length, to.1 - uy * head_length);
+    let at = |base: (f32, f32), offset: f32| (base.0 + nx * offset, base.1 + ny * offset);
+    vec![
+        at(from, shaft_width / 2.0),
+        at(neck, shaft_width / 2.0),
+        at(neck, head_width / 2.0),
+        to,
+        at(neck, -head_width / 2.0),
+        at(neck, -shaft_width / 2.0),
+        at(from, -shaft_width / 2.0),
+    ]
+}
+
+/// Draws `text` with its top-left corner at `position`.
+pub fn draw_text(
+    image: &mut RgbaImage,
+    font: &FontRef,
+    text: &str,
+    position: (f32, f32),
+    pixel_height: f32,
+    color: [u8; 4],
+) {
+    let scale = PxScale::from(pixel_height);
+    let scaled_font = font.as_scaled(scale);
+    let mut caret = point(position.0, position.1 + scaled_font.ascent());
+    for character in text.chars() {
+        let glyph_id = font.glyph_id(character);
+        let glyph = glyph_id.with_scale_and_position(scale, caret);
+        caret.x += scaled_font.h_advance(glyph_id);
+        if let Some(outlined) = font.outline_glyph(glyph) {
+            let bounds = outlined.px_bounds();
+            outlined.draw(|x, y, coverage| {
+                blend_pixel(
+                    image,
+                    bounds.min.x as i64 + x as i64,
+                    bounds.min.y as i64 + y as i64,
+                    color,
+                    coverage,
+                );
+            });
+        }
+    }
+}
+
+pub fn text_width(font: &FontRef, text: &str, pixel_height: f32) -> f32 {
+    let scaled_font = font.as_scaled(PxScale::from(pixel_height));
+    text.chars()
+        .map(|character| scaled_font.h_advance(font.glyph_id(character)))
+        .sum()
+}
+
+pub fn to_rgba8(color: [f32; 3]) -> [u8; 4] {
+    [
+        (color[0] * 255.0).round() as u8,
+        (color[1] * 255.0).round() as u8,
+        (color[2] * 255.0).round() as u8,
+        255,
+    ]
+}
+
+fn is_inside_polygon(points: &[(f32, f32)], sample: (f32, f32)) -> bool {
+    let mut inside = false;
+    let mut previous = points[points.len() - 1];
+    for &current in points {
+        if (current.1 > sample.1) != (previous.1 > sample.1) {
+            let crossing_x = previous.0
+                + (sample.1 - previous.1) / (current.1 - previous.1) * (current.0 - previous.0);
+            if sample.0 < crossing_x {
+                inside = !inside;
+            }
+        }
+        previous = current;
+    }
+    inside
+}
@@ -0,0 +1,153 @@
+use std::fmt::Write;
+
+use super::{all_squares, square_color, Corner, Diagram};
+use crate::ui::assets::{AssetError, Assets};
+use crate::ui::theme::Theme;
+
+pub fn render(diagram: &Diagram, assets: &Assets, theme: &Theme) -> Result<String, AssetError> {
+    let cell = diagram.cell_size();
+    let size = cell * 8;
+    let mut svg = String::new();
+    // Writing into a String cannot fail.
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{size}" viewBox="0 0 {size} {size}">"#,
+        size = size
+    );
+
+    for square in all_squares() {
+        let (column, row) = diagram.square_position(square);
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{cell}" height="{cell}" fill="{}"/>"#,
+            column * cell,
+            row * cell,
+            css_color(square_color(theme, square)),
+            cell = cell
+        );
+    }
+    for highlight in &diagram.highlights {
+        let (column, row) = diagram.square_position(highlight.square);
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{cell}" height="{cell}" fill="{}"/>"#,
+            column * cell,
+            row * cell,
+            css_color(highlight.color),
+            cell = cell
+        );
+    }
+
+    if diagram.show_coordinates {
+        let text_height = cell as f32 * 0.22;
+        let padding = cell as f32 * 0.06;
+        for label in diagram.coordinate_labels() {
+            let (column, row) = diagram.square_position(label.square);
+            let (left, top) = ((column * cell) as f32, (row * cell) as f32);
+            let (x, y, anchor) = match label.corner {
+                Corner::TopLeft => (left + padding, top + padding + text_height * 0.8, "start"),
+                Corner::BottomRight => (
+                    left + cell as f32 - padding,
+                    top + cell as f32 - padding,
+                    "end",
+                ),
+            };
+            let color = square_color(theme, super::flip_color(label.square));
+            let _ = writeln!(
+                svg,
+                r#"<text x="{:.1}" y="{:.1}" font-family="Roboto, sans-serif" font-size="{:.1}" text-anchor="{}" fill="{}">{}</text>"#,
+                x,
+                y,
+                text_height,
+                anchor,
+                css_color(color),
+                label.text
+            );
+        }
+    }
+
+    // Each piece image is embedded once and referenced by every square using it.
+    let mut used_pieces = Vec::new();
+    for square in all_squares() {
+        if let Some(piece) = diagram.board.piece_at_square(square) {
+            if !used_pieces.contains(&(piece.kind, piece.color)) {
+                used_pieces.push((piece.kind, piece.color));
+            }
+        }
+    }
+    let _ = writeln!(svg, "<defs>");
+    for (kind, color) in &used_pieces {
+        let (name, mime_type) = theme.piece_image_asset(*kind, *color);
+        let bytes = assets.read_bytes(&name)?;
+        let _ = writeln!(
+            svg,
+            r#"<image id="{}" width="{cell}" height="{cell}" href="data:{};base64,{}"/>"#,
+            piece_id(*kind, *color),
+            mime_type,
+            base64(&bytes),
+            cell = cell
+        );
+    }
+    let _ = writeln!(svg, "</defs>");
+    for square in all_squares() {
+        if let Some(piece) = diagram.board.piece_at_square(square) {
+            let (column, row) = diagram.square_position(square);
+            let _ = writeln!(
+                svg,
+                r##"<use href="#{}" x="{}" y="{}"/>"##,
+                piece_id(piece.kind, piece.color),
+                column * cell,
+                row * cell
+            );
+        }
+    }
+
+    for arrow in &diagram.arrows {
+        let points: Vec<String> = diagram
+            .arrow_outline(arrow)
+            .iter()
+            .map(|(x, y)| format!("{:.1},{:.1}", x, y))
+            .collect();
+        let _ = writeln!(
+            svg,
+            r#"<polygon points="{}" fill="{}"/>"#,
+            points.join(" "),
+            css_color(arrow.color)
+        );
+    }
+
+    let _ = writeln!(svg, "</svg>");
+    Ok(svg)
+}
+
+fn piece_id(kind: crate::backend::PieceKind, color: crate::backend::Color) -> String {
+    format!("{:?}-{:?}", color, kind).to_lowercase()
+}
+
+fn css_color(color: [u8; 4]) -> String {
+    format!(
+        "rgba({},{},{},{:.3})",
+        color[0],
+        color[1],
+        color[2],
+        color[3] as f32 / 255.0
+    )
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let triple = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(triple >> (18 - index * 6) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
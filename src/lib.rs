@@ -1,255 +1,622 @@
 mod backend;
+pub mod correspondence;
+pub mod export;
+pub mod player;
+pub mod protocol;
+pub mod server;
 mod ui;
 
-use backend::{Board, Color};
+pub use backend::pgn;
+pub use backend::{
+    has_mating_material, is_flag_fall_draw, AnnotationColor, Annotations, Board, CastlingRights,
+    ChessClock, Color, FenError, GameChange, GameController, GameError, GameEvent, Move, MoveError,
+    Outcome, Piece, PieceKind, PositionProblem, Square, Termination, TimeBonus, TimeControl,
+    TimeControlStage,
+};
+pub use ui::assets::{AssetError, Assets};
+pub use ui::offscreen::RenderError;
+pub use ui::theme::Theme;
+
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use backend::pgn::{pgn_date, unix_timestamp};
 use glium::{glutin::event::ElementState, Surface};
-use ui::shaders::primitives::Vertex;
-use ui::shaders::{get_board_shader, get_piece_shader, get_textures};
+use player::{HumanPlayer, Player, PlayerSpec};
+use ui::animation::MoveAnimation;
+use ui::annotations::{annotation_color, draw_annotations};
+use ui::coordinates::draw_coordinates;
+use ui::editor::{EditorAction, PositionEditor};
+use ui::layout::BoardLayout;
+use ui::offscreen::{create_headless_renderer, render_board, save_image};
+use ui::panels::{ClockReadout, SettingsAction, SettingsPanel};
+use ui::renderer::{
+    piece_instances, BoardRenderer, BoardStyle, HighlightLayer, SquareHighlights, PIECE_CELL_SIZE,
+};
+use ui::shaders::primitives::PieceInstance;
+use ui::shaders::PieceAtlas;
+use ui::theme::{load_themes, DEFAULT_THEME};
 use ui::ui_support::{init, AppSettings};
 
-pub fn run() -> () {
+/// Pieces being dragged are drawn slightly enlarged.
+const TAKEN_PIECE_CELL_SIZE: f32 = 0.32;
+
+/// Opens the window with `players` for White and Black.
+pub fn run(players: [PlayerSpec; 2]) -> Result<(), AssetError> {
     let app_settings = AppSettings {
         window_height: 640f64,
-        window_width: 640f64,
+        window_width: 960f64,
         window_name: "Chess".to_owned(),
         max_fps: 60.0,
+        side_panel_width: 160.0,
+        board_margin: 16.0,
     };
 
+    let assets = Assets::from_env();
     let system = init(&app_settings);
     let start = std::time::Instant::now();
-    let empty_texture = glium::texture::SrgbTexture2d::empty(&system.display, 1, 1).unwrap();
-    let pieces_textures = get_textures(&system.display);
-    let board_shader: ui::shaders::Shader = get_board_shader(&system.display);
-    let pawn_shader: ui::shaders::Shader = get_piece_shader(&system.display);
+    let (themes, theme_errors) = load_themes(&assets);
+    let mut theme = Theme::load(&assets, DEFAULT_THEME)?;
+    let mut piece_atlas = PieceAtlas::load(&system.display, &assets, &theme)?;
+    let mut settings_panel = SettingsPanel::new(
+        themes.iter().map(|(_, theme)| theme.name.clone()).collect(),
+        themes
+            .iter()
+            .position(|(name, _)| name == DEFAULT_THEME)
+            .unwrap_or(0),
+    );
+    if !theme_errors.is_empty() {
+        settings_panel.status = Some(
+            theme_errors
+                .iter()
+                .map(|error| format!("Skipped theme: {}", error))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+    }
+    let mut board_renderer = BoardRenderer::new(&system.display, &assets)?;
 
-    let mut board = Board::new(Color::Light);
-    let mut valid_cells_to_move: [[bool; 8]; 8] = [[false; 8]; 8];
-    let mut valid_cell_to_move_encoded: (i32, i32) = (0, 0);
+    settings_panel.set_player_specs(&players);
+    let mut player_specs = [PlayerSpec::Human, PlayerSpec::Human];
+    let mut players: [Box<dyn Player>; 2] = [Box::new(HumanPlayer), Box::new(HumanPlayer)];
+    update_players(
+        &mut players,
+        &mut player_specs,
+        settings_panel.player_specs(),
+        &mut settings_panel.status,
+    );
+    let mut game = GameController::new(Board::new(Color::Light), None, Instant::now());
+    game.record_mut()
+        .set_tag("Date", pgn_date(unix_timestamp()));
+    name_players(&mut game, &players);
+    let mut move_animation: Option<MoveAnimation> = None;
 
     let mut previous_mouse_state = &ElementState::Released;
-    let mut taken_piece_cell_position: Option<(usize, usize)> = None;
-
-    system.main_loop(move |_run, display, cursor_position, mouse_input_state| {
-        let screenspace_cursor_position: (f64, f64) = (cursor_position.0, cursor_position.1);
-        // -1. -> 1.
-        let raw_ndc_cursor_position: (f64, f64) = (
-            (screenspace_cursor_position.0 - app_settings.window_width) / app_settings.window_width,
-            -1.0 * (screenspace_cursor_position.1 - app_settings.window_height)
-                / app_settings.window_height,
+    let mut previous_right_mouse_state = &ElementState::Released;
+    let mut annotation_start_square: Option<Square> = None;
+    // Position setup replaces the settings panel and board input while open
+    let mut editor: Option<PositionEditor> = None;
+
+    system.main_loop(move |_run, display, target, ui, frame_input| {
+        let time = start.elapsed().as_secs_f32();
+        let now = Instant::now();
+        let mut is_screenshot_requested = false;
+        let mut dropped_piece: Option<Piece> = None;
+        // Board input and panel actions, handled together after the input
+        let mut game_events: Vec<GameEvent> = Vec::new();
+        // Players other than the mouse have their say, moves once it is their
+        // turn, before anything else changes the position
+        for color in Color::ALL {
+            match players[player_index(color)].poll_events(&game) {
+                Ok(events) => game_events.extend(events),
+                Err(error) => {
+                    settings_panel.status = Some(format!("{}: {}", color_label(color), error))
+                }
+            }
+        }
+        if !game.is_over() && editor.is_none() {
+            let turn = game.board().turn;
+            match players[player_index(turn)].poll_move(&game) {
+                Ok(Some(chess_move)) => game_events.push(GameEvent::Move(chess_move)),
+                Ok(None) => (),
+                Err(error) => {
+                    settings_panel.status = Some(format!("{}: {}", color_label(turn), error))
+                }
+            }
+        }
+        // Panel actions are taken for the side at this window
+        let turn = game.board().turn;
+        let acting_color = if players[player_index(turn)].is_local() {
+            turn
+        } else {
+            turn.opposite()
+        };
+        let editor_action = editor.as_mut().and_then(|position_editor| {
+            position_editor.draw(
+                ui,
+                app_settings.side_panel_width as f32,
+                app_settings.board_margin as f32,
+            )
+        });
+        match editor_action {
+            Some(EditorAction::StartGame) => {
+                if let Some(position_editor) = editor.take() {
+                    game_events.push(GameEvent::NewGame {
+                        start: Box::new(position_editor.board),
+                        time_control: settings_panel.time_control(),
+                    });
+                }
+            }
+            Some(EditorAction::Cancel) => editor = None,
+            Some(EditorAction::DropPiece(piece)) => dropped_piece = Some(piece),
+            None => (),
+        }
+        let settings_action = match editor {
+            Some(_) => None,
+            None => settings_panel.draw(
+                ui,
+                app_settings.side_panel_width as f32,
+                app_settings.board_margin as f32,
+            ),
+        };
+        match settings_action {
+            Some(SettingsAction::PickTheme(theme_index)) => {
+                let picked_theme = &themes[theme_index].1;
+                match PieceAtlas::load(display, &assets, picked_theme) {
+                    Ok(atlas) => {
+                        theme = picked_theme.clone();
+                        piece_atlas = atlas;
+                        settings_panel.status = None;
+                    }
+                    Err(error) => settings_panel.status = Some(error.to_string()),
+                }
+            }
+            Some(SettingsAction::SaveScreenshot) => is_screenshot_requested = true,
+            Some(SettingsAction::NewGame) => game_events.push(GameEvent::NewGame {
+                start: Box::new(Board::new(Color::Light)),
+                time_control: settings_panel.time_control(),
+            }),
+            Some(SettingsAction::Undo) => game_events.push(GameEvent::Undo),
+            Some(SettingsAction::Redo) => game_events.push(GameEvent::Redo),
+            Some(SettingsAction::EditPosition) => {
+                game_events.push(GameEvent::DropOnSquare(None));
+                move_animation = None;
+                editor = Some(PositionEditor::new(*game.board()));
+            }
+            Some(SettingsAction::SavePgn) => {
+                let path = PathBuf::from(format!("chess-{}.pgn", unix_timestamp()));
+                settings_panel.status = Some(match std::fs::write(&path, game.record().to_pgn()) {
+                    Ok(()) => format!("Saved {}", path.display()),
+                    Err(error) => format!("Failed to save {}: {}", path.display(), error),
+                });
+            }
+            Some(SettingsAction::Resign) => game_events.push(GameEvent::Resign(acting_color)),
+            Some(SettingsAction::OfferDraw) => game_events.push(GameEvent::OfferDraw(acting_color)),
+            Some(SettingsAction::AcceptDraw) => {
+                if let Some(offered_to) = game.draw_offer().map(Color::opposite) {
+                    game_events.push(GameEvent::AcceptDraw(offered_to));
+                }
+            }
+            Some(SettingsAction::DeclineDraw) => {
+                if let Some(offered_to) = game.draw_offer().map(Color::opposite) {
+                    game_events.push(GameEvent::DeclineDraw(offered_to));
+                }
+            }
+            Some(SettingsAction::ClaimDraw) => game_events.push(GameEvent::ClaimDraw),
+            None => (),
+        }
+
+        let mouse_input_state = &frame_input.left_mouse_button;
+        let framebuffer_dimensions = display.get_framebuffer_dimensions();
+        let board_layout = BoardLayout::fit(
+            framebuffer_dimensions,
+            frame_input.scale_factor,
+            app_settings.side_panel_width,
+            app_settings.board_margin,
         );
+        let piece_pixel_size = board_layout.ndc_length_to_pixels(PIECE_CELL_SIZE);
+        let taken_piece_pixel_size = board_layout.ndc_length_to_pixels(TAKEN_PIECE_CELL_SIZE);
+        if let Err(error) = piece_atlas.prepare(
+            display,
+            &assets,
+            &[piece_pixel_size, taken_piece_pixel_size],
+        ) {
+            settings_panel.status = Some(error.to_string());
+        }
+        let draw_parameters = glium::DrawParameters {
+            viewport: Some(board_layout.viewport()),
+            ..Default::default()
+        };
+
+        // -1. -> 1. inside the board, beyond that outside of it
+        let raw_ndc_cursor_position: (f64, f64) = board_layout.cursor_to_ndc(
+            &frame_input.cursor_position,
+            frame_input.scale_factor,
+            framebuffer_dimensions.1,
+        );
+        let is_cursor_on_board = ui::layout::is_on_board(&raw_ndc_cursor_position);
         let ndc_cursor_position: (f64, f64) = (
-            (raw_ndc_cursor_position.0 as f32).clamp(-1.0, 1.0) as f64,
-            (raw_ndc_cursor_position.1 as f32).clamp(-1.0, 1.0) as f64,
+            raw_ndc_cursor_position.0.clamp(-1.0, 1.0),
+            raw_ndc_cursor_position.1.clamp(-1.0, 1.0),
         );
 
-        if mouse_input_state == &ElementState::Pressed
+        if let Some(position_editor) = &mut editor {
+            let cursor_cell_position = is_cursor_on_board
+                .then(|| ui::ndc_cursor_position_to_cell_position(&ndc_cursor_position));
+            if let (Some(piece), Some(cell_position)) = (dropped_piece, cursor_cell_position) {
+                position_editor.drop_piece(piece, cell_position);
+            }
+            if mouse_input_state == &ElementState::Pressed
+                && previous_mouse_state == &ElementState::Released
+            {
+                if let Some(cell_position) = cursor_cell_position {
+                    position_editor.press(cell_position);
+                }
+                previous_mouse_state = &ElementState::Pressed;
+            } else if mouse_input_state == &ElementState::Released
+                && previous_mouse_state == &ElementState::Pressed
+            {
+                position_editor.release(cursor_cell_position);
+                previous_mouse_state = &ElementState::Released;
+            }
+        } else if mouse_input_state == &ElementState::Pressed
             && previous_mouse_state == &ElementState::Released
         {
-            // take piece
-            let cursor_cell_position =
-                ui::ndc_cursor_position_to_cell_position(&ndc_cursor_position);
-            let taken_piece = board.state[7 - cursor_cell_position.1][cursor_cell_position.0];
-
-            if taken_piece.is_some() && taken_piece.unwrap().color == board.turn {
-                taken_piece_cell_position = Some(cursor_cell_position);
-                // calculate appropriate moves for the piece
-                for pos_x in 0..8 {
-                    for pos_y in 0..8 {
-                        let mut is_appropriate_move = backend::can_move_piece(
-                            &board,
-                            &taken_piece_cell_position.unwrap(),
-                            &(pos_x, pos_y),
-                        );
-
-                        if is_appropriate_move {
-                            let piece = taken_piece.unwrap();
-                            let oppenent_color = match piece.color {
-                                Color::Light => Color::Dark,
-                                Color::Dark => Color::Light,
-                            };
-                            // Copy the board and make move to check if there is a check after
-                            let mut tmp_board = board.clone();
-
-                            let from_position = taken_piece_cell_position.unwrap();
-                            tmp_board.put_piece_at_cell(&(pos_x, pos_y), piece);
-                            tmp_board.clear_cell(&from_position);
-                            let this_side_king_position = tmp_board.get_king_position(piece.color);
-                            let lead_to_check = backend::is_any_piece_attacks_position(
-                                &tmp_board,
-                                oppenent_color,
-                                &this_side_king_position,
-                            );
-                            is_appropriate_move = is_appropriate_move && !lead_to_check;
-                        }
-                        valid_cells_to_move[pos_x][pos_y] = is_appropriate_move;
-                    }
+            // take piece, settling any animation first
+            move_animation = None;
+            if is_cursor_on_board {
+                let cursor_cell_position =
+                    ui::ndc_cursor_position_to_cell_position(&ndc_cursor_position);
+                let square = game.board().cell_to_square(&cursor_cell_position);
+                // Only pieces of the sides played at this window can be picked up
+                if game
+                    .board()
+                    .piece_at_square(square)
+                    .is_none_or(|piece| players[player_index(piece.color)].is_local())
+                {
+                    game_events.push(GameEvent::SelectSquare(square));
                 }
-                valid_cell_to_move_encoded =
-                    backend::encode_valid_cells_to_integers(&valid_cells_to_move);
             }
             previous_mouse_state = &ElementState::Pressed;
         } else if mouse_input_state == &ElementState::Released
             && previous_mouse_state == &ElementState::Pressed
         {
-            if taken_piece_cell_position.is_some() {
-                // drop: finish moving or eat opponent piece
-                let destination_cell_position =
-                    ui::ndc_cursor_position_to_cell_position(&ndc_cursor_position);
-                let initial_cell_position = taken_piece_cell_position.unwrap();
-                if destination_cell_position != initial_cell_position
-                    && valid_cells_to_move[destination_cell_position.0][destination_cell_position.1]
-                {
-                    board.make_a_move(&initial_cell_position, &destination_cell_position);
-                }
-                taken_piece_cell_position = None;
-            }
+            // drop: finish moving or eat opponent piece, dropping off the board cancels
+            let destination_square = is_cursor_on_board.then(|| {
+                game.board()
+                    .cell_to_square(&ui::ndc_cursor_position_to_cell_position(
+                        &ndc_cursor_position,
+                    ))
+            });
+            game_events.push(GameEvent::DropOnSquare(destination_square));
             previous_mouse_state = &ElementState::Released;
-            valid_cell_to_move_encoded = (0, 0);
         }
 
-        let time: f32 = start.elapsed().as_secs_f32();
-
-        let mut target = display.draw();
-        target.clear_color_srgb(0.52, 0.41, 0.22, 1.0);
-
-        let board_shape = vec![
-            Vertex::from_position([-1.0, -1.0]),
-            Vertex::from_position([1.0, -1.0]),
-            Vertex::from_position([-1.0, 1.0]),
-            Vertex::from_position([1.0, 1.0]),
-        ];
-        let vertex_buffer = glium::VertexBuffer::new(display, &board_shape).unwrap();
-        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
-        board_shader.draw(
-            &mut target,
-            vertex_buffer,
-            indices,
-            &ndc_cursor_position,
-            &empty_texture,
-            time,
-            valid_cell_to_move_encoded,
-        );
-
-        // Draw pieces
-        let cell_size: f32 = 0.25;
-        for pos_x in 0..8 {
-            for pos_y in 0..8 {
-                // Do not draw taken piece in the loop:
-                if taken_piece_cell_position.is_some() {
-                    let cell_position = taken_piece_cell_position.unwrap();
-                    if pos_x == cell_position.0 && pos_y == cell_position.1 {
-                        continue;
+        // Time passes every frame: flags fall and premoves come due
+        game_events.push(GameEvent::Tick);
+        for event in game_events {
+            let is_drop = matches!(event, GameEvent::DropOnSquare(_));
+            let is_new_game = matches!(event, GameEvent::NewGame { .. });
+            for change in game.handle(event, now) {
+                match &change {
+                    GameChange::Played { chess_move, .. } => {
+                        for player in &mut players {
+                            player.move_played(*chess_move, &game);
+                        }
+                    }
+                    GameChange::PositionChanged { .. } => {
+                        for player in &mut players {
+                            player.position_reset(&game);
+                        }
+                    }
+                    _ => {
+                        for player in &mut players {
+                            player.game_changed(&change, &game);
+                        }
                     }
                 }
-
-                let pos_y = 7 - pos_y;
-                let lower_left_position: [f32; 2] = [
-                    -1.0 + pos_x as f32 * cell_size,
-                    -1.0 + (7 - pos_y) as f32 * cell_size,
-                ];
-                let piece = &board.state[pos_y][pos_x];
-                match piece {
-                    Some(piece) => {
-                        let shape = vec![
-                            ui::shaders::primitives::Vertex::from_position_and_texture_coordinates(
-                                lower_left_position,
-                                [0.0, 0.0],
-                            ),
-                            ui::shaders::primitives::Vertex::from_position_and_texture_coordinates(
-                                [lower_left_position[0], lower_left_position[1] + cell_size],
-                                [0.0, 1.0],
-                            ),
-                            ui::shaders::primitives::Vertex::from_position_and_texture_coordinates(
-                                [lower_left_position[0] + cell_size, lower_left_position[1]],
-                                [1.0, 0.0],
-                            ),
-                            ui::shaders::primitives::Vertex::from_position_and_texture_coordinates(
-                                [
-                                    lower_left_position[0] + cell_size,
-                                    lower_left_position[1] + cell_size,
-                                ],
-                                [1.0, 1.0],
-                            ),
-                        ];
-
-                        let texture_ = pieces_textures.get(&(piece.kind, piece.color));
-                        let piece_texture = match texture_ {
-                            Some(texture) => texture,
-                            None => &empty_texture,
-                        };
-
-                        let indices =
-                            glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
-                        let vertex_buffer = glium::VertexBuffer::new(display, &shape).unwrap();
-                        pawn_shader.draw(
-                            &mut target,
-                            vertex_buffer,
-                            indices,
-                            &ndc_cursor_position,
-                            piece_texture,
+                match change {
+                    // A dropped piece is already where it lands
+                    GameChange::Played { previous, .. } if !is_drop => {
+                        move_animation = Some(MoveAnimation::new(
+                            &previous,
+                            game.board(),
+                            time,
+                            settings_panel.animation_duration,
+                        ));
+                    }
+                    GameChange::PositionChanged { previous } if !is_new_game => {
+                        settings_panel.status = None;
+                        move_animation = Some(MoveAnimation::new(
+                            &previous,
+                            game.board(),
                             time,
-                            (0, 0),
-                        );
+                            settings_panel.animation_duration,
+                        ));
                     }
-                    None => (),
+                    GameChange::DrawOffered(_) | GameChange::DrawDeclined(_) => {
+                        settings_panel.status = None
+                    }
+                    GameChange::Ended(outcome) => {
+                        settings_panel.status = Some(format!("{}, {}", outcome, outcome.result()))
+                    }
+                    GameChange::Refused(error) => settings_panel.status = Some(error.to_string()),
+                    _ => (),
+                }
+            }
+            if is_new_game {
+                game.record_mut()
+                    .set_tag("Date", pgn_date(unix_timestamp()));
+                move_animation = None;
+                settings_panel.status = None;
+                update_players(
+                    &mut players,
+                    &mut player_specs,
+                    settings_panel.player_specs(),
+                    &mut settings_panel.status,
+                );
+                name_players(&mut game, &players);
+                for player in &mut players {
+                    player.position_reset(&game);
                 }
             }
         }
+        let is_game_over = game.is_over();
+        settings_panel.is_game_over = is_game_over;
+        settings_panel.draw_offered_by = game.draw_offer().map(color_label);
+        settings_panel.can_claim_draw = !is_game_over && game.claimable_draw().is_some();
+        settings_panel.clocks = game.clock().map(|clock| {
+            Color::ALL.map(|color| ClockReadout {
+                player: color_label(color),
+                remaining: clock.remaining(color, now),
+                is_running: clock.running_color() == Some(color),
+            })
+        });
 
-        // Draw taken piece:
-        if taken_piece_cell_position.is_some() {
-            let piece_cell = taken_piece_cell_position.unwrap();
-            let taken_piece = &board.state[7 - piece_cell.1][piece_cell.0];
-            match taken_piece {
-                Some(piece) => {
-                    let cell_size = 0.32;
-                    let lower_left_position: [f32; 2] = [
-                        ndc_cursor_position.0 as f32 - (cell_size / 2.0),
-                        ndc_cursor_position.1 as f32 - (cell_size / 2.0),
-                    ];
-                    let shape = vec![
-                        ui::shaders::primitives::Vertex::from_position_and_texture_coordinates(
-                            lower_left_position,
-                            [0.0, 0.0],
-                        ),
-                        ui::shaders::primitives::Vertex::from_position_and_texture_coordinates(
-                            [lower_left_position[0], lower_left_position[1] + cell_size],
-                            [0.0, 1.0],
-                        ),
-                        ui::shaders::primitives::Vertex::from_position_and_texture_coordinates(
-                            [lower_left_position[0] + cell_size, lower_left_position[1]],
-                            [1.0, 0.0],
-                        ),
-                        ui::shaders::primitives::Vertex::from_position_and_texture_coordinates(
-                            [
-                                lower_left_position[0] + cell_size,
-                                lower_left_position[1] + cell_size,
-                            ],
-                            [1.0, 1.0],
-                        ),
-                    ];
-                    let texture_ = pieces_textures.get(&(piece.kind, piece.color));
-                    let piece_texture = match texture_ {
-                        Some(texture) => texture,
-                        None => &empty_texture,
-                    };
-                    let indices =
-                        glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
-                    let vertex_buffer = glium::VertexBuffer::new(display, &shape).unwrap();
-                    pawn_shader.draw(
-                        &mut target,
-                        vertex_buffer,
-                        indices,
-                        &ndc_cursor_position,
-                        piece_texture,
-                        time,
-                        (0, 0),
-                    );
+        // Right button: click marks a square, drag draws an arrow
+        let right_mouse_input_state = &frame_input.right_mouse_button;
+        if right_mouse_input_state == &ElementState::Pressed
+            && previous_right_mouse_state == &ElementState::Released
+        {
+            if is_cursor_on_board && editor.is_none() {
+                let cursor_cell_position =
+                    ui::ndc_cursor_position_to_cell_position(&ndc_cursor_position);
+                annotation_start_square = Some(game.board().cell_to_square(&cursor_cell_position));
+            }
+            previous_right_mouse_state = &ElementState::Pressed;
+        } else if right_mouse_input_state == &ElementState::Released
+            && previous_right_mouse_state == &ElementState::Pressed
+        {
+            if let Some(start_square) = annotation_start_square.take() {
+                if is_cursor_on_board {
+                    let cursor_cell_position =
+                        ui::ndc_cursor_position_to_cell_position(&ndc_cursor_position);
+                    let end_square = game.board().cell_to_square(&cursor_cell_position);
+                    let modifiers = frame_input.modifiers;
+                    let color =
+                        annotation_color(modifiers.shift() || modifiers.ctrl(), modifiers.alt());
+                    if end_square == start_square {
+                        game.record_mut()
+                            .annotations_mut()
+                            .toggle_mark(end_square, color);
+                    } else {
+                        game.record_mut()
+                            .annotations_mut()
+                            .toggle_arrow(Move::new(start_square, end_square), color);
+                    }
+                }
+            }
+            previous_right_mouse_state = &ElementState::Released;
+        }
+
+        target.clear_color_srgb(0.52, 0.41, 0.22, 1.0);
+
+        if move_animation
+            .as_ref()
+            .is_some_and(|animation| animation.is_finished(time))
+        {
+            move_animation = None;
+        }
+        // The editor shows its own board, and pieces can come from its palette
+        let board = *game.board();
+        let taken_piece_cell_position = game
+            .selected_square()
+            .map(|square| board.square_to_cell(square));
+        let shown_board = editor
+            .as_ref()
+            .map_or(board, |position_editor| position_editor.board);
+        let dragged_piece = match &editor {
+            Some(position_editor) => position_editor.dragged_piece(),
+            None => taken_piece_cell_position.and_then(|piece_cell| {
+                board.state[7 - piece_cell.1][piece_cell.0].map(|piece| (piece, Some(piece_cell)))
+            }),
+        };
+        let dragged_from_cell = dragged_piece.and_then(|(_, piece_cell)| piece_cell);
+        let mut piece_instances = match &move_animation {
+            Some(animation) => animation.piece_instances(&piece_atlas, piece_pixel_size, time),
+            None => piece_instances(
+                &shown_board,
+                &piece_atlas,
+                piece_pixel_size,
+                dragged_from_cell,
+            ),
+        };
+
+        // Taken piece goes last to be drawn on top
+        if let Some((piece, _)) = dragged_piece {
+            let lower_left_position: [f32; 2] = [
+                ndc_cursor_position.0 as f32 - (TAKEN_PIECE_CELL_SIZE / 2.0),
+                ndc_cursor_position.1 as f32 - (TAKEN_PIECE_CELL_SIZE / 2.0),
+            ];
+            if let Some(region) =
+                piece_atlas.region(piece.kind, piece.color, taken_piece_pixel_size)
+            {
+                piece_instances.push(PieceInstance::new(
+                    lower_left_position,
+                    TAKEN_PIECE_CELL_SIZE,
+                    region,
+                ));
+            }
+        }
+
+        let mut highlights = SquareHighlights::default();
+        if editor.is_none() {
+            if let Some(last_move) = game
+                .record()
+                .moves
+                .last()
+                .map(|recorded| recorded.chess_move)
+            {
+                highlights.add(
+                    HighlightLayer::LastMove,
+                    &board.square_to_cell(last_move.from),
+                );
+                highlights.add(
+                    HighlightLayer::LastMove,
+                    &board.square_to_cell(last_move.to),
+                );
+            }
+            if let Some(king_position) = board.get_king_position(board.turn) {
+                if backend::is_king_in_check(&board, board.turn) {
+                    highlights.add(HighlightLayer::Check, &king_position);
                 }
-                _ => (),
             }
+            for premove in game.premoves() {
+                highlights.add(HighlightLayer::Premove, &board.square_to_cell(premove.from));
+                highlights.add(HighlightLayer::Premove, &board.square_to_cell(premove.to));
+            }
+        }
+        if let Some(piece_cell) = dragged_from_cell {
+            highlights.add(HighlightLayer::Selection, &piece_cell);
         }
+        for target in game.targets() {
+            highlights.add(HighlightLayer::LegalTarget, &board.square_to_cell(*target));
+        }
+        let board_style = BoardStyle {
+            theme: &theme,
+            time,
+            highlights,
+        };
+        board_renderer.draw(
+            display,
+            target,
+            &board_style,
+            &piece_atlas,
+            &piece_instances,
+            &draw_parameters,
+        );
 
-        target.finish().expect("Failed to swap buffers");
+        if settings_panel.show_coordinates {
+            draw_coordinates(
+                ui,
+                &shown_board,
+                &board_layout,
+                &theme,
+                frame_input.scale_factor,
+                framebuffer_dimensions.1,
+            );
+        }
+        if editor.is_none() {
+            draw_annotations(
+                ui,
+                &board,
+                &board_layout,
+                game.record().annotations(),
+                frame_input.scale_factor,
+                framebuffer_dimensions.1,
+            );
+        }
+
+        if is_screenshot_requested {
+            let path = PathBuf::from(format!("chess-{}.png", unix_timestamp()));
+            let saved = render_board(
+                display,
+                &mut board_renderer,
+                &mut piece_atlas,
+                &assets,
+                &shown_board,
+                &board_style,
+                board_layout.size,
+            )
+            .and_then(|image| save_image(&image, &path));
+            settings_panel.status = Some(match saved {
+                Ok(()) => format!("Saved {}", path.display()),
+                Err(error) => error.to_string(),
+            });
+        }
     });
+    Ok(())
+}
+
+/// Renders the starting position with the default theme into a PNG file,
+/// without opening a window.
+pub fn render_to_file(path: &Path, size: u32) -> Result<(), RenderError> {
+    let assets = Assets::from_env();
+    let facade = create_headless_renderer(size)?;
+    let theme = Theme::load(&assets, DEFAULT_THEME)?;
+    let mut piece_atlas = PieceAtlas::load(&facade, &assets, &theme)?;
+    let mut board_renderer = BoardRenderer::new(&facade, &assets)?;
+    let board_style = BoardStyle {
+        theme: &theme,
+        time: 0.0,
+        highlights: SquareHighlights::default(),
+    };
+    let image = render_board(
+        &facade,
+        &mut board_renderer,
+        &mut piece_atlas,
+        &assets,
+        &Board::new(Color::Light),
+        &board_style,
+        size,
+    )?;
+    save_image(&image, path)
+}
+
+fn player_index(color: Color) -> usize {
+    match color {
+        Color::Light => 0,
+        Color::Dark => 1,
+    }
+}
+
+/// Starts the players of the sides whose spec changed. One that cannot start
+/// is left to a human, with the reason in `status`.
+fn update_players(
+    players: &mut [Box<dyn Player>; 2],
+    current_specs: &mut [PlayerSpec; 2],
+    specs: [PlayerSpec; 2],
+    status: &mut Option<String>,
+) {
+    for (color, spec) in Color::ALL.into_iter().zip(specs) {
+        let index = player_index(color);
+        if current_specs[index] == spec {
+            continue;
+        }
+        players[index] = match spec.create(color) {
+            Ok(player) => player,
+            Err(error) => {
+                *status = Some(format!("{}: {}", color_label(color), error));
+                Box::new(HumanPlayer)
+            }
+        };
+        current_specs[index] = spec;
+    }
+}
+
+/// Puts the names of players away from this window into the PGN tags.
+fn name_players(game: &mut GameController, players: &[Box<dyn Player>; 2]) {
+    for (color, tag) in [(Color::Light, "White"), (Color::Dark, "Black")] {
+        let player = &players[player_index(color)];
+        if !player.is_local() {
+            game.record_mut().set_tag(tag, player.name());
+        }
+    }
+}
+
+fn color_label(color: Color) -> &'static str {
+    match color {
+        Color::Light => "White",
+        Color::Dark => "Black",
+    }
 }
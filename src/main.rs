@@ -1,3 +1,69 @@
+use std::path::{Path, PathBuf};
+
+use chess::correspondence::{default_journal_path, CorrespondenceError, CorrespondenceGame};
+use chess::player::PlayerSpec;
+use chess::Color;
+
+const USAGE: &str = "usage: chess [--white <player>] [--black <player>]
+       chess --correspondence <game.pgn>
+       chess --screenshot <file.png> [--size <pixels>]
+players: human, engine[:<depth>], uci:<command>, script:<move>,<move>...,
+         connect:<host>:<port>, listen:<port>, correspondence:<game.pgn>";
+
 fn main() {
-    chess::run();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.as_slice() {
+        [flag, path] if flag == "--screenshot" => {
+            chess::render_to_file(&PathBuf::from(path), 640).map_err(|error| error.to_string())
+        }
+        [flag, path, size_flag, size] if flag == "--screenshot" && size_flag == "--size" => {
+            match size.parse::<u32>() {
+                Ok(size) => chess::render_to_file(&PathBuf::from(path), size)
+                    .map_err(|error| error.to_string()),
+                Err(_) => Err(USAGE.to_owned()),
+            }
+        }
+        [flag, path] if flag == "--correspondence" => correspondence_players(path)
+            .map_err(|error| format!("{}: {}", path, error))
+            .and_then(|players| chess::run(players).map_err(|error| error.to_string())),
+        _ => match player_specs(&args) {
+            Some(players) => chess::run(players).map_err(|error| error.to_string()),
+            None => Err(USAGE.to_owned()),
+        },
+    };
+    if let Err(error) = result {
+        eprintln!("chess: {}", error);
+        std::process::exit(1);
+    }
+}
+
+/// Someone at this window for the side to move in the correspondence game
+/// in `path`, and the file for the other side. A file that does not exist yet
+/// starts a new game, White to play here.
+fn correspondence_players(path: &str) -> Result<[PlayerSpec; 2], CorrespondenceError> {
+    let journal = default_journal_path();
+    let game = if Path::new(path).exists() {
+        CorrespondenceGame::open(Path::new(path), journal.as_deref())?
+    } else {
+        CorrespondenceGame::create(Path::new(path), journal.as_deref(), "?", "?")?
+    };
+    let file = PlayerSpec::Correspondence(path.to_owned());
+    Ok(match game.game().board().turn {
+        Color::Light => [PlayerSpec::Human, file],
+        Color::Dark => [file, PlayerSpec::Human],
+    })
+}
+
+/// `--white` and `--black` options, humans by default.
+fn player_specs(args: &[String]) -> Option<[PlayerSpec; 2]> {
+    let mut players = [PlayerSpec::Human, PlayerSpec::Human];
+    for option in args.chunks(2) {
+        let side = match option[0].as_str() {
+            "--white" => 0,
+            "--black" => 1,
+            _ => return None,
+        };
+        players[side] = PlayerSpec::parse(option.get(1)?)?;
+    }
+    Some(players)
 }
@@ -0,0 +1,183 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::backend::pgn::unix_timestamp;
+use crate::backend::{Color, GameChange, GameController, GameEvent, Move, Termination};
+use crate::correspondence::{default_journal_path, CorrespondenceError, CorrespondenceGame};
+
+use super::{local_name, Player, PlayerError};
+
+/// How often the game file is looked at for the other side's move.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The other side of a correspondence game, whose moves show up in the game
+/// file, typically in a shared folder. Moves made at this window are checked
+/// and written to the file; the file is read again whenever it changes. It
+/// is what counts: a position taken back or set up here is put back as the
+/// file has it.
+pub struct CorrespondencePlayer {
+    /// The side the other player plays.
+    color: Color,
+    file: CorrespondenceGame,
+    /// When the file was last changed, as far as this player knows.
+    modified: Option<SystemTime>,
+    next_check: Instant,
+    /// The game at the window has to be brought back in line with the file.
+    needs_sync: bool,
+    /// What went wrong writing the file, reported with the next poll.
+    error: Option<CorrespondenceError>,
+}
+
+impl CorrespondencePlayer {
+    pub fn open(path: &Path, color: Color) -> Result<CorrespondencePlayer, PlayerError> {
+        let file = CorrespondenceGame::open(path, default_journal_path().as_deref())?;
+        Ok(CorrespondencePlayer {
+            color,
+            file,
+            modified: modified(path),
+            next_check: Instant::now() + CHECK_INTERVAL,
+            needs_sync: true,
+            error: None,
+        })
+    }
+
+    /// Whether `game` has the same start and moves as the file, up to the
+    /// moves it has.
+    fn is_followed_by(&self, game: &GameController) -> bool {
+        let recorded = &self.file.game().record().moves;
+        let played = &game.record().moves;
+        game.initial_board().to_fen() == self.file.game().initial_board().to_fen()
+            && played.len() <= recorded.len()
+            && played
+                .iter()
+                .zip(recorded)
+                .all(|(played, recorded)| played.chess_move == recorded.chess_move)
+    }
+
+    /// Events that bring `game` to where the file stands: the moves it is
+    /// missing, or the whole game again if the two went different ways.
+    fn catch_up(&self, game: &GameController) -> Vec<GameEvent> {
+        let file_game = self.file.game();
+        let mut events = Vec::new();
+        let mut is_over = game.is_over();
+        let first_move = if self.is_followed_by(game) {
+            game.record().moves.len()
+        } else {
+            events.push(GameEvent::NewGame {
+                start: Box::new(*file_game.initial_board()),
+                time_control: None,
+            });
+            is_over = false;
+            0
+        };
+        events.extend(
+            file_game.record().moves[first_move..]
+                .iter()
+                .map(|recorded_move| GameEvent::Move(recorded_move.chess_move)),
+        );
+        // Mates and the like come about by the moves, resignations do not
+        if let Some(outcome) = file_game.outcome() {
+            if let (Termination::Resignation, Some(winner), false) =
+                (outcome.termination, outcome.winner, is_over)
+            {
+                events.push(GameEvent::Resign(winner.opposite()));
+            }
+        }
+        events
+    }
+
+    /// Keeps `result` of writing the file, an error to be reported and the
+    /// game at the window put back as the file has it.
+    fn written(&mut self, result: Result<(), CorrespondenceError>) {
+        match result {
+            Ok(()) => self.modified = modified(self.file.path()),
+            Err(error) => {
+                self.error = Some(error);
+                self.needs_sync = true;
+            }
+        }
+    }
+}
+
+impl Player for CorrespondencePlayer {
+    fn name(&self) -> String {
+        let tag = match self.color {
+            Color::Light => "White",
+            Color::Dark => "Black",
+        };
+        match self.file.game().record().tag(tag) {
+            Some(name) if name != "?" => name.to_owned(),
+            _ => "Correspondent".to_owned(),
+        }
+    }
+
+    fn poll_move(&mut self, _game: &GameController) -> Result<Option<Move>, PlayerError> {
+        Ok(None)
+    }
+
+    fn poll_events(&mut self, game: &GameController) -> Result<Vec<GameEvent>, PlayerError> {
+        if let Some(error) = self.error.take() {
+            return Err(error.into());
+        }
+        let now = Instant::now();
+        if now >= self.next_check {
+            self.next_check = now + CHECK_INTERVAL;
+            let path: PathBuf = self.file.path().to_owned();
+            let modified = modified(&path);
+            if modified != self.modified {
+                // A file that fails the checks is reported once per change
+                self.modified = modified;
+                self.file = CorrespondenceGame::open(&path, self.file.journal())?;
+                self.needs_sync = true;
+            }
+        }
+        if !self.needs_sync {
+            return Ok(Vec::new());
+        }
+        self.needs_sync = false;
+        Ok(self.catch_up(game))
+    }
+
+    fn move_played(&mut self, chess_move: Move, game: &GameController) {
+        let recorded = self.file.game().record().moves.len();
+        if game.record().moves.len() <= recorded && self.is_followed_by(game) {
+            // A move from the file
+            return;
+        }
+        let previous_moves = &game.record().moves[..game.record().moves.len() - 1];
+        let is_next_move = previous_moves.len() == recorded
+            && previous_moves
+                .iter()
+                .zip(&self.file.game().record().moves)
+                .all(|(played, recorded)| played.chess_move == recorded.chess_move);
+        let result = if is_next_move && game.board().turn == self.color {
+            self.file.play(chess_move, &local_name(), unix_timestamp())
+        } else {
+            Err(CorrespondenceError::Diverged)
+        };
+        self.written(result);
+    }
+
+    fn position_reset(&mut self, _game: &GameController) {
+        self.needs_sync = true;
+    }
+
+    fn game_changed(&mut self, change: &GameChange, _game: &GameController) {
+        if let GameChange::Ended(outcome) = change {
+            if outcome.termination == Termination::Resignation
+                && outcome.winner == Some(self.color)
+                && !self.file.game().is_over()
+            {
+                let result = self.file.resign(self.color.opposite(), &local_name());
+                self.written(result);
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
@@ -0,0 +1,252 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+use crate::backend::{is_king_in_check, Board, Color, GameController, Move, PieceKind};
+
+use super::{Player, PlayerError};
+
+/// Scores of mated positions, beyond anything material can add up to.
+const MATE_SCORE: i32 = 100_000;
+
+/// A small alpha-beta search over material and piece placement, run on a
+/// thread of its own so the window keeps drawing.
+pub struct EnginePlayer {
+    depth: u32,
+    search: Option<Search>,
+}
+
+/// A search under way, for the position in FEN.
+struct Search {
+    position: String,
+    best_move: Receiver<Option<Move>>,
+    /// Set when the position changed, to end the search early.
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Drop for Search {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl EnginePlayer {
+    pub const DEFAULT_DEPTH: u32 = 3;
+
+    pub fn new(depth: u32) -> EnginePlayer {
+        EnginePlayer {
+            depth: depth.max(1),
+            search: None,
+        }
+    }
+
+    /// The best move for the side to move looking `depth` half-moves ahead,
+    /// `None` when there is no legal move.
+    pub fn best_move(board: &Board, depth: u32) -> Option<Move> {
+        search(board, depth, &AtomicBool::new(false))
+    }
+}
+
+/// `EnginePlayer::best_move`, giving up with whatever it has once
+/// `cancelled` is set.
+fn search(board: &Board, depth: u32, cancelled: &AtomicBool) -> Option<Move> {
+    let mut best = None;
+    let mut alpha = -MATE_SCORE * 2;
+    for chess_move in ordered_moves(board) {
+        let mut next_board = *board;
+        next_board.make_move_unchecked(chess_move);
+        let score = -negamax(
+            &next_board,
+            depth.saturating_sub(1),
+            -MATE_SCORE * 2,
+            -alpha,
+            cancelled,
+        );
+        if best.is_none() || score > alpha {
+            alpha = score;
+            best = Some(chess_move);
+        }
+        if cancelled.load(Ordering::Relaxed) {
+            break;
+        }
+    }
+    best
+}
+
+impl Player for EnginePlayer {
+    fn name(&self) -> String {
+        format!("Engine (depth {})", self.depth)
+    }
+
+    fn poll_move(&mut self, game: &GameController) -> Result<Option<Move>, PlayerError> {
+        let position = game.board().to_fen();
+        if self
+            .search
+            .as_ref()
+            .is_none_or(|search| search.position != position)
+        {
+            // Replacing a search for another position cancels it, and its
+            // result goes nowhere
+            let (sender, receiver) = mpsc::channel();
+            let cancelled = Arc::new(AtomicBool::new(false));
+            let board = *game.board();
+            let depth = self.depth;
+            let thread_cancelled = Arc::clone(&cancelled);
+            thread::spawn(move || {
+                let best_move = search(&board, depth, &thread_cancelled);
+                if !thread_cancelled.load(Ordering::Relaxed) {
+                    let _ = sender.send(best_move);
+                }
+            });
+            self.search = Some(Search {
+                position,
+                best_move: receiver,
+                cancelled,
+            });
+        }
+        let search = match &self.search {
+            Some(search) => search,
+            None => return Ok(None),
+        };
+        match search.best_move.try_recv() {
+            Ok(best_move) => {
+                self.search = None;
+                best_move.map(Some).ok_or(PlayerError::NoMove)
+            }
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => {
+                self.search = None;
+                Err(PlayerError::NoMove)
+            }
+        }
+    }
+}
+
+fn negamax(board: &Board, depth: u32, mut alpha: i32, beta: i32, cancelled: &AtomicBool) -> i32 {
+    if depth == 0 || cancelled.load(Ordering::Relaxed) {
+        return evaluate(board);
+    }
+    let moves = ordered_moves(board);
+    if moves.is_empty() {
+        // Mates sooner, with more depth left, score further from zero
+        return if is_king_in_check(board, board.turn) {
+            -MATE_SCORE - depth as i32
+        } else {
+            0
+        };
+    }
+    for chess_move in moves {
+        let mut next_board = *board;
+        next_board.make_move_unchecked(chess_move);
+        let score = -negamax(&next_board, depth - 1, -beta, -alpha, cancelled);
+        if score >= beta {
+            return beta;
+        }
+        alpha = alpha.max(score);
+    }
+    alpha
+}
+
+/// Legal moves with captures and promotions first, which prunes more.
+fn ordered_moves(board: &Board) -> Vec<Move> {
+    let mut moves = board.legal_moves();
+    moves.sort_by_key(|chess_move| {
+        let captured = board
+            .piece_at_square(chess_move.to)
+            .map_or(0, |piece| piece_value(piece.kind));
+        let promoted = chess_move.promotion.map_or(0, piece_value);
+        -(captured + promoted)
+    });
+    moves
+}
+
+/// Material plus small bonuses for advanced pawns and central pieces, from
+/// the side to move's point of view.
+fn evaluate(board: &Board) -> i32 {
+    board
+        .pieces()
+        .map(|(square, piece)| {
+            let advance = match piece.color {
+                Color::Light => square.rank as i32,
+                Color::Dark => 7 - square.rank as i32,
+            };
+            let centrality = 3
+                - (square.file as i32 * 2 - 7)
+                    .abs()
+                    .max((square.rank as i32 * 2 - 7).abs())
+                    / 2;
+            let placement = match piece.kind {
+                PieceKind::Pawn => advance * 5,
+                PieceKind::Knight | PieceKind::Bishop => centrality * 8,
+                PieceKind::Queen => centrality * 2,
+                PieceKind::Rook | PieceKind::King => 0,
+            };
+            let score = piece_value(piece.kind) + placement;
+            if piece.color == board.turn {
+                score
+            } else {
+                -score
+            }
+        })
+        .sum()
+}
+
+fn piece_value(kind: PieceKind) -> i32 {
+    match kind {
+        PieceKind::Pawn => 100,
+        PieceKind::Knight => 320,
+        PieceKind::Bishop => 330,
+        PieceKind::Rook => 500,
+        PieceKind::Queen => 900,
+        PieceKind::King => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn finds_mate_in_one() {
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        assert_eq!(EnginePlayer::best_move(&board, 2), Move::parse("a1a8"));
+    }
+
+    #[test]
+    fn no_move_without_legal_moves() {
+        let board = Board::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(EnginePlayer::best_move(&board, 2), None);
+    }
+
+    #[test]
+    fn cancelled_search_stops_early() {
+        let board = Board::new(Color::Light);
+        let started = Instant::now();
+        let best_move = search(&board, 8, &AtomicBool::new(true));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(best_move.is_some());
+    }
+
+    #[test]
+    fn answers_for_the_current_position() {
+        let mut game = GameController::new(Board::new(Color::Light), None, Instant::now());
+        let mut player = EnginePlayer::new(4);
+        // Starts a search for White, most likely still running
+        let _ = player.poll_move(&game);
+        game.play(Move::parse("e2e4").unwrap(), Instant::now())
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let best_move = loop {
+            if let Some(best_move) = player.poll_move(&game).unwrap() {
+                break best_move;
+            }
+            assert!(Instant::now() < deadline);
+            thread::sleep(Duration::from_millis(5));
+        };
+        // A move for Black, not the stale search for White
+        assert!(game.board().legal_moves().contains(&best_move));
+    }
+}
@@ -0,0 +1,232 @@
+//! Who makes the moves of each side: someone at this window, the built-in
+//! engine, a UCI engine process, a peer over the network, a correspondence
+//! game file or a fixed list of moves. The game loop polls the player of the side to move every frame.
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::backend::{Color, GameChange, GameController, GameEvent, Move};
+use crate::correspondence::CorrespondenceError;
+use crate::protocol::ProtocolError;
+
+mod correspondence;
+mod engine;
+mod network;
+mod scripted;
+mod uci;
+
+pub use correspondence::CorrespondencePlayer;
+pub use engine::EnginePlayer;
+pub use network::NetworkPlayer;
+pub use scripted::ScriptedPlayer;
+pub use uci::UciPlayer;
+
+/// A source of moves for one side.
+pub trait Player {
+    /// For the panel and the PGN `White` and `Black` tags.
+    fn name(&self) -> String;
+
+    /// Whether moves come from the board input of this window.
+    fn is_local(&self) -> bool {
+        false
+    }
+
+    /// Asked while it is this player's turn, without blocking: the move once
+    /// it is decided, `None` until then.
+    fn poll_move(&mut self, game: &GameController) -> Result<Option<Move>, PlayerError>;
+
+    /// Asked every frame, whoever is to move: what else the player does,
+    /// like resigning or answering a draw offer.
+    fn poll_events(&mut self, _game: &GameController) -> Result<Vec<GameEvent>, PlayerError> {
+        Ok(Vec::new())
+    }
+
+    /// Told about every move played, by either side, after it is on the
+    /// board of `game`.
+    fn move_played(&mut self, _chess_move: Move, _game: &GameController) {}
+
+    /// The game started over or its moves were taken back.
+    fn position_reset(&mut self, _game: &GameController) {}
+
+    /// Told about the rest of what happens in the game, like draw offers and
+    /// its end.
+    fn game_changed(&mut self, _change: &GameChange, _game: &GameController) {}
+}
+
+/// Why a player could not come up with a move.
+#[derive(Debug)]
+pub enum PlayerError {
+    Io(io::Error),
+    /// The engine or peer said something that makes no sense here.
+    Protocol(String),
+    Disconnected,
+    /// The peer ended the connection, giving this reason.
+    Refused(String),
+    /// The side to move has no legal move, so nothing to search.
+    NoMove,
+    /// The engine picked a move that is not legal in the position.
+    IllegalMove(Move),
+    /// A scripted player ran out of moves.
+    OutOfMoves,
+    /// The correspondence game file could not be read or written.
+    Correspondence(CorrespondenceError),
+}
+
+impl fmt::Display for PlayerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayerError::Io(error) => write!(f, "{}", error),
+            PlayerError::Protocol(message) => write!(f, "protocol error: {}", message),
+            PlayerError::Disconnected => write!(f, "the other side disconnected"),
+            PlayerError::Refused(reason) => write!(f, "the other side refused: {}", reason),
+            PlayerError::NoMove => write!(f, "there is no move to make"),
+            PlayerError::IllegalMove(chess_move) => {
+                write!(
+                    f,
+                    "the engine played {}, which is not legal here",
+                    chess_move
+                )
+            }
+            PlayerError::OutOfMoves => write!(f, "the script has no more moves"),
+            PlayerError::Correspondence(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for PlayerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PlayerError::Io(error) => Some(error),
+            PlayerError::Correspondence(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PlayerError {
+    fn from(error: io::Error) -> Self {
+        PlayerError::Io(error)
+    }
+}
+
+impl From<ProtocolError> for PlayerError {
+    fn from(error: ProtocolError) -> Self {
+        match error {
+            ProtocolError::Io(error) => PlayerError::Io(error),
+            ProtocolError::Malformed(line) => PlayerError::Protocol(line),
+            ProtocolError::Closed => PlayerError::Disconnected,
+        }
+    }
+}
+
+impl From<CorrespondenceError> for PlayerError {
+    fn from(error: CorrespondenceError) -> Self {
+        PlayerError::Correspondence(error)
+    }
+}
+
+/// Someone at this window, moving pieces with the mouse.
+pub struct HumanPlayer;
+
+impl Player for HumanPlayer {
+    fn name(&self) -> String {
+        "Human".to_owned()
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    fn poll_move(&mut self, _game: &GameController) -> Result<Option<Move>, PlayerError> {
+        Ok(None)
+    }
+}
+
+/// Who plays at this window, by login name, as told to the other side.
+pub(crate) fn local_name() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default()
+}
+
+/// A player described on the command line or picked in the panel, written
+/// like `human`, `engine:3`, `uci:stockfish`, `script:e2e4,e7e5`,
+/// `connect:192.168.1.5:7878`, `listen:7878` or `correspondence:game.pgn`.
+#[derive(Eq, Hash, PartialEq, Debug, Clone)]
+pub enum PlayerSpec {
+    Human,
+    /// The built-in engine searching this many half-moves ahead.
+    Engine(u32),
+    /// A command starting a UCI engine, with its arguments.
+    Uci(String),
+    Script(Vec<Move>),
+    /// Play a peer listening at this address.
+    Connect(String),
+    /// Wait for a peer on this port or address.
+    Listen(String),
+    /// The opponent in the correspondence game kept in this file.
+    Correspondence(String),
+}
+
+impl PlayerSpec {
+    pub fn parse(text: &str) -> Option<PlayerSpec> {
+        let (kind, argument) = match text.split_once(':') {
+            Some((kind, argument)) => (kind, Some(argument)),
+            None => (text, None),
+        };
+        match (kind, argument) {
+            ("human", None) => Some(PlayerSpec::Human),
+            ("engine", None) => Some(PlayerSpec::Engine(EnginePlayer::DEFAULT_DEPTH)),
+            ("engine", Some(depth)) => depth.parse().ok().map(PlayerSpec::Engine),
+            ("uci", Some(command)) if !command.trim().is_empty() => {
+                Some(PlayerSpec::Uci(command.to_owned()))
+            }
+            ("script", Some(moves)) => moves
+                .split([',', ' '])
+                .filter(|name| !name.is_empty())
+                .map(Move::parse)
+                .collect::<Option<Vec<_>>>()
+                .map(PlayerSpec::Script),
+            ("connect", Some(address)) => Some(PlayerSpec::Connect(address.to_owned())),
+            ("listen", Some(address)) => Some(PlayerSpec::Listen(address.to_owned())),
+            ("correspondence", Some(path)) if !path.is_empty() => {
+                Some(PlayerSpec::Correspondence(path.to_owned()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Starts the player for `color`. Joining a network game connects right
+    /// away; hosting one only opens the port and waits for the peer.
+    pub fn create(&self, color: Color) -> Result<Box<dyn Player>, PlayerError> {
+        Ok(match self {
+            PlayerSpec::Human => Box::new(HumanPlayer),
+            PlayerSpec::Engine(depth) => Box::new(EnginePlayer::new(*depth)),
+            PlayerSpec::Uci(command) => Box::new(UciPlayer::start(command)?),
+            PlayerSpec::Script(moves) => Box::new(ScriptedPlayer::new(color, moves.clone())),
+            PlayerSpec::Connect(address) => Box::new(NetworkPlayer::join(address, color)?),
+            PlayerSpec::Listen(address) => Box::new(NetworkPlayer::host(address, color)?),
+            PlayerSpec::Correspondence(path) => {
+                Box::new(CorrespondencePlayer::open(Path::new(path), color)?)
+            }
+        })
+    }
+}
+
+impl fmt::Display for PlayerSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayerSpec::Human => write!(f, "human"),
+            PlayerSpec::Engine(depth) => write!(f, "engine:{}", depth),
+            PlayerSpec::Uci(command) => write!(f, "uci:{}", command),
+            PlayerSpec::Script(moves) => {
+                let names: Vec<String> = moves.iter().map(Move::to_string).collect();
+                write!(f, "script:{}", names.join(","))
+            }
+            PlayerSpec::Connect(address) => write!(f, "connect:{}", address),
+            PlayerSpec::Listen(address) => write!(f, "listen:{}", address),
+            PlayerSpec::Correspondence(path) => write!(f, "correspondence:{}", path),
+        }
+    }
+}
@@ -0,0 +1,851 @@
+use std::io::{self, ErrorKind};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backend::{Board, Color, GameChange, GameController, GameEvent, Move, Termination};
+use crate::protocol::{color_name, Connection, DrawAction, Message, PROTOCOL_VERSION};
+
+use super::{local_name, Player, PlayerError};
+
+/// How long to wait for a peer to acknowledge a move before sending it again.
+const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a joining player tries to reach the host at a time.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+/// Pause between attempts to reach the host again.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The other side of a game played over TCP with another instance of the
+/// app, speaking the messages of `crate::protocol`. Whoever drops out can
+/// come back: the host keeps listening, the joining side keeps trying to
+/// reconnect, and both compare positions before playing on.
+pub struct NetworkPlayer {
+    /// The side the peer plays.
+    color: Color,
+    role: Role,
+    link: Option<Link>,
+    peer_name: Option<String>,
+    /// Moves received, each from the position at its ply, to be played in
+    /// order. After a reconnect they can include lost moves of this side.
+    received_moves: Vec<(u16, Move)>,
+    /// Received moves handed to the game, acknowledged once played.
+    delivered_moves: Vec<(u16, Move)>,
+    /// Resignations, draw actions and positions from the peer, not played yet.
+    events: Vec<GameEvent>,
+    /// The last move sent until the peer acknowledges it, and when it went.
+    unacknowledged: Option<(u16, Move, Instant)>,
+    /// The peer's draw offer stands, so a draw by agreement is our accepting.
+    has_peer_offered_draw: bool,
+    /// The peer claimed the draw, which it already knows about.
+    has_peer_claimed_draw: bool,
+    /// A sync was sent and not answered yet: until then the peer may send
+    /// moves of either side, or a position to play on from.
+    is_awaiting_sync: bool,
+    /// The position was set up or taken back here, and is sent in answer to
+    /// the peer's sync.
+    has_position_to_hand_over: bool,
+    /// The last position taken over from the peer, which is not handed back.
+    taken_position: Option<String>,
+}
+
+enum Role {
+    /// Waits for the peer on a port.
+    Host(TcpListener),
+    /// Connects to the host at `address`, again after losing it.
+    Join {
+        address: String,
+        attempt: Option<Receiver<io::Result<TcpStream>>>,
+        next_attempt: Instant,
+    },
+}
+
+/// A connection, trusted once the peer said hello.
+struct Link {
+    connection: Connection,
+    is_greeted: bool,
+}
+
+impl NetworkPlayer {
+    /// Waits for the peer on `address`, a port alone meaning any local
+    /// address. The peer connects later, polling picks it up.
+    pub fn host(address: &str, color: Color) -> Result<NetworkPlayer, PlayerError> {
+        let address = if address.contains(':') {
+            address.to_owned()
+        } else {
+            format!("0.0.0.0:{}", address)
+        };
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(NetworkPlayer::new(Role::Host(listener), color))
+    }
+
+    /// Joins the host at `address`, like `192.168.1.5:7878`. The first
+    /// attempt blocks, so a wrong address shows up at once.
+    pub fn join(address: &str, color: Color) -> Result<NetworkPlayer, PlayerError> {
+        let stream = connect(address)?;
+        let mut player = NetworkPlayer::new(
+            Role::Join {
+                address: address.to_owned(),
+                attempt: None,
+                next_attempt: Instant::now(),
+            },
+            color,
+        );
+        player.open_link(stream)?;
+        Ok(player)
+    }
+
+    fn new(role: Role, color: Color) -> NetworkPlayer {
+        NetworkPlayer {
+            color,
+            role,
+            link: None,
+            peer_name: None,
+            received_moves: Vec::new(),
+            delivered_moves: Vec::new(),
+            events: Vec::new(),
+            unacknowledged: None,
+            has_peer_offered_draw: false,
+            has_peer_claimed_draw: false,
+            is_awaiting_sync: false,
+            has_position_to_hand_over: false,
+            taken_position: None,
+        }
+    }
+
+    /// Whether the peer is connected and said hello.
+    pub fn is_connected(&self) -> bool {
+        self.link.as_ref().is_some_and(|link| link.is_greeted)
+    }
+
+    fn open_link(&mut self, stream: TcpStream) -> Result<(), PlayerError> {
+        // A peer coming back replaces a connection not yet found dead
+        let mut connection = Connection::new(stream)?;
+        connection.send(&Message::Hello {
+            version: PROTOCOL_VERSION,
+            color: self.color.opposite(),
+            name: local_name(),
+        })?;
+        self.link = Some(Link {
+            connection,
+            is_greeted: false,
+        });
+        Ok(())
+    }
+
+    /// Sends `message` if the peer is there to hear it. Whatever it misses
+    /// is made up for when it comes back.
+    fn send(&mut self, message: Message) {
+        if let Some(link) = self.link.as_mut().filter(|link| link.is_greeted) {
+            if link.connection.send(&message).is_err() {
+                self.link = None;
+            }
+        }
+    }
+
+    /// Asks the peer to compare positions, taking what it answers.
+    fn request_sync(&mut self, game: &GameController) {
+        self.is_awaiting_sync = true;
+        self.send(Message::Sync {
+            fen: game.board().to_fen(),
+        });
+    }
+
+    /// Sends the position on the board, announcing it or answering the
+    /// peer's sync.
+    fn send_position(&mut self, game: &GameController) {
+        self.send(Message::Position {
+            fen: game.board().to_fen(),
+        });
+    }
+
+    /// Takes new connections and reads what the peer sent.
+    fn pump(&mut self, game: &GameController) -> Result<(), PlayerError> {
+        if let Some(stream) = self.accept_peer()? {
+            self.open_link(stream)?;
+        }
+        loop {
+            let message = match self.link.as_mut().map(|link| link.connection.try_receive()) {
+                None | Some(Ok(None)) => break,
+                Some(Ok(Some(message))) => message,
+                Some(Err(error)) => {
+                    self.link = None;
+                    return Err(error.into());
+                }
+            };
+            if let Err(error) = self.receive(message, game) {
+                if let (Some(link), PlayerError::Protocol(reason)) = (&mut self.link, &error) {
+                    let _ = link.connection.send(&Message::Error(reason.clone()));
+                }
+                self.link = None;
+                return Err(error);
+            }
+            // The position is about to be replaced, later messages wait for it
+            if self
+                .events
+                .iter()
+                .any(|event| matches!(event, GameEvent::NewGame { .. }))
+            {
+                break;
+            }
+        }
+        if let Some((ply, chess_move, sent_at)) = self.unacknowledged {
+            if sent_at.elapsed() >= ACK_TIMEOUT {
+                self.unacknowledged = Some((ply, chess_move, Instant::now()));
+                self.send(Message::Move { ply, chess_move });
+            }
+        }
+        Ok(())
+    }
+
+    /// A peer connecting to the host, or the joining side getting through.
+    fn accept_peer(&mut self) -> Result<Option<TcpStream>, PlayerError> {
+        let has_link = self.link.is_some();
+        match &mut self.role {
+            Role::Host(listener) => match listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false)?;
+                    Ok(Some(stream))
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(None),
+                Err(error) => Err(error.into()),
+            },
+            Role::Join { .. } if has_link => Ok(None),
+            Role::Join {
+                address,
+                attempt,
+                next_attempt,
+            } => {
+                let Some(receiver) = attempt else {
+                    if Instant::now() >= *next_attempt {
+                        // Connecting can take seconds, so not on this thread
+                        let (sender, receiver) = mpsc::channel();
+                        let address = address.clone();
+                        thread::spawn(move || {
+                            let _ = sender.send(connect(&address));
+                        });
+                        *attempt = Some(receiver);
+                    }
+                    return Ok(None);
+                };
+                match receiver.try_recv() {
+                    Ok(Ok(stream)) => {
+                        *attempt = None;
+                        Ok(Some(stream))
+                    }
+                    Err(TryRecvError::Empty) => Ok(None),
+                    Ok(Err(_)) | Err(TryRecvError::Disconnected) => {
+                        *attempt = None;
+                        *next_attempt = Instant::now() + RECONNECT_INTERVAL;
+                        Ok(None)
+                    }
+                }
+            }
+        }
+    }
+
+    fn receive(&mut self, message: Message, game: &GameController) -> Result<(), PlayerError> {
+        let is_greeted = self.link.as_ref().is_some_and(|link| link.is_greeted);
+        match message {
+            Message::Hello {
+                version,
+                color,
+                name,
+            } => {
+                if version != PROTOCOL_VERSION {
+                    return Err(PlayerError::Protocol(format!(
+                        "protocol version {}, expected {}",
+                        version, PROTOCOL_VERSION
+                    )));
+                }
+                if color != self.color {
+                    return Err(PlayerError::Protocol(format!(
+                        "both sides play {}",
+                        color_name(color)
+                    )));
+                }
+                if let Some(link) = &mut self.link {
+                    link.is_greeted = true;
+                }
+                if !name.is_empty() {
+                    self.peer_name = Some(name);
+                }
+                if self.has_position_to_hand_over {
+                    // Set here while the peer was away, so nothing to compare
+                    self.is_awaiting_sync = false;
+                    self.send_position(game);
+                } else {
+                    self.request_sync(game);
+                }
+            }
+            Message::Error(reason) => return Err(PlayerError::Refused(reason)),
+            message if !is_greeted => {
+                return Err(PlayerError::Protocol(format!("{} before hello", message)))
+            }
+            Message::Move { ply, chess_move } => {
+                let current_ply = game.board().number_of_moves;
+                let next_ply = current_ply + self.received_moves.len() as u16;
+                if self.has_position_to_hand_over {
+                    // Played from a position the peer is about to leave
+                } else if ply == next_ply {
+                    let turn = game.board().turn;
+                    let mover = if (ply - current_ply).is_multiple_of(2) {
+                        turn
+                    } else {
+                        turn.opposite()
+                    };
+                    // Moves of this side only come in answer to its sync
+                    if mover != self.color && !self.is_awaiting_sync {
+                        return Err(PlayerError::Protocol(format!(
+                            "a move for {}",
+                            color_name(mover)
+                        )));
+                    }
+                    self.received_moves.push((ply, chess_move));
+                } else if ply < current_ply
+                    && history(game).iter().any(|(board, played)| {
+                        board.number_of_moves == ply && *played == Some(chess_move)
+                    })
+                {
+                    // Sent again before our acknowledgement got through
+                    self.send(Message::Ack { ply });
+                } else if self.received_moves.contains(&(ply, chess_move)) {
+                    // Sent again while waiting to be played
+                } else {
+                    // Moves went missing or the games went apart
+                    self.request_sync(game);
+                }
+            }
+            Message::Ack { ply } => {
+                if self
+                    .unacknowledged
+                    .is_some_and(|(sent_ply, _, _)| sent_ply == ply)
+                {
+                    self.unacknowledged = None;
+                }
+            }
+            Message::Sync { .. } if self.has_position_to_hand_over => {
+                self.is_awaiting_sync = false;
+                self.send_position(game);
+                self.has_position_to_hand_over = self.link.is_none();
+            }
+            Message::Sync { fen } => self.synchronize(&fen, game)?,
+            Message::Position { fen } if self.is_awaiting_sync => {
+                self.is_awaiting_sync = false;
+                // Where the moves sent along lead to, or one to play on from
+                let mut board = *game.board();
+                for (_, chess_move) in &self.received_moves {
+                    if board.try_make_move(*chess_move).is_err() {
+                        break;
+                    }
+                }
+                if fen == board.to_fen() {
+                    return Ok(());
+                }
+                let board = Board::from_fen(&fen)
+                    .map_err(|error| PlayerError::Protocol(format!("{}: {}", fen, error)))?;
+                self.received_moves.clear();
+                self.unacknowledged = None;
+                self.taken_position = Some(fen);
+                self.events.push(GameEvent::NewGame {
+                    start: Box::new(board),
+                    time_control: game.clock().map(|clock| clock.control().clone()),
+                });
+            }
+            Message::Position { .. } => {
+                // Announced out of the blue, so taken only once the sync that
+                // asks for it is answered. When both sides set one up at the
+                // same time, the host's wins.
+                if !(self.has_position_to_hand_over && matches!(self.role, Role::Host(_))) {
+                    self.has_position_to_hand_over = false;
+                    self.request_sync(game);
+                }
+            }
+            Message::Resign => self.events.push(GameEvent::Resign(self.color)),
+            Message::Draw(action) => self.events.push(match action {
+                DrawAction::Offer => {
+                    self.has_peer_offered_draw = true;
+                    GameEvent::OfferDraw(self.color)
+                }
+                DrawAction::Accept => GameEvent::AcceptDraw(self.color),
+                DrawAction::Decline => GameEvent::DeclineDraw(self.color),
+                DrawAction::Claim => {
+                    self.has_peer_claimed_draw = true;
+                    GameEvent::ClaimDraw
+                }
+            }),
+        }
+        Ok(())
+    }
+
+    /// Catches up with the peer standing at `fen`. Whoever is ahead sends
+    /// the moves the other missed and where it stands; games that went apart
+    /// continue from the position further on, or the host's when level.
+    fn synchronize(&mut self, fen: &str, game: &GameController) -> Result<(), PlayerError> {
+        let peer_board = Board::from_fen(fen)
+            .map_err(|error| PlayerError::Protocol(format!("{}: {}", fen, error)))?;
+        let peer_ply = peer_board.number_of_moves;
+        let current_ply = game.board().number_of_moves;
+        let is_level = peer_ply == current_ply;
+        if peer_ply > current_ply {
+            // The peer is ahead and does the comparing, once asked
+            if !self.is_awaiting_sync {
+                self.request_sync(game);
+            }
+            return Ok(());
+        }
+        let history = history(game);
+        match history
+            .iter()
+            .position(|(board, _)| board.number_of_moves == peer_ply && board.to_fen() == fen)
+        {
+            Some(common) => {
+                for (board, played) in &history[common..] {
+                    if let Some(chess_move) = played {
+                        self.send(Message::Move {
+                            ply: board.number_of_moves,
+                            chess_move: *chess_move,
+                        });
+                    }
+                }
+                self.send_position(game);
+                // A peer behind has nothing to answer with
+                if !is_level {
+                    self.is_awaiting_sync = false;
+                }
+            }
+            // Both notice when level, only the host answers
+            None if is_level && matches!(self.role, Role::Join { .. }) => {
+                if !self.is_awaiting_sync {
+                    self.request_sync(game);
+                }
+            }
+            None => {
+                self.is_awaiting_sync = false;
+                self.send_position(game);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Player for NetworkPlayer {
+    fn name(&self) -> String {
+        match (&self.peer_name, &self.link, &self.role) {
+            (Some(name), _, _) => name.clone(),
+            (None, Some(link), _) => link.connection.peer().to_owned(),
+            (None, None, Role::Join { address, .. }) => address.clone(),
+            (None, None, Role::Host(_)) => "Network".to_owned(),
+        }
+    }
+
+    fn poll_move(&mut self, game: &GameController) -> Result<Option<Move>, PlayerError> {
+        // Moves come with the events, as those caught up on can be either side's
+        self.pump(game)?;
+        Ok(None)
+    }
+
+    fn poll_events(&mut self, game: &GameController) -> Result<Vec<GameEvent>, PlayerError> {
+        self.pump(game)?;
+        let mut events = Vec::new();
+        let mut next_ply = game.board().number_of_moves;
+        for (ply, chess_move) in self.received_moves.drain(..) {
+            if ply == next_ply {
+                events.push(GameEvent::Move(chess_move));
+                self.delivered_moves.push((ply, chess_move));
+                next_ply += 1;
+            }
+        }
+        events.append(&mut self.events);
+        Ok(events)
+    }
+
+    fn move_played(&mut self, chess_move: Move, game: &GameController) {
+        // Any move lets a draw offer lapse
+        self.has_peer_offered_draw = false;
+        self.taken_position = None;
+        let ply = game.board().number_of_moves.saturating_sub(1);
+        if let Some(index) = self
+            .delivered_moves
+            .iter()
+            .position(|delivered| *delivered == (ply, chess_move))
+        {
+            self.delivered_moves.drain(..=index);
+            self.send(Message::Ack { ply });
+        } else {
+            self.delivered_moves.clear();
+            self.is_awaiting_sync = false;
+            self.unacknowledged = Some((ply, chess_move, Instant::now()));
+            self.send(Message::Move { ply, chess_move });
+        }
+    }
+
+    fn position_reset(&mut self, game: &GameController) {
+        self.received_moves.clear();
+        self.delivered_moves.clear();
+        self.unacknowledged = None;
+        self.has_peer_offered_draw = false;
+        self.has_peer_claimed_draw = false;
+        if self.taken_position.as_deref() == Some(&game.board().to_fen()) {
+            return;
+        }
+        // The peer takes over positions set up or taken back here, asking
+        // for them with a sync
+        self.is_awaiting_sync = false;
+        self.has_position_to_hand_over = true;
+        self.send_position(game);
+    }
+
+    fn game_changed(&mut self, change: &GameChange, _game: &GameController) {
+        match change {
+            GameChange::DrawOffered(color) if *color != self.color => {
+                self.send(Message::Draw(DrawAction::Offer))
+            }
+            GameChange::DrawDeclined(color) if *color != self.color => {
+                self.has_peer_offered_draw = false;
+                self.send(Message::Draw(DrawAction::Decline));
+            }
+            GameChange::Ended(outcome) => {
+                let message = match outcome.termination {
+                    Termination::Resignation if outcome.winner == Some(self.color) => {
+                        Some(Message::Resign)
+                    }
+                    Termination::Agreement if self.has_peer_offered_draw => {
+                        Some(Message::Draw(DrawAction::Accept))
+                    }
+                    Termination::Repetition | Termination::FiftyMoves
+                        if !self.has_peer_claimed_draw =>
+                    {
+                        Some(Message::Draw(DrawAction::Claim))
+                    }
+                    _ => None,
+                };
+                self.has_peer_offered_draw = false;
+                self.has_peer_claimed_draw = false;
+                if let Some(message) = message {
+                    self.send(message);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Positions of the game from its start, each with the move played from it.
+fn history(game: &GameController) -> Vec<(Board, Option<Move>)> {
+    let mut board = *game.initial_board();
+    let mut history = Vec::with_capacity(game.record().moves.len() + 1);
+    for recorded_move in &game.record().moves {
+        history.push((board, Some(recorded_move.chess_move)));
+        board.make_move_unchecked(recorded_move.chess_move);
+    }
+    history.push((board, None));
+    history
+}
+
+fn connect(address: &str) -> io::Result<TcpStream> {
+    let socket_address = address.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(ErrorKind::NotFound, format!("no address for {}", address))
+    })?;
+    TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+
+    use super::*;
+    use crate::backend::{Termination, STARTING_FEN};
+
+    /// One end of the link: a game and the player standing for the peer,
+    /// told about changes the way the window does.
+    struct Side {
+        game: GameController,
+        player: NetworkPlayer,
+    }
+
+    impl Side {
+        fn new(player: NetworkPlayer) -> Side {
+            let start = Board::from_fen(STARTING_FEN).unwrap();
+            Side {
+                game: GameController::new(start, None, Instant::now()),
+                player,
+            }
+        }
+
+        fn handle(&mut self, event: GameEvent) {
+            for change in self.game.handle(event, Instant::now()) {
+                match &change {
+                    GameChange::Played { chess_move, .. } => {
+                        self.player.move_played(*chess_move, &self.game)
+                    }
+                    GameChange::PositionChanged { .. } => self.player.position_reset(&self.game),
+                    _ => self.player.game_changed(&change, &self.game),
+                }
+            }
+        }
+
+        fn poll(&mut self) -> Result<(), PlayerError> {
+            for event in self.player.poll_events(&self.game)? {
+                self.handle(event);
+            }
+            Ok(())
+        }
+
+        fn fen(&self) -> String {
+            self.game.board().to_fen()
+        }
+    }
+
+    fn chess_move(name: &str) -> Move {
+        Move::parse(name).unwrap()
+    }
+
+    fn host() -> (Side, String) {
+        let player = NetworkPlayer::host("127.0.0.1:0", Color::Dark).unwrap();
+        let Role::Host(listener) = &player.role else {
+            unreachable!()
+        };
+        let address = listener.local_addr().unwrap().to_string();
+        (Side::new(player), address)
+    }
+
+    /// A host playing White and a joiner playing Black, greeted.
+    fn connected() -> (Side, Side) {
+        let (mut host, address) = host();
+        let mut joiner = Side::new(NetworkPlayer::join(&address, Color::Light).unwrap());
+        exchange(&mut host, &mut joiner, |host, joiner| {
+            host.player.is_connected() && joiner.player.is_connected()
+        });
+        (host, joiner)
+    }
+
+    /// Polls both sides until `is_done`, taking lost connections in stride.
+    fn exchange(host: &mut Side, joiner: &mut Side, is_done: impl Fn(&Side, &Side) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !is_done(host, joiner) {
+            assert!(Instant::now() < deadline, "the sides did not get there");
+            for side in [&mut *host, &mut *joiner] {
+                match side.poll() {
+                    Ok(()) | Err(PlayerError::Disconnected) => (),
+                    Err(error) => panic!("{}", error),
+                }
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn poll_until_error(side: &mut Side) -> PlayerError {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            match side.poll() {
+                Ok(()) if Instant::now() < deadline => thread::sleep(Duration::from_millis(5)),
+                Ok(()) => panic!("no error"),
+                Err(error) => return error,
+            }
+        }
+    }
+
+    /// A hand-written peer playing Black, greeted by `host`.
+    fn raw_peer(host: &mut Side, address: &str) -> (TcpStream, BufReader<TcpStream>) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        writeln!(stream, "hello {} black bob", PROTOCOL_VERSION).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !host.player.is_connected() {
+            assert!(Instant::now() < deadline, "the host did not take the peer");
+            host.poll().unwrap();
+            thread::sleep(Duration::from_millis(5));
+        }
+        let reader = BufReader::new(stream.try_clone().unwrap());
+        (stream, reader)
+    }
+
+    /// Reads lines up to the first one starting with `prefix`.
+    fn read_until(reader: &mut BufReader<TcpStream>, prefix: &str) -> String {
+        loop {
+            let mut line = String::new();
+            assert!(reader.read_line(&mut line).unwrap() > 0, "no {}", prefix);
+            if line.starts_with(prefix) {
+                return line.trim_end().to_owned();
+            }
+        }
+    }
+
+    #[test]
+    fn host_and_joiner_greet_each_other() {
+        let (mut host, mut joiner) = connected();
+        // Level, so each answers the other's sync with the same position
+        exchange(&mut host, &mut joiner, |host, joiner| {
+            !host.player.is_awaiting_sync && !joiner.player.is_awaiting_sync
+        });
+        assert_eq!(host.fen(), joiner.fen());
+        assert_eq!(host.game.record().moves.len(), 0);
+    }
+
+    #[test]
+    fn another_protocol_version_is_turned_away() {
+        let (mut host, address) = host();
+        let mut peer = TcpStream::connect(&address).unwrap();
+        writeln!(peer, "hello 99 black bob").unwrap();
+        let error = poll_until_error(&mut host);
+        assert!(
+            matches!(&error, PlayerError::Protocol(reason) if reason == "protocol version 99, expected 1"),
+            "{}",
+            error
+        );
+        assert!(!host.player.is_connected());
+        let lines: Vec<String> = BufReader::new(peer).lines().map_while(Result::ok).collect();
+        assert!(lines[0].starts_with("hello 1 white"));
+        assert_eq!(
+            lines.last().unwrap(),
+            "error protocol version 99, expected 1"
+        );
+    }
+
+    #[test]
+    fn moves_are_played_and_acknowledged() {
+        let (mut host, mut joiner) = connected();
+        host.handle(GameEvent::Move(chess_move("e2e4")));
+        assert!(host.player.unacknowledged.is_some());
+        exchange(&mut host, &mut joiner, |host, joiner| {
+            joiner.game.board().number_of_moves == 1 && host.player.unacknowledged.is_none()
+        });
+        joiner.handle(GameEvent::Move(chess_move("e7e5")));
+        exchange(&mut host, &mut joiner, |host, joiner| {
+            host.game.board().number_of_moves == 2 && joiner.player.unacknowledged.is_none()
+        });
+        assert_eq!(host.fen(), joiner.fen());
+    }
+
+    #[test]
+    fn a_move_is_sent_again_when_its_ack_is_lost() {
+        let (mut host, mut joiner) = connected();
+        host.handle(GameEvent::Move(chess_move("e2e4")));
+        exchange(&mut host, &mut joiner, |host, _| {
+            host.player.unacknowledged.is_none()
+        });
+        // As if the acknowledgement never came
+        let sent_at = Instant::now().checked_sub(ACK_TIMEOUT).unwrap();
+        host.player.unacknowledged = Some((0, chess_move("e2e4"), sent_at));
+        exchange(&mut host, &mut joiner, |host, _| {
+            host.player.unacknowledged.is_none()
+        });
+        assert_eq!(joiner.game.board().number_of_moves, 1);
+        assert_eq!(host.fen(), joiner.fen());
+    }
+
+    #[test]
+    fn resigning_ends_the_game_on_both_sides() {
+        let (mut host, mut joiner) = connected();
+        joiner.handle(GameEvent::Resign(Color::Dark));
+        exchange(&mut host, &mut joiner, |host, _| host.game.is_over());
+        let outcome = host.game.outcome().unwrap();
+        assert_eq!(outcome.termination, Termination::Resignation);
+        assert_eq!(outcome.winner, Some(Color::Light));
+    }
+
+    #[test]
+    fn a_draw_offer_is_accepted_across() {
+        let (mut host, mut joiner) = connected();
+        host.handle(GameEvent::OfferDraw(Color::Light));
+        exchange(&mut host, &mut joiner, |_, joiner| {
+            joiner.game.draw_offer() == Some(Color::Light)
+        });
+        joiner.handle(GameEvent::AcceptDraw(Color::Dark));
+        exchange(&mut host, &mut joiner, |host, _| host.game.is_over());
+        for side in [&host, &joiner] {
+            let outcome = side.game.outcome().unwrap();
+            assert_eq!(outcome.termination, Termination::Agreement);
+            assert_eq!(outcome.winner, None);
+        }
+    }
+
+    #[test]
+    fn moves_missed_while_away_are_caught_up_on() {
+        let (mut host, mut joiner) = connected();
+        host.handle(GameEvent::Move(chess_move("e2e4")));
+        exchange(&mut host, &mut joiner, |host, joiner| {
+            joiner.game.board().number_of_moves == 1 && host.player.unacknowledged.is_none()
+        });
+        joiner.player.link = None;
+        // Found dead by the host before the joiner comes back
+        let error = poll_until_error(&mut host);
+        assert!(matches!(error, PlayerError::Disconnected), "{}", error);
+        assert!(host.player.link.is_none());
+        joiner.handle(GameEvent::Move(chess_move("e7e5")));
+        exchange(&mut host, &mut joiner, |host, joiner| {
+            host.game.board().number_of_moves == 2
+                && joiner.player.unacknowledged.is_none()
+                && host.player.is_connected()
+        });
+        assert_eq!(host.fen(), joiner.fen());
+        // Play goes on as before
+        host.handle(GameEvent::Move(chess_move("g1f3")));
+        exchange(&mut host, &mut joiner, |_, joiner| {
+            joiner.game.board().number_of_moves == 3
+        });
+    }
+
+    #[test]
+    fn a_take_back_is_handed_over_through_a_sync() {
+        let (mut host, mut joiner) = connected();
+        host.handle(GameEvent::Move(chess_move("e2e4")));
+        exchange(&mut host, &mut joiner, |_, joiner| {
+            joiner.game.board().number_of_moves == 1
+        });
+        host.handle(GameEvent::Undo);
+        exchange(&mut host, &mut joiner, |host, joiner| {
+            joiner.game.board().number_of_moves == 0 && !host.player.has_position_to_hand_over
+        });
+        assert_eq!(host.fen(), joiner.fen());
+        assert!(!joiner.player.has_position_to_hand_over);
+    }
+
+    #[test]
+    fn moves_for_this_side_are_refused_outside_a_sync() {
+        let (mut host, address) = host();
+        let (mut peer, _reader) = raw_peer(&mut host, &address);
+        host.handle(GameEvent::Move(chess_move("e2e4")));
+        writeln!(peer, "move 1 e7e5\nmove 2 g1f3").unwrap();
+        let error = poll_until_error(&mut host);
+        assert!(
+            matches!(&error, PlayerError::Protocol(reason) if reason == "a move for white"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn a_position_sent_unasked_waits_for_a_sync() {
+        let (mut host, address) = host();
+        let (mut peer, mut reader) = raw_peer(&mut host, &address);
+        read_until(&mut reader, "sync ");
+        host.handle(GameEvent::Move(chess_move("e2e4")));
+        let before = host.fen();
+        let fen = "4k3/8/8/8/8/8/8/4K3 w - - 0 1";
+        writeln!(peer, "position {}", fen).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !host.player.is_awaiting_sync {
+            assert!(Instant::now() < deadline, "the host did not ask for a sync");
+            host.poll().unwrap();
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(host.fen(), before);
+        assert_eq!(read_until(&mut reader, "sync "), format!("sync {}", before));
+        writeln!(peer, "position {}", fen).unwrap();
+        while host.fen() != fen {
+            assert!(
+                Instant::now() < deadline,
+                "the host did not take the position"
+            );
+            host.poll().unwrap();
+            thread::sleep(Duration::from_millis(5));
+        }
+        // Taken over, so not announced back
+        assert!(!host.player.has_position_to_hand_over);
+    }
+}
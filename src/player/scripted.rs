@@ -0,0 +1,49 @@
+use crate::backend::{Color, GameController, Move};
+
+use super::{Player, PlayerError};
+
+/// Plays a fixed list of moves for `color` in order, e.g. to replay an
+/// opening or to drive a test.
+pub struct ScriptedPlayer {
+    color: Color,
+    moves: Vec<Move>,
+    next_move: usize,
+}
+
+impl ScriptedPlayer {
+    pub fn new(color: Color, moves: Vec<Move>) -> ScriptedPlayer {
+        ScriptedPlayer {
+            color,
+            moves,
+            next_move: 0,
+        }
+    }
+}
+
+impl Player for ScriptedPlayer {
+    fn name(&self) -> String {
+        "Script".to_owned()
+    }
+
+    fn poll_move(&mut self, _game: &GameController) -> Result<Option<Move>, PlayerError> {
+        let chess_move = *self
+            .moves
+            .get(self.next_move)
+            .ok_or(PlayerError::OutOfMoves)?;
+        self.next_move += 1;
+        Ok(Some(chess_move))
+    }
+
+    fn position_reset(&mut self, game: &GameController) {
+        // Go on after the moves of this side still on the record
+        let moves_first = game.initial_board().turn == self.color;
+        let moves_made = game
+            .record()
+            .moves
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| index.is_multiple_of(2) == moves_first)
+            .count();
+        self.next_move = moves_made.min(self.moves.len());
+    }
+}
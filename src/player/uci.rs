@@ -0,0 +1,292 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backend::{Board, Color, GameController, Move};
+
+use super::{Player, PlayerError};
+
+/// How long an engine gets to answer `uci` and `isready`.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Thinking time per move in untimed games.
+const MOVE_TIME: Duration = Duration::from_secs(1);
+
+/// An engine speaking UCI in a child process, like Stockfish.
+pub struct UciPlayer {
+    name: String,
+    process: Child,
+    input: ChildStdin,
+    /// Lines the engine writes, read on a thread of its own.
+    output: Receiver<String>,
+    /// FEN of the position the engine is thinking about.
+    thinking_about: Option<String>,
+    /// Searches stopped for a position that changed, whose `bestmove` is
+    /// still to come and ignored.
+    stale_searches: usize,
+    /// FEN of a position whose `bestmove` was not legal, not searched again
+    /// until the position changes.
+    refused_position: Option<String>,
+}
+
+impl UciPlayer {
+    /// Runs `command`, split on whitespace into the program and its
+    /// arguments, and waits for the engine to be ready.
+    pub fn start(command: &str) -> Result<UciPlayer, PlayerError> {
+        let mut words = command.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| PlayerError::Protocol("empty engine command".to_owned()))?;
+        let mut process = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let (input, stdout) = match (process.stdin.take(), process.stdout.take()) {
+            (Some(input), Some(stdout)) => (input, stdout),
+            _ => return Err(PlayerError::Disconnected),
+        };
+        let (sender, output) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut player = UciPlayer {
+            name: program.to_owned(),
+            process,
+            input,
+            output,
+            thinking_about: None,
+            stale_searches: 0,
+            refused_position: None,
+        };
+        player.send("uci")?;
+        player.wait_for("uciok")?;
+        player.send("isready")?;
+        player.wait_for("readyok")?;
+        Ok(player)
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), PlayerError> {
+        writeln!(self.input, "{}", command)?;
+        self.input.flush()?;
+        Ok(())
+    }
+
+    /// Reads lines up to `expected`, picking up the engine's name on the way.
+    fn wait_for(&mut self, expected: &str) -> Result<(), PlayerError> {
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let line = match self.output.recv_timeout(timeout) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(PlayerError::Protocol(format!(
+                        "no {} from the engine",
+                        expected
+                    )))
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(PlayerError::Disconnected),
+            };
+            if let Some(name) = line.strip_prefix("id name ") {
+                self.name = name.trim().to_owned();
+            }
+            if line.trim() == expected {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl Player for UciPlayer {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn poll_move(&mut self, game: &GameController) -> Result<Option<Move>, PlayerError> {
+        let position = game.board().to_fen();
+        if self.refused_position.as_ref() == Some(&position) {
+            return Ok(None);
+        }
+        self.refused_position = None;
+        if self.thinking_about.as_ref() != Some(&position) {
+            if self.thinking_about.is_some() {
+                // The position changed under the engine, its answer is stale
+                self.send("stop")?;
+                self.stale_searches += 1;
+            }
+            let mut command = format!("position fen {}", without_castling(game.initial_board()));
+            if !game.record().moves.is_empty() {
+                command.push_str(" moves");
+                for recorded_move in &game.record().moves {
+                    command.push_str(&format!(" {}", recorded_move.chess_move));
+                }
+            }
+            self.send(&command)?;
+            let go = match game.clock() {
+                Some(clock) => {
+                    let now = Instant::now();
+                    format!(
+                        "go wtime {} btime {}",
+                        clock.remaining(Color::Light, now).as_millis(),
+                        clock.remaining(Color::Dark, now).as_millis()
+                    )
+                }
+                None => format!("go movetime {}", MOVE_TIME.as_millis()),
+            };
+            self.send(&go)?;
+            self.thinking_about = Some(position.clone());
+        }
+
+        loop {
+            let line = match self.output.try_recv() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => return Err(PlayerError::Disconnected),
+            };
+            let mut words = line.split_whitespace();
+            if words.next() != Some("bestmove") {
+                continue;
+            }
+            let best_move = words.next().unwrap_or_default();
+            if self.stale_searches > 0 {
+                self.stale_searches -= 1;
+                continue;
+            }
+            self.thinking_about = None;
+            return match Move::parse(best_move) {
+                Some(chess_move) if game.board().legal_moves().contains(&chess_move) => {
+                    Ok(Some(chess_move))
+                }
+                // Asking again would get the same answer
+                Some(chess_move) => {
+                    self.refused_position = Some(position);
+                    Err(PlayerError::IllegalMove(chess_move))
+                }
+                None if best_move == "(none)" || best_move == "0000" => Err(PlayerError::NoMove),
+                None => Err(PlayerError::Protocol(line.clone())),
+            };
+        }
+    }
+}
+
+/// FEN of `board` without castling rights: castling is not a move here yet,
+/// so an engine must not pick it.
+fn without_castling(board: &Board) -> String {
+    let fen = board.to_fen();
+    let mut fields: Vec<&str> = fen.split(' ').collect();
+    if let Some(castling) = fields.get_mut(2) {
+        *castling = "-";
+    }
+    fields.join(" ")
+}
+
+impl Drop for UciPlayer {
+    fn drop(&mut self) {
+        // Give the engine a moment to quit by itself before insisting
+        if self.send("quit").is_ok() {
+            for _ in 0..10 {
+                if let Ok(Some(_)) = self.process.try_wait() {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A shell script playing an engine that logs what it is sent and always
+    /// answers `bestmove`.
+    fn fake_engine(name: &str, best_move: &str) -> (PathBuf, PathBuf) {
+        let directory =
+            std::env::temp_dir().join(format!("chess-uci-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let log = directory.join("log");
+        let script = directory.join("engine.sh");
+        fs::write(
+            &script,
+            format!(
+                r#"while read -r line; do
+  echo "$line" >> "{log}"
+  case "$line" in
+    uci) echo "id name Fake"; echo uciok ;;
+    isready) echo readyok ;;
+    go*) echo "bestmove {best_move}" ;;
+    quit) exit 0 ;;
+  esac
+done
+"#,
+                log = log.display(),
+                best_move = best_move
+            ),
+        )
+        .unwrap();
+        (script, log)
+    }
+
+    fn poll(player: &mut UciPlayer, game: &GameController) -> Result<Option<Move>, PlayerError> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            match player.poll_move(game) {
+                Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(5)),
+                result => return result,
+            }
+        }
+    }
+
+    #[test]
+    fn engine_moves_are_played() {
+        let (script, log) = fake_engine("legal", "e2e4");
+        let mut player = UciPlayer::start(&format!("sh {}", script.display())).unwrap();
+        assert_eq!(player.name(), "Fake");
+        let game = GameController::new(Board::new(Color::Light), None, Instant::now());
+        assert_eq!(poll(&mut player, &game).unwrap(), Move::parse("e2e4"));
+        drop(player);
+        let sent = fs::read_to_string(&log).unwrap();
+        // No castling rights, as castling cannot be played
+        assert!(
+            sent.contains("position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1"),
+            "{}",
+            sent
+        );
+        let _ = fs::remove_dir_all(script.parent().unwrap());
+    }
+
+    #[test]
+    fn illegal_engine_move_is_reported_once() {
+        let (script, log) = fake_engine("illegal", "e1g1");
+        let mut player = UciPlayer::start(&format!("sh {}", script.display())).unwrap();
+        let game = GameController::new(Board::new(Color::Light), None, Instant::now());
+        assert!(matches!(
+            poll(&mut player, &game),
+            Err(PlayerError::IllegalMove(chess_move)) if Move::parse("e1g1") == Some(chess_move)
+        ));
+        // The engine is not asked again for the same position
+        for _ in 0..20 {
+            assert!(matches!(player.poll_move(&game), Ok(None)));
+            thread::sleep(Duration::from_millis(5));
+        }
+        drop(player);
+        let sent = fs::read_to_string(&log).unwrap();
+        assert_eq!(
+            sent.lines().filter(|line| line.starts_with("go")).count(),
+            1
+        );
+        let _ = fs::remove_dir_all(script.parent().unwrap());
+    }
+}
@@ -0,0 +1,388 @@
+//! The line protocol two instances of the app speak over TCP. Every message
+//! is one line of words, the first naming the message:
+//!
+//! ```text
+//! hello 1 white alice        protocol version, the sender's side and name
+//! move 24 g1f3               a move from the position at this ply
+//! ack 24                     the move from ply 24 is on the board
+//! sync <fen>                 where the sender's game stands, after connecting
+//! position <fen>             play on from this position, set up or taken back
+//! resign
+//! draw offer|accept|decline|claim
+//! error <text>               the sender gives up on the connection
+//! ```
+//!
+//! Each side sends moves of its own side only. A `sync` asks the other side
+//! to compare: it answers with the moves the sender missed, whichever side
+//! played them, and the `position` they lead to, or with a position to play
+//! on from when the games went apart. A
+//! `position` sent unasked announces one set up or taken back, and the
+//! other side asks for it with a `sync` before playing on from it.
+//!
+//! Plies count half-moves from the start of the game as
+//! `Board::number_of_moves` does, so they survive positions set up from FEN.
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use crate::backend::{Color, Move};
+
+/// Bumped whenever a message changes meaning; peers on other versions are
+/// turned away at the handshake.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum Message {
+    Hello {
+        version: u32,
+        /// The side the sender plays.
+        color: Color,
+        name: String,
+    },
+    Move {
+        ply: u16,
+        chess_move: Move,
+    },
+    Ack {
+        ply: u16,
+    },
+    Sync {
+        fen: String,
+    },
+    Position {
+        fen: String,
+    },
+    Resign,
+    Draw(DrawAction),
+    Error(String),
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum DrawAction {
+    Offer,
+    Accept,
+    Decline,
+    Claim,
+}
+
+/// A message that travels as one line of text, written with `Display`.
+pub trait LineMessage: fmt::Display + Sized {
+    fn parse(line: &str) -> Option<Self>;
+}
+
+impl LineMessage for Message {
+    fn parse(line: &str) -> Option<Message> {
+        let line = line.trim();
+        let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+        let mut words = rest.split_whitespace();
+        let message = match kind {
+            "hello" => {
+                let version = words.next()?.parse().ok()?;
+                let color = parse_color(words.next()?)?;
+                Message::Hello {
+                    version,
+                    color,
+                    name: words.by_ref().collect::<Vec<_>>().join(" "),
+                }
+            }
+            "move" => Message::Move {
+                ply: words.next()?.parse().ok()?,
+                chess_move: Move::parse(words.next()?)?,
+            },
+            "ack" => Message::Ack {
+                ply: words.next()?.parse().ok()?,
+            },
+            // A FEN takes the rest of the line
+            "sync" if !rest.is_empty() => {
+                return Some(Message::Sync {
+                    fen: rest.to_owned(),
+                })
+            }
+            "position" if !rest.is_empty() => {
+                return Some(Message::Position {
+                    fen: rest.to_owned(),
+                })
+            }
+            "resign" => Message::Resign,
+            "draw" => Message::Draw(parse_draw_action(words.next()?)?),
+            "error" => return Some(Message::Error(rest.to_owned())),
+            _ => return None,
+        };
+        // Trailing words would be a message this version does not know
+        match words.next() {
+            Some(_) => None,
+            None => Some(message),
+        }
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Message::Hello {
+                version,
+                color,
+                name,
+            } => write!(f, "hello {} {} {}", version, color_name(*color), name),
+            Message::Move { ply, chess_move } => write!(f, "move {} {}", ply, chess_move),
+            Message::Ack { ply } => write!(f, "ack {}", ply),
+            Message::Sync { fen } => write!(f, "sync {}", fen),
+            Message::Position { fen } => write!(f, "position {}", fen),
+            Message::Resign => write!(f, "resign"),
+            Message::Draw(action) => write!(f, "draw {}", draw_action_name(*action)),
+            Message::Error(text) => write!(f, "error {}", text),
+        }
+    }
+}
+
+pub(crate) fn parse_color(name: &str) -> Option<Color> {
+    match name {
+        "white" => Some(Color::Light),
+        "black" => Some(Color::Dark),
+        _ => None,
+    }
+}
+
+pub(crate) fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::Light => "white",
+        Color::Dark => "black",
+    }
+}
+
+pub(crate) fn parse_draw_action(name: &str) -> Option<DrawAction> {
+    match name {
+        "offer" => Some(DrawAction::Offer),
+        "accept" => Some(DrawAction::Accept),
+        "decline" => Some(DrawAction::Decline),
+        "claim" => Some(DrawAction::Claim),
+        _ => None,
+    }
+}
+
+pub(crate) fn draw_action_name(action: DrawAction) -> &'static str {
+    match action {
+        DrawAction::Offer => "offer",
+        DrawAction::Accept => "accept",
+        DrawAction::Decline => "decline",
+        DrawAction::Claim => "claim",
+    }
+}
+
+/// Why a connection could not go on.
+#[derive(Debug)]
+pub enum ProtocolError {
+    Io(io::Error),
+    /// A line that is no message of this version, as received.
+    Malformed(String),
+    Closed,
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Io(error) => write!(f, "{}", error),
+            ProtocolError::Malformed(line) => write!(f, "unexpected message: {}", line),
+            ProtocolError::Closed => write!(f, "the connection is closed"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProtocolError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ProtocolError {
+    fn from(error: io::Error) -> Self {
+        ProtocolError::Io(error)
+    }
+}
+
+/// How long a peer may leave output unread before the connection is given
+/// up on.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A TCP stream carrying line messages, read and written on threads of its
+/// own so neither receiving nor sending ever blocks.
+pub struct Connection {
+    stream: TcpStream,
+    peer: String,
+    incoming: Receiver<String>,
+    outgoing: Sender<String>,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Result<Connection, ProtocolError> {
+        stream.set_nodelay(true)?;
+        let peer = stream.peer_addr()?.to_string();
+        let reader = BufReader::new(stream.try_clone()?);
+        let (sender, incoming) = mpsc::channel();
+        thread::spawn(move || {
+            for line in reader.lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let mut writer = stream.try_clone()?;
+        let (outgoing, lines) = mpsc::channel::<String>();
+        thread::spawn(move || {
+            for line in lines {
+                if writeln!(writer, "{}", line).is_err() {
+                    break;
+                }
+            }
+            // Everything sent before the connection was dropped is out
+            let _ = writer.shutdown(Shutdown::Both);
+        });
+        Ok(Connection {
+            stream,
+            peer,
+            incoming,
+            outgoing,
+        })
+    }
+
+    /// Address of the other end.
+    pub fn peer(&self) -> &str {
+        &self.peer
+    }
+
+    /// Queues `message` for the writer thread. Fails once that gave up on
+    /// the stream.
+    pub fn send(&mut self, message: &impl LineMessage) -> Result<(), ProtocolError> {
+        self.outgoing
+            .send(message.to_string())
+            .map_err(|_| ProtocolError::Closed)
+    }
+
+    /// The next message if one has arrived, skipping blank lines.
+    pub fn try_receive<M: LineMessage>(&mut self) -> Result<Option<M>, ProtocolError> {
+        loop {
+            let line = match self.incoming.try_recv() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => return Err(ProtocolError::Closed),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            return M::parse(&line)
+                .map(Some)
+                .ok_or(ProtocolError::Malformed(line));
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // Ends the reader thread; the writer closes the rest once its queue
+        // is out, which tells the peer
+        let _ = self.stream.shutdown(Shutdown::Read);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::STARTING_FEN;
+
+    fn pair() -> (Connection, TcpStream) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        (Connection::new(stream).unwrap(), client)
+    }
+
+    #[test]
+    fn sending_does_not_wait_for_a_peer_that_does_not_read() {
+        let (mut connection, _peer) = pair();
+        let message = Message::Error("x".repeat(1000));
+        let started = std::time::Instant::now();
+        // Far more than the socket buffers take
+        for _ in 0..10_000 {
+            connection.send(&message).unwrap();
+        }
+        assert!(started.elapsed() < WRITE_TIMEOUT);
+    }
+
+    #[test]
+    fn messages_sent_before_dropping_arrive() {
+        let (mut connection, peer) = pair();
+        connection.send(&Message::Resign).unwrap();
+        connection.send(&Message::Error("bye".to_owned())).unwrap();
+        drop(connection);
+        let lines: Vec<String> = BufReader::new(peer).lines().map_while(Result::ok).collect();
+        assert_eq!(lines, ["resign", "error bye"]);
+    }
+
+    #[test]
+    fn messages_read_back_as_written() {
+        let messages = [
+            Message::Hello {
+                version: PROTOCOL_VERSION,
+                color: Color::Light,
+                name: "alice smith".to_owned(),
+            },
+            Message::Hello {
+                version: PROTOCOL_VERSION,
+                color: Color::Dark,
+                name: String::new(),
+            },
+            Message::Move {
+                ply: 24,
+                chess_move: Move::parse("g1f3").unwrap(),
+            },
+            Message::Move {
+                ply: 0,
+                chess_move: Move::parse("e7e8q").unwrap(),
+            },
+            Message::Ack { ply: 24 },
+            Message::Sync {
+                fen: STARTING_FEN.to_owned(),
+            },
+            Message::Position {
+                fen: "4k3/8/8/8/8/8/8/4K3 w - - 0 1".to_owned(),
+            },
+            Message::Resign,
+            Message::Draw(DrawAction::Offer),
+            Message::Draw(DrawAction::Accept),
+            Message::Draw(DrawAction::Decline),
+            Message::Draw(DrawAction::Claim),
+            Message::Error("both sides play white".to_owned()),
+        ];
+        for message in messages {
+            assert_eq!(Message::parse(&message.to_string()), Some(message));
+        }
+    }
+
+    #[test]
+    fn other_lines_are_no_messages() {
+        for line in [
+            "",
+            "bye",
+            "hello 1",
+            "hello one white alice",
+            "hello 1 red alice",
+            "move 3",
+            "move 3 e2e4 e7e5",
+            "ack -1",
+            "sync",
+            "position ",
+            "draw maybe",
+        ] {
+            assert_eq!(Message::parse(line), None, "{}", line);
+        }
+    }
+}
@@ -0,0 +1,471 @@
+//! The server's messages as JSON, for browser clients on the WebSocket
+//! endpoint. Every WebSocket text message is one object whose `"type"` is
+//! the word starting the same message in the line protocol, with the other
+//! words as fields. Colors are `"white"` and `"black"`, moves are in
+//! coordinate notation (`"e2e4"`, `"e7e8q"`), positions are FEN, time
+//! controls are written as in the line protocol (`"300+2"`) and clocks are
+//! milliseconds. Fields shown as `null` may be left out in what clients send.
+//!
+//! From the client:
+//!
+//! ```text
+//! {"type": "hello", "version": 1, "name": "alice"}
+//! {"type": "seek", "time_control": "300+2" | null, "color": "white" | "black" | null}
+//! {"type": "cancel", "seek": 3}
+//! {"type": "seeks"}
+//! {"type": "games"}
+//! {"type": "accept", "seek": 3}
+//! {"type": "watch", "game": 4}
+//! {"type": "unwatch", "game": 4}
+//! {"type": "position", "game": 4}
+//! {"type": "move", "game": 4, "move": "e2e4"}
+//! {"type": "resign", "game": 4}
+//! {"type": "draw", "game": 4, "action": "offer" | "accept" | "decline" | "claim"}
+//! {"type": "resume", "game": 4, "token": "5f0c2e8a91d3b7e4"}
+//! ```
+//!
+//! From the server:
+//!
+//! ```text
+//! {"type": "welcome", "version": 1}
+//! {"type": "seek", "seek": 3, "name": "alice", "color": "white" | null, "time_control": "300+2" | null}
+//! {"type": "unseek", "seek": 3}
+//! {"type": "game", "game": 4, "white": "alice", "black": "bob", "time_control": "300+2" | null, "result": "*"}
+//! {"type": "joined", "game": 4, "color": "white" | "black" | null, "token": "5f0c2e8a91d3b7e4" | null,
+//!  "white": "alice", "black": "bob", "time_control": "300+2" | null, "fen": "<start position>"}
+//! {"type": "moved", "game": 4, "ply": 0, "move": "e2e4", "clocks": {"white": 300000, "black": 300000} | null}
+//! {"type": "position", "game": 4, "fen": "<current position>", "legal_moves": ["e7e5", ...]}
+//! {"type": "draw", "game": 4, "action": "offer" | "decline", "color": "white"}
+//! {"type": "ended", "game": 4, "result": "1-0", "reason": "Black resigns"}
+//! {"type": "error", "message": "game 4: not your move"}
+//! ```
+//!
+//! After `joined` with the moves so far, and after every `moved`, WebSocket
+//! clients also get a `position`, ahead of any `draw` or `ended` that comes
+//! with it. A front-end thus needs no rules of its own: it shows the FEN,
+//! offers the legal moves and sends one of them back. `legal_moves` is
+//! empty once the game is over.
+
+use serde_json::{json, Value};
+
+use super::messages::{ClientMessage, ServerMessage};
+use crate::backend::{Move, TimeControl};
+use crate::protocol::{color_name, draw_action_name, parse_color, parse_draw_action};
+
+impl ClientMessage {
+    /// Reads a message from a JSON object, `None` for anything that is not
+    /// one as documented above.
+    pub fn from_json(text: &str) -> Option<ClientMessage> {
+        let value: Value = serde_json::from_str(text).ok()?;
+        let object = value.as_object()?;
+        let number = |key: &str| -> Option<u32> { object.get(key)?.as_u64()?.try_into().ok() };
+        let text = |key: &str| object.get(key)?.as_str();
+        let message = match text("type")? {
+            "hello" => ClientMessage::Hello {
+                version: number("version")?,
+                // Names are single words in the line protocol
+                name: text("name")?
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join("_"),
+            },
+            "seek" => ClientMessage::Seek {
+                time_control: match object.get("time_control") {
+                    None | Some(Value::Null) => None,
+                    Some(value) => Some(TimeControl::parse(value.as_str()?)?),
+                },
+                color: match object.get("color") {
+                    None | Some(Value::Null) => None,
+                    Some(value) => Some(parse_color(value.as_str()?)?),
+                },
+            },
+            "cancel" => ClientMessage::Cancel {
+                seek: number("seek")?,
+            },
+            "seeks" => ClientMessage::ListSeeks,
+            "games" => ClientMessage::ListGames,
+            "accept" => ClientMessage::Accept {
+                seek: number("seek")?,
+            },
+            "watch" => ClientMessage::Watch {
+                game: number("game")?,
+            },
+            "unwatch" => ClientMessage::Unwatch {
+                game: number("game")?,
+            },
+            "position" => ClientMessage::Position {
+                game: number("game")?,
+            },
+            "move" => ClientMessage::Move {
+                game: number("game")?,
+                chess_move: Move::parse(text("move")?)?,
+            },
+            "resign" => ClientMessage::Resign {
+                game: number("game")?,
+            },
+            "draw" => ClientMessage::Draw {
+                game: number("game")?,
+                action: parse_draw_action(text("action")?)?,
+            },
+            "resume" => ClientMessage::Resume {
+                game: number("game")?,
+                token: text("token")?.to_owned(),
+            },
+            _ => return None,
+        };
+        Some(message)
+    }
+}
+
+impl ServerMessage {
+    pub fn to_json(&self) -> String {
+        let (kind, fields) = match self {
+            ServerMessage::Welcome { version } => ("welcome", json!({ "version": version })),
+            ServerMessage::Seek {
+                seek,
+                name,
+                color,
+                time_control,
+            } => (
+                "seek",
+                json!({
+                    "seek": seek,
+                    "name": name,
+                    "color": color.map(color_name),
+                    "time_control": time_control_json(time_control),
+                }),
+            ),
+            ServerMessage::SeekRemoved { seek } => ("unseek", json!({ "seek": seek })),
+            ServerMessage::Game {
+                game,
+                white,
+                black,
+                time_control,
+                result,
+            } => (
+                "game",
+                json!({
+                    "game": game,
+                    "white": white,
+                    "black": black,
+                    "time_control": time_control_json(time_control),
+                    "result": result,
+                }),
+            ),
+            ServerMessage::Joined {
+                game,
+                color,
+                token,
+                white,
+                black,
+                time_control,
+                fen,
+            } => (
+                "joined",
+                json!({
+                    "game": game,
+                    "color": color.map(color_name),
+                    "token": token,
+                    "white": white,
+                    "black": black,
+                    "time_control": time_control_json(time_control),
+                    "fen": fen,
+                }),
+            ),
+            ServerMessage::Moved {
+                game,
+                ply,
+                chess_move,
+                clocks,
+            } => (
+                "moved",
+                json!({
+                    "game": game,
+                    "ply": ply,
+                    "move": chess_move.to_string(),
+                    "clocks": clocks.map(|[white, black]| json!({
+                        "white": white.as_millis() as u64,
+                        "black": black.as_millis() as u64,
+                    })),
+                }),
+            ),
+            ServerMessage::Position {
+                game,
+                fen,
+                legal_moves,
+            } => (
+                "position",
+                json!({
+                    "game": game,
+                    "fen": fen,
+                    "legal_moves": legal_moves.iter().map(Move::to_string).collect::<Vec<_>>(),
+                }),
+            ),
+            ServerMessage::Draw {
+                game,
+                action,
+                color,
+            } => (
+                "draw",
+                json!({
+                    "game": game,
+                    "action": draw_action_name(*action),
+                    "color": color_name(*color),
+                }),
+            ),
+            ServerMessage::Ended {
+                game,
+                result,
+                reason,
+            } => (
+                "ended",
+                json!({ "game": game, "result": result, "reason": reason }),
+            ),
+            ServerMessage::Error(text) => ("error", json!({ "message": text })),
+        };
+        let mut object = fields;
+        object["type"] = Value::from(kind);
+        object.to_string()
+    }
+}
+
+fn time_control_json(time_control: &Option<TimeControl>) -> Option<String> {
+    time_control.as_ref().map(TimeControl::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::backend::Color;
+    use crate::protocol::DrawAction;
+
+    fn chess_move(name: &str) -> Move {
+        Move::parse(name).unwrap()
+    }
+
+    #[test]
+    fn every_client_message_is_read() {
+        let time_control = TimeControl::parse("300+2");
+        let messages = [
+            (
+                r#"{"type": "hello", "version": 1, "name": "alice smith"}"#,
+                ClientMessage::Hello {
+                    version: 1,
+                    name: "alice_smith".to_owned(),
+                },
+            ),
+            (
+                r#"{"type": "seek", "time_control": "300+2", "color": "black"}"#,
+                ClientMessage::Seek {
+                    time_control,
+                    color: Some(Color::Dark),
+                },
+            ),
+            (
+                r#"{"type": "seek", "time_control": null}"#,
+                ClientMessage::Seek {
+                    time_control: None,
+                    color: None,
+                },
+            ),
+            (
+                r#"{"type": "cancel", "seek": 3}"#,
+                ClientMessage::Cancel { seek: 3 },
+            ),
+            (r#"{"type": "seeks"}"#, ClientMessage::ListSeeks),
+            (r#"{"type": "games"}"#, ClientMessage::ListGames),
+            (
+                r#"{"type": "accept", "seek": 3}"#,
+                ClientMessage::Accept { seek: 3 },
+            ),
+            (
+                r#"{"type": "watch", "game": 4}"#,
+                ClientMessage::Watch { game: 4 },
+            ),
+            (
+                r#"{"type": "unwatch", "game": 4}"#,
+                ClientMessage::Unwatch { game: 4 },
+            ),
+            (
+                r#"{"type": "position", "game": 4}"#,
+                ClientMessage::Position { game: 4 },
+            ),
+            (
+                r#"{"type": "move", "game": 4, "move": "e7e8q"}"#,
+                ClientMessage::Move {
+                    game: 4,
+                    chess_move: chess_move("e7e8q"),
+                },
+            ),
+            (
+                r#"{"type": "resign", "game": 4}"#,
+                ClientMessage::Resign { game: 4 },
+            ),
+            (
+                r#"{"type": "draw", "game": 4, "action": "claim"}"#,
+                ClientMessage::Draw {
+                    game: 4,
+                    action: DrawAction::Claim,
+                },
+            ),
+            (
+                r#"{"type": "resume", "game": 4, "token": "5f0c2e8a91d3b7e4"}"#,
+                ClientMessage::Resume {
+                    game: 4,
+                    token: "5f0c2e8a91d3b7e4".to_owned(),
+                },
+            ),
+        ];
+        for (text, message) in messages {
+            assert_eq!(ClientMessage::from_json(text), Some(message), "{}", text);
+        }
+    }
+
+    #[test]
+    fn other_json_is_no_client_message() {
+        for text in [
+            "",
+            "[]",
+            "hello",
+            r#"{"version": 1, "name": "alice"}"#,
+            r#"{"type": "bye"}"#,
+            r#"{"type": "hello", "version": -1, "name": "alice"}"#,
+            r#"{"type": "seek", "color": "red"}"#,
+            r#"{"type": "seek", "time_control": 300}"#,
+            r#"{"type": "move", "game": 4, "move": "e2"}"#,
+            r#"{"type": "draw", "game": 4, "action": "maybe"}"#,
+            r#"{"type": "resume", "game": 4}"#,
+        ] {
+            assert_eq!(ClientMessage::from_json(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn every_server_message_is_written() {
+        let time_control = TimeControl::parse("300+2");
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+        let messages = [
+            (
+                ServerMessage::Welcome { version: 1 },
+                json!({ "type": "welcome", "version": 1 }),
+            ),
+            (
+                ServerMessage::Seek {
+                    seek: 3,
+                    name: "alice".to_owned(),
+                    color: None,
+                    time_control: time_control.clone(),
+                },
+                json!({
+                    "type": "seek", "seek": 3, "name": "alice", "color": null,
+                    "time_control": "300+2",
+                }),
+            ),
+            (
+                ServerMessage::SeekRemoved { seek: 3 },
+                json!({ "type": "unseek", "seek": 3 }),
+            ),
+            (
+                ServerMessage::Game {
+                    game: 4,
+                    white: "alice".to_owned(),
+                    black: "bob".to_owned(),
+                    time_control: None,
+                    result: "*".to_owned(),
+                },
+                json!({
+                    "type": "game", "game": 4, "white": "alice", "black": "bob",
+                    "time_control": null, "result": "*",
+                }),
+            ),
+            (
+                ServerMessage::Joined {
+                    game: 4,
+                    color: Some(Color::Light),
+                    token: Some("5f0c2e8a91d3b7e4".to_owned()),
+                    white: "alice".to_owned(),
+                    black: "bob".to_owned(),
+                    time_control: time_control.clone(),
+                    fen: fen.to_owned(),
+                },
+                json!({
+                    "type": "joined", "game": 4, "color": "white", "token": "5f0c2e8a91d3b7e4",
+                    "white": "alice", "black": "bob", "time_control": "300+2", "fen": fen,
+                }),
+            ),
+            (
+                ServerMessage::Joined {
+                    game: 4,
+                    color: None,
+                    token: None,
+                    white: "alice".to_owned(),
+                    black: "bob".to_owned(),
+                    time_control: None,
+                    fen: fen.to_owned(),
+                },
+                json!({
+                    "type": "joined", "game": 4, "color": null, "token": null,
+                    "white": "alice", "black": "bob", "time_control": null, "fen": fen,
+                }),
+            ),
+            (
+                ServerMessage::Moved {
+                    game: 4,
+                    ply: 0,
+                    chess_move: chess_move("e2e4"),
+                    clocks: Some([Duration::from_millis(301_500), Duration::from_secs(300)]),
+                },
+                json!({
+                    "type": "moved", "game": 4, "ply": 0, "move": "e2e4",
+                    "clocks": { "white": 301_500, "black": 300_000 },
+                }),
+            ),
+            (
+                ServerMessage::Moved {
+                    game: 4,
+                    ply: 1,
+                    chess_move: chess_move("e7e5"),
+                    clocks: None,
+                },
+                json!({ "type": "moved", "game": 4, "ply": 1, "move": "e7e5", "clocks": null }),
+            ),
+            (
+                ServerMessage::Position {
+                    game: 4,
+                    fen: fen.to_owned(),
+                    legal_moves: vec![chess_move("e7e5"), chess_move("g8f6")],
+                },
+                json!({
+                    "type": "position", "game": 4, "fen": fen,
+                    "legal_moves": ["e7e5", "g8f6"],
+                }),
+            ),
+            (
+                ServerMessage::Draw {
+                    game: 4,
+                    action: DrawAction::Offer,
+                    color: Color::Dark,
+                },
+                json!({ "type": "draw", "game": 4, "action": "offer", "color": "black" }),
+            ),
+            (
+                ServerMessage::Ended {
+                    game: 4,
+                    result: "1-0".to_owned(),
+                    reason: "Black resigns".to_owned(),
+                },
+                json!({ "type": "ended", "game": 4, "result": "1-0", "reason": "Black resigns" }),
+            ),
+            (
+                ServerMessage::Error("game 4: not your move".to_owned()),
+                json!({ "type": "error", "message": "game 4: not your move" }),
+            ),
+        ];
+        for (message, expected) in messages {
+            let written: Value = serde_json::from_str(&message.to_json()).unwrap();
+            assert_eq!(written, expected);
+        }
+    }
+}
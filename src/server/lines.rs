@@ -0,0 +1,145 @@
+//! Connections of line clients: the server's messages as lines of text on a
+//! nonblocking stream, like `nc` speaks.
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+use super::messages::{ClientMessage, ServerMessage};
+use super::MAX_UNSENT;
+use crate::protocol::{LineMessage, ProtocolError};
+
+/// Longest line a client may send.
+const MAX_LINE_LENGTH: usize = 1 << 16;
+
+/// A stream of line messages that never blocks the server: what the client
+/// does not take yet waits in a buffer, written out with every poll.
+pub struct LineConnection {
+    stream: TcpStream,
+    /// Read and not yet a whole line.
+    received: Vec<u8>,
+    /// Written and not yet taken by the stream.
+    unsent: Vec<u8>,
+}
+
+impl LineConnection {
+    pub fn new(stream: TcpStream) -> io::Result<LineConnection> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(LineConnection {
+            stream,
+            received: Vec::new(),
+            unsent: Vec::new(),
+        })
+    }
+
+    pub fn send(&mut self, message: &ServerMessage) -> Result<(), ProtocolError> {
+        if self.unsent.len() > MAX_UNSENT {
+            return Err(ProtocolError::Io(io::Error::new(
+                ErrorKind::TimedOut,
+                "the client does not keep up with reading",
+            )));
+        }
+        writeln!(self.unsent, "{}", message)?;
+        self.flush()
+    }
+
+    /// The next message if one has arrived, skipping blank lines. Output
+    /// still waiting goes out first.
+    pub fn try_receive(&mut self) -> Result<Option<ClientMessage>, ProtocolError> {
+        self.flush()?;
+        loop {
+            if let Some(end) = self.received.iter().position(|byte| *byte == b'\n') {
+                let bytes: Vec<u8> = self.received.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&bytes).trim().to_owned();
+                if line.is_empty() {
+                    continue;
+                }
+                return ClientMessage::parse(&line)
+                    .map(Some)
+                    .ok_or(ProtocolError::Malformed(line));
+            }
+            if self.received.len() > MAX_LINE_LENGTH {
+                return Err(ProtocolError::Io(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "line too long",
+                )));
+            }
+            let mut buffer = [0; 4096];
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(ProtocolError::Closed),
+                Ok(length) => self.received.extend_from_slice(&buffer[..length]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(None),
+                Err(error) if error.kind() == ErrorKind::Interrupted => (),
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+
+    /// Writes what the stream takes without waiting.
+    fn flush(&mut self) -> Result<(), ProtocolError> {
+        while !self.unsent.is_empty() {
+            match self.stream.write(&self.unsent) {
+                Ok(0) => return Err(ProtocolError::Closed),
+                Ok(length) => {
+                    self.unsent.drain(..length);
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == ErrorKind::Interrupted => (),
+                Err(error) => return Err(error.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn pair() -> (LineConnection, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        (LineConnection::new(stream).unwrap(), client)
+    }
+
+    #[test]
+    fn lines_travel_both_ways() {
+        let (mut connection, mut client) = pair();
+        assert!(matches!(connection.try_receive(), Ok(None)));
+        // A line can arrive in pieces, blank ones are skipped
+        client.write_all(b"\ngam").unwrap();
+        assert!(matches!(connection.try_receive(), Ok(None)));
+        client.write_all(b"es\nbogus\n").unwrap();
+        client.flush().unwrap();
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            match connection.try_receive() {
+                Ok(None) => std::thread::yield_now(),
+                result => received.push(result),
+            }
+        }
+        assert!(matches!(received[0], Ok(Some(ClientMessage::ListGames))));
+        assert!(matches!(&received[1], Err(ProtocolError::Malformed(line)) if line == "bogus"));
+
+        connection
+            .send(&ServerMessage::Welcome { version: 1 })
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(client).read_line(&mut line).unwrap();
+        assert_eq!(line, "welcome 1\n");
+    }
+
+    #[test]
+    fn a_client_that_does_not_read_is_given_up_on_without_blocking() {
+        let (mut connection, _client) = pair();
+        let message = ServerMessage::Error("x".repeat(1000));
+        let sends = (0..100_000)
+            .position(|_| connection.send(&message).is_err())
+            .expect("sending never failed");
+        assert!(sends * 1000 > MAX_UNSENT);
+    }
+}
@@ -0,0 +1,432 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::backend::{Color, Move, TimeControl};
+use crate::protocol::{
+    color_name, draw_action_name, parse_color, parse_draw_action, DrawAction, LineMessage,
+};
+
+/// What a client asks of the server.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum ClientMessage {
+    /// First line of every client, with a name of one word.
+    Hello {
+        version: u32,
+        name: String,
+    },
+    /// Looks for an opponent, playing `color` or either side if `None`.
+    Seek {
+        time_control: Option<TimeControl>,
+        color: Option<Color>,
+    },
+    Cancel {
+        seek: u32,
+    },
+    ListSeeks,
+    ListGames,
+    Accept {
+        seek: u32,
+    },
+    Watch {
+        game: u32,
+    },
+    Unwatch {
+        game: u32,
+    },
+    Move {
+        game: u32,
+        chess_move: Move,
+    },
+    Resign {
+        game: u32,
+    },
+    Draw {
+        game: u32,
+        action: DrawAction,
+    },
+    /// Takes the seat the token was handed out for back, after reconnecting.
+    Resume {
+        game: u32,
+        token: String,
+    },
+    /// Asks for the current position of `game`.
+    Position {
+        game: u32,
+    },
+}
+
+/// What the server tells a client, in answer or as things happen.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum ServerMessage {
+    Welcome {
+        version: u32,
+    },
+    /// An open seek, sent to everyone in the lobby.
+    Seek {
+        seek: u32,
+        name: String,
+        color: Option<Color>,
+        time_control: Option<TimeControl>,
+    },
+    /// The seek was accepted or cancelled.
+    SeekRemoved {
+        seek: u32,
+    },
+    /// A game on the server, with its result, `*` while in progress.
+    Game {
+        game: u32,
+        white: String,
+        black: String,
+        time_control: Option<TimeControl>,
+        result: String,
+    },
+    /// The client takes part in `game`, playing `color` or watching if
+    /// `None`. Players get the token to resume with. The moves so far
+    /// follow as `Moved`.
+    Joined {
+        game: u32,
+        color: Option<Color>,
+        token: Option<String>,
+        white: String,
+        black: String,
+        time_control: Option<TimeControl>,
+        fen: String,
+    },
+    /// A move was played from the position at `ply`, leaving the clocks of
+    /// White and Black at `clocks`.
+    Moved {
+        game: u32,
+        ply: u16,
+        chess_move: Move,
+        clocks: Option<[Duration; 2]>,
+    },
+    /// Where `game` stands and what the side to move can play. WebSocket
+    /// clients get it after every move, others when they ask.
+    Position {
+        game: u32,
+        fen: String,
+        legal_moves: Vec<Move>,
+    },
+    Draw {
+        game: u32,
+        action: DrawAction,
+        color: Color,
+    },
+    Ended {
+        game: u32,
+        result: String,
+        reason: String,
+    },
+    Error(String),
+}
+
+impl LineMessage for ClientMessage {
+    fn parse(line: &str) -> Option<ClientMessage> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |index: usize| words.get(index)?.parse().ok();
+        let (message, word_count) = match *words.first()? {
+            "hello" => (
+                ClientMessage::Hello {
+                    version: number(1)?,
+                    name: words.get(2)?.to_string(),
+                },
+                3,
+            ),
+            "seek" => (
+                ClientMessage::Seek {
+                    time_control: match words.get(1) {
+                        None | Some(&"-") => None,
+                        Some(text) => Some(TimeControl::parse(text)?),
+                    },
+                    color: match words.get(2) {
+                        None | Some(&"any") => None,
+                        Some(name) => Some(parse_color(name)?),
+                    },
+                },
+                words.len().min(3),
+            ),
+            "cancel" => (ClientMessage::Cancel { seek: number(1)? }, 2),
+            "seeks" => (ClientMessage::ListSeeks, 1),
+            "games" => (ClientMessage::ListGames, 1),
+            "accept" => (ClientMessage::Accept { seek: number(1)? }, 2),
+            "watch" => (ClientMessage::Watch { game: number(1)? }, 2),
+            "unwatch" => (ClientMessage::Unwatch { game: number(1)? }, 2),
+            "move" => (
+                ClientMessage::Move {
+                    game: number(1)?,
+                    chess_move: Move::parse(words.get(2)?)?,
+                },
+                3,
+            ),
+            "resign" => (ClientMessage::Resign { game: number(1)? }, 2),
+            "draw" => (
+                ClientMessage::Draw {
+                    game: number(1)?,
+                    action: parse_draw_action(words.get(2)?)?,
+                },
+                3,
+            ),
+            "resume" => (
+                ClientMessage::Resume {
+                    game: number(1)?,
+                    token: words.get(2)?.to_string(),
+                },
+                3,
+            ),
+            "position" => (ClientMessage::Position { game: number(1)? }, 2),
+            _ => return None,
+        };
+        // Trailing words would be a message this version does not know
+        (words.len() == word_count).then_some(message)
+    }
+}
+
+impl fmt::Display for ClientMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientMessage::Hello { version, name } => write!(f, "hello {} {}", version, name),
+            ClientMessage::Seek {
+                time_control,
+                color,
+            } => write!(
+                f,
+                "seek {} {}",
+                time_control_name(time_control),
+                color.map_or("any", color_name)
+            ),
+            ClientMessage::Cancel { seek } => write!(f, "cancel {}", seek),
+            ClientMessage::ListSeeks => write!(f, "seeks"),
+            ClientMessage::ListGames => write!(f, "games"),
+            ClientMessage::Accept { seek } => write!(f, "accept {}", seek),
+            ClientMessage::Watch { game } => write!(f, "watch {}", game),
+            ClientMessage::Unwatch { game } => write!(f, "unwatch {}", game),
+            ClientMessage::Move { game, chess_move } => write!(f, "move {} {}", game, chess_move),
+            ClientMessage::Resign { game } => write!(f, "resign {}", game),
+            ClientMessage::Draw { game, action } => {
+                write!(f, "draw {} {}", game, draw_action_name(*action))
+            }
+            ClientMessage::Resume { game, token } => write!(f, "resume {} {}", game, token),
+            ClientMessage::Position { game } => write!(f, "position {}", game),
+        }
+    }
+}
+
+impl LineMessage for ServerMessage {
+    fn parse(line: &str) -> Option<ServerMessage> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |index: usize| words.get(index)?.parse().ok();
+        let time_control = |index: usize| match *words.get(index)? {
+            "-" => Some(None),
+            text => TimeControl::parse(text).map(Some),
+        };
+        let (message, word_count) = match *words.first()? {
+            "welcome" => (
+                ServerMessage::Welcome {
+                    version: number(1)?,
+                },
+                2,
+            ),
+            "seek" => (
+                ServerMessage::Seek {
+                    seek: number(1)?,
+                    name: words.get(2)?.to_string(),
+                    color: match *words.get(3)? {
+                        "any" => None,
+                        name => Some(parse_color(name)?),
+                    },
+                    time_control: time_control(4)?,
+                },
+                5,
+            ),
+            "unseek" => (ServerMessage::SeekRemoved { seek: number(1)? }, 2),
+            "game" => (
+                ServerMessage::Game {
+                    game: number(1)?,
+                    white: words.get(2)?.to_string(),
+                    black: words.get(3)?.to_string(),
+                    time_control: time_control(4)?,
+                    result: words.get(5)?.to_string(),
+                },
+                6,
+            ),
+            "joined" if words.len() > 7 => {
+                // A FEN takes the rest of the line
+                return Some(ServerMessage::Joined {
+                    game: number(1)?,
+                    color: match *words.get(2)? {
+                        "spectator" => None,
+                        name => Some(parse_color(name)?),
+                    },
+                    token: match *words.get(3)? {
+                        "-" => None,
+                        token => Some(token.to_owned()),
+                    },
+                    white: words.get(4)?.to_string(),
+                    black: words.get(5)?.to_string(),
+                    time_control: time_control(6)?,
+                    fen: words[7..].join(" "),
+                });
+            }
+            "moved" => {
+                let clocks = match (words.get(4), words.get(5)) {
+                    (None, None) => None,
+                    (Some(white), Some(black)) => Some([
+                        Duration::from_millis(white.parse().ok()?),
+                        Duration::from_millis(black.parse().ok()?),
+                    ]),
+                    _ => return None,
+                };
+                (
+                    ServerMessage::Moved {
+                        game: number(1)?,
+                        ply: words.get(2)?.parse().ok()?,
+                        chess_move: Move::parse(words.get(3)?)?,
+                        clocks,
+                    },
+                    if clocks.is_some() { 6 } else { 4 },
+                )
+            }
+            "position" if words.len() > 3 => {
+                // A FEN takes the rest of the line
+                return Some(ServerMessage::Position {
+                    game: number(1)?,
+                    legal_moves: match *words.get(2)? {
+                        "-" => Vec::new(),
+                        moves => moves
+                            .split(',')
+                            .map(Move::parse)
+                            .collect::<Option<Vec<_>>>()?,
+                    },
+                    fen: words[3..].join(" "),
+                });
+            }
+            "draw" => (
+                ServerMessage::Draw {
+                    game: number(1)?,
+                    action: parse_draw_action(words.get(2)?)?,
+                    color: parse_color(words.get(3)?)?,
+                },
+                4,
+            ),
+            "ended" if words.len() > 3 => {
+                return Some(ServerMessage::Ended {
+                    game: number(1)?,
+                    result: words.get(2)?.to_string(),
+                    reason: words[3..].join(" "),
+                });
+            }
+            "error" => return Some(ServerMessage::Error(words[1..].join(" "))),
+            _ => return None,
+        };
+        (words.len() == word_count).then_some(message)
+    }
+}
+
+impl fmt::Display for ServerMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerMessage::Welcome { version } => write!(f, "welcome {}", version),
+            ServerMessage::Seek {
+                seek,
+                name,
+                color,
+                time_control,
+            } => write!(
+                f,
+                "seek {} {} {} {}",
+                seek,
+                name,
+                color.map_or("any", color_name),
+                time_control_name(time_control)
+            ),
+            ServerMessage::SeekRemoved { seek } => write!(f, "unseek {}", seek),
+            ServerMessage::Game {
+                game,
+                white,
+                black,
+                time_control,
+                result,
+            } => write!(
+                f,
+                "game {} {} {} {} {}",
+                game,
+                white,
+                black,
+                time_control_name(time_control),
+                result
+            ),
+            ServerMessage::Joined {
+                game,
+                color,
+                token,
+                white,
+                black,
+                time_control,
+                fen,
+            } => write!(
+                f,
+                "joined {} {} {} {} {} {} {}",
+                game,
+                color.map_or("spectator", color_name),
+                token.as_deref().unwrap_or("-"),
+                white,
+                black,
+                time_control_name(time_control),
+                fen
+            ),
+            ServerMessage::Moved {
+                game,
+                ply,
+                chess_move,
+                clocks,
+            } => {
+                write!(f, "moved {} {} {}", game, ply, chess_move)?;
+                if let Some([white, black]) = clocks {
+                    write!(f, " {} {}", white.as_millis(), black.as_millis())?;
+                }
+                Ok(())
+            }
+            ServerMessage::Position {
+                game,
+                fen,
+                legal_moves,
+            } => {
+                let moves: Vec<String> = legal_moves.iter().map(Move::to_string).collect();
+                write!(
+                    f,
+                    "position {} {} {}",
+                    game,
+                    if moves.is_empty() {
+                        "-".to_owned()
+                    } else {
+                        moves.join(",")
+                    },
+                    fen
+                )
+            }
+            ServerMessage::Draw {
+                game,
+                action,
+                color,
+            } => write!(
+                f,
+                "draw {} {} {}",
+                game,
+                draw_action_name(*action),
+                color_name(*color)
+            ),
+            ServerMessage::Ended {
+                game,
+                result,
+                reason,
+            } => write!(f, "ended {} {} {}", game, result, reason),
+            ServerMessage::Error(text) => write!(f, "error {}", text),
+        }
+    }
+}
+
+fn time_control_name(time_control: &Option<TimeControl>) -> String {
+    time_control
+        .as_ref()
+        .map_or("-".to_owned(), TimeControl::to_string)
+}
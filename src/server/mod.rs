@@ -0,0 +1,1109 @@
+//! A server hosting many games at once, for tournaments on one machine or a
+//! LAN. Clients say hello with a name, post or accept seeks in the lobby,
+//! play their games, watch others move by move, and take their seat back
+//! with a token after losing the connection. The server plays every move
+//! through a `GameController`, so its rules and clocks are the ones that
+//! count.
+//!
+//! It speaks lines of text like the peer protocol, see `ClientMessage` and
+//! `ServerMessage`, so even `nc` makes a client:
+//!
+//! ```text
+//! > hello 1 alice
+//! < welcome 1
+//! > seek 300+2 white
+//! < seek 1 alice white 300+2
+//! < unseek 1
+//! < joined 1 white 5f0c2e8a91d3b7e4 alice bob 300+2 rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1
+//! < game 1 alice bob 300+2 *
+//! > move 1 e2e4
+//! < moved 1 0 e2e4 300000 300000
+//! ```
+//!
+//! Browsers connect to a WebSocket endpoint instead, opened with
+//! `listen_websocket`, where the same messages travel as JSON (see `json`
+//! for the schema) and the position with its legal moves follows every move.
+
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::OpenOptions;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, ErrorKind, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::backend::pgn::{pgn_date, unix_timestamp};
+use crate::backend::{Board, Color, GameChange, GameController, GameEvent, TimeControl};
+use crate::protocol::{DrawAction, ProtocolError, PROTOCOL_VERSION};
+
+pub mod json;
+mod lines;
+mod messages;
+mod websocket;
+
+use lines::LineConnection;
+pub use messages::{ClientMessage, ServerMessage};
+pub use websocket::{WebSocketConnection, WebSocketListener};
+
+/// Pause between rounds of reading clients and ticking clocks.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// Output a client may fall behind on reading before it is given up on.
+const MAX_UNSENT: usize = 1 << 20;
+
+pub struct Server {
+    listener: TcpListener,
+    websocket: Option<WebSocketListener>,
+    clients: HashMap<u32, Client>,
+    seeks: Vec<Seek>,
+    /// Games being played; finished ones are saved and dropped.
+    games: BTreeMap<u32, ServerGame>,
+    /// Clients, seeks and games share the numbering.
+    next_id: u32,
+    /// File finished games are appended to as PGN.
+    pgn_path: Option<PathBuf>,
+}
+
+struct Client {
+    transport: Transport,
+    /// Given in the hello, before which the client can do nothing else.
+    name: Option<String>,
+    is_closed: bool,
+}
+
+enum Transport {
+    Lines(LineConnection),
+    /// Gets the position after every move, for front-ends without rules.
+    WebSocket(Box<WebSocketConnection>),
+}
+
+impl Transport {
+    fn send(&mut self, message: &ServerMessage) -> Result<(), ProtocolError> {
+        match self {
+            Transport::Lines(connection) => connection.send(message),
+            Transport::WebSocket(connection) => connection.send(message),
+        }
+    }
+
+    fn try_receive(&mut self) -> Result<Option<ClientMessage>, ProtocolError> {
+        match self {
+            Transport::Lines(connection) => connection.try_receive(),
+            Transport::WebSocket(connection) => connection.try_receive(),
+        }
+    }
+}
+
+struct Seek {
+    id: u32,
+    client: u32,
+    name: String,
+    time_control: Option<TimeControl>,
+    color: Option<Color>,
+}
+
+struct ServerGame {
+    controller: GameController,
+    /// White's and Black's.
+    seats: [Seat; 2],
+    spectators: Vec<u32>,
+}
+
+struct Seat {
+    name: String,
+    /// Proves a reconnecting client is this player.
+    token: String,
+    /// The player's connection, `None` while away. The game and its clock go
+    /// on without them.
+    client: Option<u32>,
+}
+
+impl Server {
+    /// Listens on `address`, a port alone meaning any local address.
+    pub fn bind(address: &str) -> io::Result<Server> {
+        let listener = TcpListener::bind(full_address(address))?;
+        listener.set_nonblocking(true)?;
+        Ok(Server {
+            listener,
+            websocket: None,
+            clients: HashMap::new(),
+            seeks: Vec::new(),
+            games: BTreeMap::new(),
+            next_id: 1,
+            pgn_path: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Also takes WebSocket clients speaking JSON on `address`, a port alone
+    /// meaning any local address. Returns the address listened on.
+    pub fn listen_websocket(&mut self, address: &str) -> io::Result<SocketAddr> {
+        let websocket = WebSocketListener::bind(&full_address(address))?;
+        let address = websocket.local_addr()?;
+        self.websocket = Some(websocket);
+        Ok(address)
+    }
+
+    /// Appends every finished game to `path` as PGN.
+    pub fn save_games_to(&mut self, path: PathBuf) {
+        self.pgn_path = Some(path);
+    }
+
+    /// Serves clients until the listener fails.
+    pub fn run(&mut self) -> io::Result<()> {
+        loop {
+            self.poll(Instant::now())?;
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Takes new connections, answers what clients sent and rules on flag
+    /// falls, without blocking.
+    pub fn poll(&mut self, now: Instant) -> io::Result<()> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    // One that fails right away is as good as gone
+                    if let Ok(connection) = LineConnection::new(stream) {
+                        self.add_client(Transport::Lines(connection));
+                    }
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) => return Err(error),
+            }
+        }
+        if let Some(websocket) = &mut self.websocket {
+            for connection in websocket.accept()? {
+                self.add_client(Transport::WebSocket(Box::new(connection)));
+            }
+        }
+
+        let client_ids: Vec<u32> = self.clients.keys().copied().collect();
+        for id in client_ids {
+            loop {
+                let received = match self.clients.get_mut(&id) {
+                    Some(client) if !client.is_closed => client.transport.try_receive(),
+                    _ => break,
+                };
+                match received {
+                    Ok(Some(message)) => self.receive(id, message, now),
+                    Ok(None) => break,
+                    // Typos are answered, clients typing by hand stay on
+                    Err(ProtocolError::Malformed(line)) => self.send(
+                        id,
+                        ServerMessage::Error(format!("unknown message: {}", line)),
+                    ),
+                    Err(_) => {
+                        self.close(id);
+                        break;
+                    }
+                }
+            }
+        }
+
+        let game_ids: Vec<u32> = self.games.keys().copied().collect();
+        for id in game_ids {
+            if self
+                .games
+                .get(&id)
+                .is_some_and(|game| !game.controller.is_over())
+            {
+                self.play(id, GameEvent::Tick, None, now);
+            }
+        }
+
+        let closed: Vec<u32> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| client.is_closed)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in closed {
+            self.remove_client(id);
+        }
+        Ok(())
+    }
+
+    fn add_client(&mut self, transport: Transport) {
+        let id = self.next_id();
+        self.clients.insert(
+            id,
+            Client {
+                transport,
+                name: None,
+                is_closed: false,
+            },
+        );
+    }
+
+    fn next_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn receive(&mut self, client: u32, message: ClientMessage, now: Instant) {
+        let Some(name) = self
+            .clients
+            .get(&client)
+            .and_then(|client| client.name.clone())
+        else {
+            return match message {
+                ClientMessage::Hello { version, name } => self.greet(client, version, name),
+                _ => self.send(client, ServerMessage::Error("say hello first".to_owned())),
+            };
+        };
+        match message {
+            ClientMessage::Hello { .. } => self.send(
+                client,
+                ServerMessage::Error("already said hello".to_owned()),
+            ),
+            ClientMessage::Seek {
+                time_control,
+                color,
+            } => {
+                let seek = Seek {
+                    id: self.next_id(),
+                    client,
+                    name,
+                    time_control,
+                    color,
+                };
+                let message = seek_message(&seek);
+                self.seeks.push(seek);
+                self.broadcast(message);
+            }
+            ClientMessage::Cancel { seek } => {
+                match self
+                    .seeks
+                    .iter()
+                    .position(|open| open.id == seek && open.client == client)
+                {
+                    Some(index) => {
+                        self.seeks.remove(index);
+                        self.broadcast(ServerMessage::SeekRemoved { seek });
+                    }
+                    None => self.send(
+                        client,
+                        ServerMessage::Error(format!("no seek {} of yours", seek)),
+                    ),
+                }
+            }
+            ClientMessage::ListSeeks => self.list_seeks(client),
+            ClientMessage::ListGames => {
+                let games: Vec<ServerMessage> = self
+                    .games
+                    .iter()
+                    .map(|(id, game)| game_message(*id, game))
+                    .collect();
+                for message in games {
+                    self.send(client, message);
+                }
+            }
+            ClientMessage::Accept { seek } => self.accept(client, name, seek, now),
+            ClientMessage::Watch { game } => match self.games.get_mut(&game) {
+                Some(server_game) => {
+                    if !server_game.spectators.contains(&client) {
+                        server_game.spectators.push(client);
+                    }
+                    self.send_game(client, game, None, now);
+                }
+                None => self.send(client, no_game(game)),
+            },
+            ClientMessage::Unwatch { game } => {
+                if let Some(server_game) = self.games.get_mut(&game) {
+                    server_game
+                        .spectators
+                        .retain(|spectator| *spectator != client);
+                }
+            }
+            ClientMessage::Move { game, chess_move } => {
+                let turn = self
+                    .games
+                    .get(&game)
+                    .map(|game| game.controller.board().turn);
+                match (self.seat_color(game, client), turn) {
+                    (Some(color), Some(turn)) if color == turn => {
+                        self.play(game, GameEvent::Move(chess_move), Some(client), now)
+                    }
+                    (Some(_), _) => self.send(
+                        client,
+                        ServerMessage::Error(format!("game {}: not your move", game)),
+                    ),
+                    (None, _) => self.send(client, not_playing(game)),
+                }
+            }
+            ClientMessage::Resign { game } => match self.seat_color(game, client) {
+                Some(color) => self.play(game, GameEvent::Resign(color), Some(client), now),
+                None => self.send(client, not_playing(game)),
+            },
+            ClientMessage::Draw { game, action } => match self.seat_color(game, client) {
+                Some(color) => {
+                    let event = match action {
+                        DrawAction::Offer => GameEvent::OfferDraw(color),
+                        DrawAction::Accept => GameEvent::AcceptDraw(color),
+                        DrawAction::Decline => GameEvent::DeclineDraw(color),
+                        DrawAction::Claim => GameEvent::ClaimDraw,
+                    };
+                    self.play(game, event, Some(client), now);
+                }
+                None => self.send(client, not_playing(game)),
+            },
+            ClientMessage::Position { game } => match self.games.get(&game) {
+                Some(server_game) => {
+                    let message = position_message(game, &server_game.controller);
+                    self.send(client, message);
+                }
+                None => self.send(client, no_game(game)),
+            },
+            ClientMessage::Resume { game, token } => {
+                let color = self.games.get_mut(&game).and_then(|server_game| {
+                    let color = Color::ALL
+                        .into_iter()
+                        .find(|color| server_game.seats[seat_index(*color)].token == token)?;
+                    server_game.seats[seat_index(color)].client = Some(client);
+                    Some(color)
+                });
+                match color {
+                    Some(color) => self.send_game(client, game, Some(color), now),
+                    None => self.send(
+                        client,
+                        ServerMessage::Error(format!("game {}: no seat with that token", game)),
+                    ),
+                }
+            }
+        }
+    }
+
+    fn greet(&mut self, client: u32, version: u32, name: String) {
+        if version != PROTOCOL_VERSION {
+            self.send(
+                client,
+                ServerMessage::Error(format!(
+                    "protocol version {}, expected {}",
+                    version, PROTOCOL_VERSION
+                )),
+            );
+            return self.close(client);
+        }
+        if let Some(client) = self.clients.get_mut(&client) {
+            client.name = Some(name);
+        }
+        self.send(
+            client,
+            ServerMessage::Welcome {
+                version: PROTOCOL_VERSION,
+            },
+        );
+        self.list_seeks(client);
+    }
+
+    /// Starts the game of `seek` between its author and `client`.
+    fn accept(&mut self, client: u32, name: String, seek: u32, now: Instant) {
+        let Some(index) = self.seeks.iter().position(|open| open.id == seek) else {
+            return self.send(client, ServerMessage::Error(format!("no seek {}", seek)));
+        };
+        if self.seeks[index].client == client {
+            return self.send(
+                client,
+                ServerMessage::Error(format!("seek {} is your own", seek)),
+            );
+        }
+        let seek = self.seeks.remove(index);
+        self.broadcast(ServerMessage::SeekRemoved { seek: seek.id });
+
+        let seeker_color = seek.color.unwrap_or_else(|| {
+            if random_bits().is_multiple_of(2) {
+                Color::Light
+            } else {
+                Color::Dark
+            }
+        });
+        let mut seats = [
+            Seat {
+                name: seek.name,
+                token: new_token(),
+                client: Some(seek.client),
+            },
+            Seat {
+                name,
+                token: new_token(),
+                client: Some(client),
+            },
+        ];
+        if seeker_color == Color::Dark {
+            seats.swap(0, 1);
+        }
+        let mut controller = GameController::new(Board::new(Color::Light), seek.time_control, now);
+        let record = controller.record_mut();
+        record.set_tag("Event", "chess-server".to_owned());
+        record.set_tag("Date", pgn_date(unix_timestamp()));
+        record.set_tag("White", seats[0].name.clone());
+        record.set_tag("Black", seats[1].name.clone());
+
+        let id = self.next_id();
+        self.games.insert(
+            id,
+            ServerGame {
+                controller,
+                seats,
+                spectators: Vec::new(),
+            },
+        );
+        for color in Color::ALL {
+            let seat_client = if seeker_color == color {
+                seek.client
+            } else {
+                client
+            };
+            self.send_game(seat_client, id, Some(color), now);
+        }
+        if let Some(game) = self.games.get(&id) {
+            self.broadcast(game_message(id, game));
+        }
+    }
+
+    /// Tells `client` all about `game`: who plays, the moves so far with the
+    /// clocks as they stand, a standing draw offer and the result. `color`
+    /// is the seat the client takes, whose token it gets.
+    fn send_game(&mut self, client: u32, game: u32, color: Option<Color>, now: Instant) {
+        let Some(server_game) = self.games.get(&game) else {
+            return;
+        };
+        let controller = &server_game.controller;
+        let mut messages = vec![ServerMessage::Joined {
+            game,
+            color,
+            token: color.map(|color| server_game.seats[seat_index(color)].token.clone()),
+            white: server_game.seats[0].name.clone(),
+            black: server_game.seats[1].name.clone(),
+            time_control: controller.clock().map(|clock| clock.control().clone()),
+            fen: controller.initial_board().to_fen(),
+        }];
+        let first_ply = controller.initial_board().number_of_moves;
+        let move_count = controller.record().moves.len();
+        for (index, recorded_move) in controller.record().moves.iter().enumerate() {
+            messages.push(ServerMessage::Moved {
+                game,
+                ply: first_ply + index as u16,
+                chess_move: recorded_move.chess_move,
+                // Only the last move's clocks still matter
+                clocks: if index + 1 == move_count {
+                    clocks(controller, now)
+                } else {
+                    None
+                },
+            });
+        }
+        if self.wants_positions(client) {
+            messages.push(position_message(game, controller));
+        }
+        if let Some(color) = controller.draw_offer() {
+            messages.push(ServerMessage::Draw {
+                game,
+                action: DrawAction::Offer,
+                color,
+            });
+        }
+        if let Some(outcome) = controller.outcome() {
+            messages.push(ServerMessage::Ended {
+                game,
+                result: outcome.result().to_owned(),
+                reason: outcome.to_string(),
+            });
+        }
+        for message in messages {
+            self.send(client, message);
+        }
+    }
+
+    /// Hands `event` to the game and tells its players and spectators what
+    /// came of it. Refusals go to `requester` alone.
+    fn play(&mut self, game: u32, event: GameEvent, requester: Option<u32>, now: Instant) {
+        let Some(server_game) = self.games.get_mut(&game) else {
+            return;
+        };
+        let changes = server_game.controller.handle(event, now);
+        let controller = &server_game.controller;
+        let mut messages = Vec::new();
+        let mut has_moved = false;
+        let mut has_ended = false;
+        for change in changes {
+            match change {
+                GameChange::Played {
+                    chess_move,
+                    previous,
+                } => {
+                    has_moved = true;
+                    messages.push(ServerMessage::Moved {
+                        game,
+                        ply: previous.number_of_moves,
+                        chess_move,
+                        clocks: clocks(controller, now),
+                    });
+                }
+                GameChange::DrawOffered(color) => messages.push(ServerMessage::Draw {
+                    game,
+                    action: DrawAction::Offer,
+                    color,
+                }),
+                GameChange::DrawDeclined(color) => messages.push(ServerMessage::Draw {
+                    game,
+                    action: DrawAction::Decline,
+                    color,
+                }),
+                GameChange::Ended(outcome) => {
+                    has_ended = true;
+                    messages.push(ServerMessage::Ended {
+                        game,
+                        result: outcome.result().to_owned(),
+                        reason: outcome.to_string(),
+                    });
+                }
+                GameChange::Refused(error) => {
+                    if let Some(requester) = requester {
+                        let message = ServerMessage::Error(format!("game {}: {}", game, error));
+                        self.send(requester, message);
+                    }
+                    return;
+                }
+                _ => (),
+            }
+        }
+        let audience: Vec<u32> = server_game
+            .seats
+            .iter()
+            .filter_map(|seat| seat.client)
+            .chain(server_game.spectators.iter().copied())
+            .collect();
+        // Once the moves are in, before the draw offers and the result
+        if has_moved {
+            let after_moves = messages
+                .iter()
+                .rposition(|message| matches!(message, ServerMessage::Moved { .. }))
+                .map_or(0, |index| index + 1);
+            messages.insert(after_moves, position_message(game, controller));
+        }
+        for message in messages {
+            let is_position = matches!(message, ServerMessage::Position { .. });
+            for client in &audience {
+                if !is_position || self.wants_positions(*client) {
+                    self.send(*client, message.clone());
+                }
+            }
+        }
+        if has_ended {
+            // The lobby learns the result, then the game is gone
+            if let Some(server_game) = self.games.get(&game) {
+                self.broadcast(game_message(game, server_game));
+            }
+            self.save_game(game);
+            self.games.remove(&game);
+        }
+    }
+
+    fn save_game(&mut self, game: u32) {
+        let (Some(path), Some(server_game)) = (&self.pgn_path, self.games.get(&game)) else {
+            return;
+        };
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{}", server_game.controller.record().to_pgn()));
+        if let Err(error) = result {
+            eprintln!(
+                "chess-server: failed to save game {} to {}: {}",
+                game,
+                path.display(),
+                error
+            );
+        }
+    }
+
+    fn seat_color(&self, game: u32, client: u32) -> Option<Color> {
+        let server_game = self.games.get(&game)?;
+        Color::ALL
+            .into_iter()
+            .find(|color| server_game.seats[seat_index(*color)].client == Some(client))
+    }
+
+    fn list_seeks(&mut self, client: u32) {
+        let seeks: Vec<ServerMessage> = self.seeks.iter().map(seek_message).collect();
+        for message in seeks {
+            self.send(client, message);
+        }
+    }
+
+    fn send(&mut self, client: u32, message: ServerMessage) {
+        if let Some(client) = self.clients.get_mut(&client) {
+            if client.transport.send(&message).is_err() {
+                client.is_closed = true;
+            }
+        }
+    }
+
+    /// Whether `client` is told the position after every move.
+    fn wants_positions(&self, client: u32) -> bool {
+        self.clients
+            .get(&client)
+            .is_some_and(|client| matches!(client.transport, Transport::WebSocket(_)))
+    }
+
+    /// Sends `message` to everyone in the lobby, that is every client that
+    /// said hello.
+    fn broadcast(&mut self, message: ServerMessage) {
+        let clients: Vec<u32> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| client.name.is_some())
+            .map(|(id, _)| *id)
+            .collect();
+        for client in clients {
+            self.send(client, message.clone());
+        }
+    }
+
+    fn close(&mut self, client: u32) {
+        if let Some(client) = self.clients.get_mut(&client) {
+            client.is_closed = true;
+        }
+    }
+
+    /// Forgets a client that went away: its seeks are withdrawn and its
+    /// seats wait for a resume.
+    fn remove_client(&mut self, client: u32) {
+        self.clients.remove(&client);
+        let withdrawn: Vec<u32> = self
+            .seeks
+            .iter()
+            .filter(|seek| seek.client == client)
+            .map(|seek| seek.id)
+            .collect();
+        self.seeks.retain(|seek| seek.client != client);
+        for seek in withdrawn {
+            self.broadcast(ServerMessage::SeekRemoved { seek });
+        }
+        for game in self.games.values_mut() {
+            for seat in &mut game.seats {
+                if seat.client == Some(client) {
+                    seat.client = None;
+                }
+            }
+            game.spectators.retain(|spectator| *spectator != client);
+        }
+    }
+}
+
+fn seek_message(seek: &Seek) -> ServerMessage {
+    ServerMessage::Seek {
+        seek: seek.id,
+        name: seek.name.clone(),
+        color: seek.color,
+        time_control: seek.time_control.clone(),
+    }
+}
+
+fn game_message(id: u32, game: &ServerGame) -> ServerMessage {
+    ServerMessage::Game {
+        game: id,
+        white: game.seats[0].name.clone(),
+        black: game.seats[1].name.clone(),
+        time_control: game.controller.clock().map(|clock| clock.control().clone()),
+        result: game
+            .controller
+            .outcome()
+            .map_or("*", |outcome| outcome.result())
+            .to_owned(),
+    }
+}
+
+/// The position of `game` with the moves the side to move has, none once the
+/// game is over.
+fn position_message(game: u32, controller: &GameController) -> ServerMessage {
+    ServerMessage::Position {
+        game,
+        fen: controller.board().to_fen(),
+        legal_moves: if controller.is_over() {
+            Vec::new()
+        } else {
+            controller.board().legal_moves()
+        },
+    }
+}
+
+fn clocks(controller: &GameController, now: Instant) -> Option<[Duration; 2]> {
+    controller
+        .clock()
+        .map(|clock| Color::ALL.map(|color| clock.remaining(color, now)))
+}
+
+fn no_game(game: u32) -> ServerMessage {
+    ServerMessage::Error(format!("no game {}", game))
+}
+
+fn not_playing(game: u32) -> ServerMessage {
+    ServerMessage::Error(format!("game {}: you are not playing", game))
+}
+
+fn full_address(address: &str) -> String {
+    if address.contains(':') {
+        address.to_owned()
+    } else {
+        format!("0.0.0.0:{}", address)
+    }
+}
+
+fn seat_index(color: Color) -> usize {
+    match color {
+        Color::Light => 0,
+        Color::Dark => 1,
+    }
+}
+
+/// Hard to guess bits from the standard library's randomly keyed hasher.
+fn random_bits() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos()),
+    );
+    hasher.finish()
+}
+
+fn new_token() -> String {
+    format!("{:016x}", random_bits())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpStream;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread::JoinHandle;
+
+    use serde_json::{json, Value};
+    use tungstenite::WebSocket;
+
+    use super::*;
+    use crate::backend::STARTING_FEN;
+
+    /// A server polled on a thread of its own until dropped.
+    struct Running {
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl Running {
+        fn start(mut server: Server) -> Running {
+            let stop = Arc::new(AtomicBool::new(false));
+            let thread = thread::spawn({
+                let stop = stop.clone();
+                move || {
+                    while !stop.load(Ordering::Relaxed) {
+                        server.poll(Instant::now()).unwrap();
+                        thread::sleep(POLL_INTERVAL);
+                    }
+                }
+            });
+            Running {
+                stop,
+                thread: Some(thread),
+            }
+        }
+    }
+
+    impl Drop for Running {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Relaxed);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    /// A client speaking lines, greeted as `name`.
+    struct LineClient {
+        stream: TcpStream,
+        reader: BufReader<TcpStream>,
+    }
+
+    impl LineClient {
+        fn connect(address: SocketAddr, name: &str) -> LineClient {
+            let stream = TcpStream::connect(address).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let reader = BufReader::new(stream.try_clone().unwrap());
+            let mut client = LineClient { stream, reader };
+            client.send(&format!("hello {} {}", PROTOCOL_VERSION, name));
+            client.expect("welcome ");
+            client
+        }
+
+        fn send(&mut self, line: &str) {
+            writeln!(self.stream, "{}", line).unwrap();
+        }
+
+        /// The first line starting with `prefix`, skipping those before.
+        fn expect(&mut self, prefix: &str) -> String {
+            loop {
+                let mut line = String::new();
+                assert!(
+                    self.reader.read_line(&mut line).unwrap() > 0,
+                    "no {}",
+                    prefix
+                );
+                if line.starts_with(prefix) {
+                    return line.trim_end().to_owned();
+                }
+            }
+        }
+    }
+
+    /// A browser-like client speaking JSON over WebSocket, greeted as `name`.
+    struct WebSocketClient {
+        socket: WebSocket<TcpStream>,
+    }
+
+    impl WebSocketClient {
+        fn connect(address: SocketAddr, name: &str) -> WebSocketClient {
+            let stream = TcpStream::connect(address).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let (socket, _) = tungstenite::client(format!("ws://{}/", address), stream).unwrap();
+            let mut client = WebSocketClient { socket };
+            client.send(json!({ "type": "hello", "version": PROTOCOL_VERSION, "name": name }));
+            client.expect("welcome");
+            client
+        }
+
+        fn send(&mut self, message: Value) {
+            self.socket
+                .send(tungstenite::Message::Text(message.to_string()))
+                .unwrap();
+        }
+
+        fn next(&mut self) -> Value {
+            loop {
+                if let tungstenite::Message::Text(text) = self.socket.read().unwrap() {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        /// The first message of type `kind`, skipping those before.
+        fn expect(&mut self, kind: &str) -> Value {
+            loop {
+                let message = self.next();
+                if message["type"] == kind {
+                    return message;
+                }
+            }
+        }
+
+        /// The `moved` message of `chess_move`, past those of earlier moves.
+        fn expect_moved(&mut self, chess_move: &str) -> Value {
+            loop {
+                let moved = self.expect("moved");
+                if moved["move"] == chess_move {
+                    return moved;
+                }
+            }
+        }
+    }
+
+    fn keys(message: &Value) -> Vec<&str> {
+        let mut keys: Vec<&str> = message
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    fn word(line: &str, index: usize) -> &str {
+        line.split_whitespace().nth(index).unwrap()
+    }
+
+    #[test]
+    fn players_resume_with_their_token_and_spectators_only_watch() {
+        let server = Server::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap();
+        let _running = Running::start(server);
+
+        let mut alice = LineClient::connect(address, "alice");
+        let mut bob = LineClient::connect(address, "bob");
+        alice.send("seek - white");
+        let seek = alice.expect("seek ");
+        bob.send(&format!("accept {}", word(&seek, 1)));
+        let joined = alice.expect("joined ");
+        let (game, token) = (word(&joined, 1).to_owned(), word(&joined, 3).to_owned());
+        bob.expect("joined ");
+
+        // A spectator sees the moves but cannot make them
+        let mut carol = LineClient::connect(address, "carol");
+        carol.send(&format!("watch {}", game));
+        let watching = carol.expect("joined ");
+        assert_eq!(word(&watching, 2), "spectator");
+        assert_eq!(word(&watching, 3), "-");
+        alice.send(&format!("move {} e2e4", game));
+        assert!(carol
+            .expect("moved ")
+            .starts_with(&format!("moved {} 0 e2e4", game)));
+        for line in [format!("move {} e7e5", game), format!("resign {}", game)] {
+            carol.send(&line);
+            assert_eq!(
+                carol.expect("error "),
+                format!("error game {}: you are not playing", game)
+            );
+        }
+        bob.send(&format!("move {} e7e5", game));
+        carol.expect(&format!("moved {} 1 e7e5", game));
+
+        // Alice loses the connection; a wrong token does not give the seat back
+        drop(alice);
+        let mut alice = LineClient::connect(address, "alice");
+        alice.send(&format!("resume {} {}", game, "0".repeat(token.len())));
+        assert_eq!(
+            alice.expect("error "),
+            format!("error game {}: no seat with that token", game)
+        );
+        alice.send(&format!("move {} g1f3", game));
+        assert_eq!(
+            alice.expect("error "),
+            format!("error game {}: you are not playing", game)
+        );
+
+        // The right one gives back the seat and the game so far
+        alice.send(&format!("resume {} {}", game, token));
+        let resumed = alice.expect("joined ");
+        assert_eq!(word(&resumed, 2), "white");
+        assert_eq!(word(&resumed, 3), token);
+        assert!(resumed.ends_with(STARTING_FEN));
+        alice.expect(&format!("moved {} 0 e2e4", game));
+        alice.expect(&format!("moved {} 1 e7e5", game));
+        alice.send(&format!("move {} g1f3", game));
+        bob.expect(&format!("moved {} 2 g1f3", game));
+        carol.expect(&format!("moved {} 2 g1f3", game));
+    }
+
+    #[test]
+    fn a_finished_game_is_saved_and_dropped() {
+        let path = std::env::temp_dir().join(format!("chess-server-{}.pgn", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        server.save_games_to(path.clone());
+        let address = server.local_addr().unwrap();
+        let running = Running::start(server);
+
+        let mut alice = LineClient::connect(address, "alice");
+        let mut bob = LineClient::connect(address, "bob");
+        alice.send("seek - white");
+        let seek = alice.expect("seek ");
+        bob.send(&format!("accept {}", word(&seek, 1)));
+        let joined = alice.expect("joined ");
+        assert_eq!(word(&joined, 2), "white");
+        let game = word(&joined, 1);
+
+        alice.send(&format!("resign {}", game));
+        assert!(bob
+            .expect("ended ")
+            .starts_with(&format!("ended {} 0-1", game)));
+        bob.expect(&format!("game {} alice bob - 0-1", game));
+        bob.send(&format!("watch {}", game));
+        assert_eq!(bob.expect("error "), format!("error no game {}", game));
+
+        drop(running);
+        let pgn = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert!(pgn.contains("[White \"alice\"]"), "{}", pgn);
+        assert!(pgn.contains("[Result \"0-1\"]"), "{}", pgn);
+    }
+
+    #[test]
+    fn websocket_clients_play_a_game_in_json() {
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let address = server.listen_websocket("127.0.0.1:0").unwrap();
+        let _running = Running::start(server);
+
+        let mut alice = WebSocketClient::connect(address, "alice");
+        let mut bob = WebSocketClient::connect(address, "bob");
+        alice.send(json!({ "type": "seek", "time_control": "300+2", "color": "white" }));
+        let seek = bob.expect("seek");
+        assert_eq!(seek["name"], "alice");
+        bob.send(json!({ "type": "accept", "seek": seek["seek"] }));
+
+        let joined = bob.expect("joined");
+        assert_eq!(
+            keys(&joined),
+            [
+                "black",
+                "color",
+                "fen",
+                "game",
+                "time_control",
+                "token",
+                "type",
+                "white"
+            ]
+        );
+        assert_eq!(joined["color"], "black");
+        assert_eq!(joined["white"], "alice");
+        assert_eq!(joined["black"], "bob");
+        assert_eq!(joined["time_control"], "300+2");
+        assert_eq!(joined["fen"], STARTING_FEN);
+        assert_eq!(joined["token"].as_str().unwrap().len(), 16);
+        let game = joined["game"].clone();
+        assert_eq!(alice.expect("joined")["color"], "white");
+        let position = bob.expect("position");
+        assert_eq!(position["game"], game);
+        assert_eq!(position["legal_moves"].as_array().unwrap().len(), 20);
+
+        // Fool's mate
+        alice.send(json!({ "type": "move", "game": game, "move": "f2f3" }));
+        let moved = bob.expect_moved("f2f3");
+        assert_eq!(keys(&moved), ["clocks", "game", "move", "ply", "type"]);
+        assert_eq!(moved["game"], game);
+        assert_eq!(moved["ply"], 0);
+        assert_eq!(moved["move"], "f2f3");
+        for color in ["white", "black"] {
+            let clock = moved["clocks"][color].as_u64().unwrap();
+            assert!(clock > 290_000 && clock <= 302_000, "{}", clock);
+        }
+        let position = bob.expect("position");
+        assert_eq!(keys(&position), ["fen", "game", "legal_moves", "type"]);
+        assert_eq!(
+            position["fen"],
+            "rnbqkbnr/pppppppp/8/8/8/5P2/PPPPP1PP/RNBQKBNR b KQkq - 0 1"
+        );
+        assert!(position["legal_moves"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("e7e5")));
+        bob.send(json!({ "type": "move", "game": game, "move": "e7e5" }));
+        alice.expect_moved("e7e5");
+        alice.send(json!({ "type": "move", "game": game, "move": "g2g4" }));
+        bob.expect_moved("g2g4");
+        bob.send(json!({ "type": "move", "game": game, "move": "d8h4" }));
+
+        for client in [&mut alice, &mut bob] {
+            client.expect_moved("d8h4");
+            // Straight after the move, before the result
+            let position = client.expect("position");
+            assert_eq!(position["legal_moves"], json!([]));
+            let ended = client.next();
+            assert_eq!(ended["type"], "ended");
+            assert_eq!(ended["result"], "0-1");
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! The WebSocket endpoint: connections that carry the server's messages as
+//! JSON text messages instead of lines, see `json`.
+
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use tungstenite::handshake::server::{NoCallback, ServerHandshake};
+use tungstenite::handshake::{HandshakeError, MidHandshake};
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::{Error, Message, WebSocket};
+
+use super::messages::{ClientMessage, ServerMessage};
+use super::MAX_UNSENT;
+use crate::protocol::ProtocolError;
+
+/// How long a new connection gets to finish its opening handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Connections in their opening handshake at a time; more are turned away.
+const MAX_HANDSHAKES: usize = 64;
+
+type Handshake = MidHandshake<ServerHandshake<TcpStream, NoCallback>>;
+
+/// Takes WebSocket connections without blocking. Handshakes go on a step
+/// at a time with every call, so a slow browser holds up nobody.
+pub struct WebSocketListener {
+    listener: TcpListener,
+    /// Handshakes waiting on the client, with when they started.
+    handshakes: Vec<(Handshake, Instant)>,
+}
+
+impl WebSocketListener {
+    pub fn bind(address: &str) -> io::Result<WebSocketListener> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(WebSocketListener {
+            listener,
+            handshakes: Vec::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Connections that finished their handshake since the last call.
+    pub fn accept(&mut self) -> io::Result<Vec<WebSocketConnection>> {
+        let mut connections = Vec::new();
+        let handshakes = std::mem::take(&mut self.handshakes);
+        for (handshake, started) in handshakes {
+            // One that fails or takes too long drops the stream
+            if started.elapsed() < HANDSHAKE_TIMEOUT {
+                self.advance(handshake.handshake(), started, &mut connections);
+            }
+        }
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if self.handshakes.len() >= MAX_HANDSHAKES
+                        || stream.set_nonblocking(true).is_err()
+                    {
+                        continue;
+                    }
+                    let _ = stream.set_nodelay(true);
+                    let config = WebSocketConfig {
+                        max_write_buffer_size: MAX_UNSENT,
+                        ..WebSocketConfig::default()
+                    };
+                    let result = tungstenite::accept_with_config(stream, Some(config));
+                    self.advance(result, Instant::now(), &mut connections);
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(connections)
+    }
+
+    fn advance(
+        &mut self,
+        result: Result<
+            WebSocket<TcpStream>,
+            HandshakeError<ServerHandshake<TcpStream, NoCallback>>,
+        >,
+        started: Instant,
+        connections: &mut Vec<WebSocketConnection>,
+    ) {
+        match result {
+            Ok(socket) => connections.push(WebSocketConnection { socket }),
+            Err(HandshakeError::Interrupted(handshake)) => {
+                self.handshakes.push((handshake, started))
+            }
+            Err(HandshakeError::Failure(_)) => (),
+        }
+    }
+}
+
+/// A WebSocket on a nonblocking stream, one JSON message per text message.
+/// A client that falls too far behind on reading is given up on, as line
+/// clients are.
+pub struct WebSocketConnection {
+    socket: WebSocket<TcpStream>,
+}
+
+impl WebSocketConnection {
+    pub fn send(&mut self, message: &ServerMessage) -> Result<(), ProtocolError> {
+        match self.socket.send(Message::Text(message.to_json())) {
+            // What did not fit is written with the next flush, up to
+            // `MAX_UNSENT`; past that tungstenite refuses and the client goes
+            Ok(()) => Ok(()),
+            Err(error) => would_block(error),
+        }
+    }
+
+    /// The next message if one has arrived. Pings are answered on the way.
+    pub fn try_receive(&mut self) -> Result<Option<ClientMessage>, ProtocolError> {
+        if let Err(error) = self.socket.flush() {
+            would_block(error)?;
+        }
+        loop {
+            let text = match self.socket.read() {
+                Ok(Message::Text(text)) => text,
+                Ok(Message::Binary(_)) => {
+                    return Err(ProtocolError::Malformed("binary message".to_owned()))
+                }
+                Ok(Message::Close(_)) => return Err(ProtocolError::Closed),
+                Ok(_) => continue,
+                Err(error) => return would_block(error).map(|()| None),
+            };
+            if text.trim().is_empty() {
+                continue;
+            }
+            return ClientMessage::from_json(&text)
+                .map(Some)
+                .ok_or(ProtocolError::Malformed(text));
+        }
+    }
+}
+
+/// Waiting on the stream is no error on a nonblocking socket; anything else
+/// ends the connection.
+fn would_block(error: Error) -> Result<(), ProtocolError> {
+    match error {
+        Error::Io(error) if error.kind() == ErrorKind::WouldBlock => Ok(()),
+        Error::Io(error) => Err(ProtocolError::Io(error)),
+        _ => Err(ProtocolError::Closed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn listener() -> WebSocketListener {
+        WebSocketListener::bind("127.0.0.1:0").unwrap()
+    }
+
+    /// Polls the listener while a client on another thread shakes hands.
+    fn pair(listener: &mut WebSocketListener) -> (WebSocketConnection, WebSocket<TcpStream>) {
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            tungstenite::client(format!("ws://{}/", address), stream)
+                .unwrap()
+                .0
+        });
+        loop {
+            if let Some(connection) = listener.accept().unwrap().pop() {
+                return (connection, client.join().unwrap());
+            }
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn a_client_that_does_not_read_is_given_up_on_without_blocking() {
+        let mut listener = listener();
+        let (mut connection, _client) = pair(&mut listener);
+        let message = ServerMessage::Error("x".repeat(1000));
+        let sends = (0..100_000)
+            .position(|_| connection.send(&message).is_err())
+            .expect("sending never failed");
+        assert!(sends * 1000 > MAX_UNSENT);
+    }
+
+    #[test]
+    fn pending_handshakes_are_bounded_in_number() {
+        let mut listener = listener();
+        let address = listener.local_addr().unwrap();
+        let _silent: Vec<TcpStream> = (0..MAX_HANDSHAKES + 1)
+            .map(|_| TcpStream::connect(address).unwrap())
+            .collect();
+        assert!(listener.accept().unwrap().is_empty());
+        assert_eq!(listener.handshakes.len(), MAX_HANDSHAKES);
+    }
+
+    #[test]
+    fn a_handshake_that_takes_too_long_is_dropped() {
+        let mut listener = listener();
+        let _silent = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        assert!(listener.accept().unwrap().is_empty());
+        assert_eq!(listener.handshakes.len(), 1);
+        listener.handshakes[0].1 -= HANDSHAKE_TIMEOUT;
+        assert!(listener.accept().unwrap().is_empty());
+        assert!(listener.handshakes.is_empty());
+    }
+}
@@ -0,0 +1,147 @@
+use crate::backend::{Board, Piece};
+use crate::ui::renderer::{cell_lower_left_position, PIECE_CELL_SIZE};
+use crate::ui::shaders::primitives::PieceInstance;
+use crate::ui::shaders::PieceAtlas;
+
+type Cell = (usize, usize);
+
+/// Tween between two positions: pieces that changed squares slide, pieces
+/// that disappeared fade out and new ones fade in. Works out what moved by
+/// comparing the boards, so castling or undoing several moves need nothing
+/// special.
+pub struct MoveAnimation {
+    /// Piece, from cell, to cell.
+    slides: Vec<(Piece, Cell, Cell)>,
+    fade_outs: Vec<(Piece, Cell)>,
+    fade_ins: Vec<(Piece, Cell)>,
+    unchanged: Vec<(Piece, Cell)>,
+    started_at: f32,
+    duration: f32,
+}
+
+impl MoveAnimation {
+    /// `started_at` and `duration` are seconds on the clock passed to shaders.
+    pub fn new(from: &Board, to: &Board, started_at: f32, duration: f32) -> MoveAnimation {
+        let mut departed = Vec::new();
+        let mut arrived = Vec::new();
+        let mut unchanged = Vec::new();
+        for pos_x in 0..8 {
+            for pos_y in 0..8 {
+                let before = from.state[7 - pos_y][pos_x];
+                let after = to.state[7 - pos_y][pos_x];
+                match (before, after) {
+                    (Some(before), Some(after)) if is_same_piece(&before, &after) => {
+                        unchanged.push((after, (pos_x, pos_y)))
+                    }
+                    _ => {
+                        if let Some(before) = before {
+                            departed.push((before, (pos_x, pos_y)));
+                        }
+                        if let Some(after) = after {
+                            arrived.push((after, (pos_x, pos_y)));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Each arrival slides from the nearest departure of the same piece kind and colour
+        let mut slides = Vec::new();
+        let mut fade_ins = Vec::new();
+        for (piece, to_cell) in arrived {
+            let nearest = departed
+                .iter()
+                .enumerate()
+                .filter(|(_, (departed_piece, _))| is_same_piece(departed_piece, &piece))
+                .min_by_key(|(_, (_, from_cell))| cell_distance(from_cell, &to_cell))
+                .map(|(index, _)| index);
+            match nearest {
+                Some(index) => {
+                    let (_, from_cell) = departed.remove(index);
+                    slides.push((piece, from_cell, to_cell));
+                }
+                None => fade_ins.push((piece, to_cell)),
+            }
+        }
+
+        MoveAnimation {
+            slides,
+            fade_outs: departed,
+            fade_ins,
+            unchanged,
+            started_at,
+            duration,
+        }
+    }
+
+    pub fn is_finished(&self, time: f32) -> bool {
+        time >= self.started_at + self.duration
+    }
+
+    /// Pieces at `time`. Fading pieces come first so sliding ones pass over them.
+    pub fn piece_instances(
+        &self,
+        atlas: &PieceAtlas,
+        pixel_size: u32,
+        time: f32,
+    ) -> Vec<PieceInstance> {
+        let progress = if self.duration > 0.0 {
+            ((time - self.started_at) / self.duration).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        // Smoothstep: starts and ends at rest
+        let eased = progress * progress * (3.0 - 2.0 * progress);
+
+        let instance = |piece: &Piece, position: (f32, f32), alpha: f32| {
+            atlas
+                .region(piece.kind, piece.color, pixel_size)
+                .map(|region| {
+                    PieceInstance::new(
+                        cell_lower_left_position(position.0, position.1),
+                        PIECE_CELL_SIZE,
+                        region,
+                    )
+                    .with_alpha(alpha)
+                })
+        };
+        let at_cell = |cell: &Cell| (cell.0 as f32, cell.1 as f32);
+
+        let mut instances = Vec::with_capacity(33);
+        instances.extend(
+            self.unchanged
+                .iter()
+                .filter_map(|(piece, cell)| instance(piece, at_cell(cell), 1.0)),
+        );
+        instances.extend(
+            self.fade_outs
+                .iter()
+                .filter_map(|(piece, cell)| instance(piece, at_cell(cell), 1.0 - eased)),
+        );
+        instances.extend(
+            self.fade_ins
+                .iter()
+                .filter_map(|(piece, cell)| instance(piece, at_cell(cell), eased)),
+        );
+        instances.extend(
+            self.slides
+                .iter()
+                .filter_map(|(piece, from_cell, to_cell)| {
+                    let position = (
+                        from_cell.0 as f32 + (to_cell.0 as f32 - from_cell.0 as f32) * eased,
+                        from_cell.1 as f32 + (to_cell.1 as f32 - from_cell.1 as f32) * eased,
+                    );
+                    instance(piece, position, 1.0)
+                }),
+        );
+        instances
+    }
+}
+
+fn is_same_piece(a: &Piece, b: &Piece) -> bool {
+    a.kind == b.kind && a.color == b.color
+}
+
+fn cell_distance(a: &Cell, b: &Cell) -> usize {
+    a.0.abs_diff(b.0) + a.1.abs_diff(b.1)
+}
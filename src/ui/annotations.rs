@@ -0,0 +1,76 @@
+use imgui::{ImColor32, Ui};
+
+use crate::backend::{AnnotationColor, Annotations, Board};
+use crate::export::annotation_rgba;
+use crate::export::raster::arrow_polygon;
+use crate::ui::layout::BoardLayout;
+
+/// Modifier keys to annotation colour, as on common chess sites: none for
+/// green, Shift or Ctrl for red, Alt for blue, both for yellow.
+pub fn annotation_color(shift_or_ctrl: bool, alt: bool) -> AnnotationColor {
+    match (shift_or_ctrl, alt) {
+        (false, false) => AnnotationColor::Green,
+        (true, false) => AnnotationColor::Red,
+        (false, true) => AnnotationColor::Blue,
+        (true, true) => AnnotationColor::Yellow,
+    }
+}
+
+/// Draws marked squares as rings and arrows between square centres, over
+/// the board and pieces.
+pub fn draw_annotations(
+    ui: &Ui,
+    board: &Board,
+    layout: &BoardLayout,
+    annotations: &Annotations,
+    scale_factor: f64,
+    framebuffer_height: u32,
+) {
+    let cell_centre = |square| {
+        let ([left, top], cell_size) = layout.logical_cell_rect(
+            &board.square_to_cell(square),
+            scale_factor,
+            framebuffer_height,
+        );
+        ([left + cell_size / 2.0, top + cell_size / 2.0], cell_size)
+    };
+
+    let draw_list = ui.get_background_draw_list();
+    for (square, color) in &annotations.marks {
+        let (centre, cell_size) = cell_centre(*square);
+        draw_list
+            .add_circle(centre, cell_size * 0.45, im_color(*color))
+            .num_segments(32)
+            .thickness(cell_size * 0.07)
+            .build();
+    }
+    for (chess_move, color) in &annotations.arrows {
+        let (from, cell_size) = cell_centre(chess_move.from);
+        let (to, _) = cell_centre(chess_move.to);
+        let outline = arrow_polygon(
+            (from[0], from[1]),
+            (to[0], to[1]),
+            cell_size * 0.15,
+            cell_size * 0.4,
+            cell_size * 0.4,
+        );
+        let point = |index: usize| [outline[index].0, outline[index].1];
+        // imgui fills convex shapes only: the shaft and the head separately.
+        draw_list
+            .add_polyline(
+                vec![point(0), point(1), point(5), point(6)],
+                im_color(*color),
+            )
+            .filled(true)
+            .build();
+        draw_list
+            .add_triangle(point(2), point(3), point(4), im_color(*color))
+            .filled(true)
+            .build();
+    }
+}
+
+fn im_color(color: AnnotationColor) -> ImColor32 {
+    let [red, green, blue, alpha] = annotation_rgba(color);
+    ImColor32::from_rgba(red, green, blue, alpha)
+}
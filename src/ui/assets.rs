@@ -0,0 +1,363 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use image::RgbaImage;
+use resvg::{tiny_skia, usvg};
+
+/// Environment variable pointing at a directory that overrides bundled assets.
+pub const ASSETS_DIR_ENV_VAR: &str = "CHESS_ASSETS_DIR";
+
+/// Assets compiled into the binary, addressed by the same relative names
+/// that are looked up in the override directory.
+const EMBEDDED_ASSETS: &[(&str, &[u8])] = &[
+    (
+        "shaders/board.vert",
+        include_bytes!("shaders/data/board.vert"),
+    ),
+    (
+        "shaders/board.frag",
+        include_bytes!("shaders/data/board.frag"),
+    ),
+    (
+        "shaders/piece.vert",
+        include_bytes!("shaders/data/piece.vert"),
+    ),
+    (
+        "shaders/piece.frag",
+        include_bytes!("shaders/data/piece.frag"),
+    ),
+    (
+        "pieces/light/pawn.png",
+        include_bytes!("resources/pieces/light/pawn.png"),
+    ),
+    (
+        "pieces/light/rook.png",
+        include_bytes!("resources/pieces/light/rook.png"),
+    ),
+    (
+        "pieces/light/knight.png",
+        include_bytes!("resources/pieces/light/knight.png"),
+    ),
+    (
+        "pieces/light/bishop.png",
+        include_bytes!("resources/pieces/light/bishop.png"),
+    ),
+    (
+        "pieces/light/queen.png",
+        include_bytes!("resources/pieces/light/queen.png"),
+    ),
+    (
+        "pieces/light/king.png",
+        include_bytes!("resources/pieces/light/king.png"),
+    ),
+    (
+        "pieces/dark/pawn.png",
+        include_bytes!("resources/pieces/dark/pawn.png"),
+    ),
+    (
+        "pieces/dark/rook.png",
+        include_bytes!("resources/pieces/dark/rook.png"),
+    ),
+    (
+        "pieces/dark/knight.png",
+        include_bytes!("resources/pieces/dark/knight.png"),
+    ),
+    (
+        "pieces/dark/bishop.png",
+        include_bytes!("resources/pieces/dark/bishop.png"),
+    ),
+    (
+        "pieces/dark/queen.png",
+        include_bytes!("resources/pieces/dark/queen.png"),
+    ),
+    (
+        "pieces/dark/king.png",
+        include_bytes!("resources/pieces/dark/king.png"),
+    ),
+    (
+        "pieces-flat/light/pawn.svg",
+        include_bytes!("resources/pieces-flat/light/pawn.svg"),
+    ),
+    (
+        "pieces-flat/light/rook.svg",
+        include_bytes!("resources/pieces-flat/light/rook.svg"),
+    ),
+    (
+        "pieces-flat/light/knight.svg",
+        include_bytes!("resources/pieces-flat/light/knight.svg"),
+    ),
+    (
+        "pieces-flat/light/bishop.svg",
+        include_bytes!("resources/pieces-flat/light/bishop.svg"),
+    ),
+    (
+        "pieces-flat/light/queen.svg",
+        include_bytes!("resources/pieces-flat/light/queen.svg"),
+    ),
+    (
+        "pieces-flat/light/king.svg",
+        include_bytes!("resources/pieces-flat/light/king.svg"),
+    ),
+    (
+        "pieces-flat/dark/pawn.svg",
+        include_bytes!("resources/pieces-flat/dark/pawn.svg"),
+    ),
+    (
+        "pieces-flat/dark/rook.svg",
+        include_bytes!("resources/pieces-flat/dark/rook.svg"),
+    ),
+    (
+        "pieces-flat/dark/knight.svg",
+        include_bytes!("resources/pieces-flat/dark/knight.svg"),
+    ),
+    (
+        "pieces-flat/dark/bishop.svg",
+        include_bytes!("resources/pieces-flat/dark/bishop.svg"),
+    ),
+    (
+        "pieces-flat/dark/queen.svg",
+        include_bytes!("resources/pieces-flat/dark/queen.svg"),
+    ),
+    (
+        "pieces-flat/dark/king.svg",
+        include_bytes!("resources/pieces-flat/dark/king.svg"),
+    ),
+    (
+        "fonts/Roboto-Regular.ttf",
+        include_bytes!("resources/Roboto-Regular.ttf"),
+    ),
+    (
+        "themes/classic.theme",
+        include_bytes!("resources/themes/classic.theme"),
+    ),
+    (
+        "themes/flat.theme",
+        include_bytes!("resources/themes/flat.theme"),
+    ),
+    (
+        "themes/green.theme",
+        include_bytes!("resources/themes/green.theme"),
+    ),
+];
+
+#[derive(Debug)]
+pub enum AssetError {
+    NotFound(String),
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    NotUtf8(String),
+    Image {
+        name: String,
+        source: image::ImageError,
+    },
+    Svg {
+        name: String,
+        source: resvg::usvg::Error,
+    },
+    Config {
+        name: String,
+        message: String,
+    },
+    Texture {
+        name: String,
+        message: String,
+    },
+    Shader {
+        name: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::NotFound(name) => write!(f, "asset `{}` not found", name),
+            AssetError::Io { path, source } => {
+                write!(f, "failed to read `{}`: {}", path.display(), source)
+            }
+            AssetError::NotUtf8(name) => write!(f, "asset `{}` is not valid UTF-8", name),
+            AssetError::Image { name, source } => {
+                write!(f, "asset `{}` is not a readable image: {}", name, source)
+            }
+            AssetError::Svg { name, source } => {
+                write!(f, "asset `{}` is not a readable SVG: {}", name, source)
+            }
+            AssetError::Config { name, message } => write!(f, "invalid `{}`: {}", name, message),
+            AssetError::Texture { name, message } => {
+                write!(f, "failed to upload `{}` to the GPU: {}", name, message)
+            }
+            AssetError::Shader { name, message } => {
+                write!(f, "failed to compile shader `{}`: {}", name, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AssetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AssetError::Io { source, .. } => Some(source),
+            AssetError::Image { source, .. } => Some(source),
+            AssetError::Svg { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Resolves assets by relative name: the override directory first, if any,
+/// then the copies embedded at build time.
+#[derive(Clone, Debug, Default)]
+pub struct Assets {
+    override_dir: Option<PathBuf>,
+}
+
+impl Assets {
+    pub fn new(override_dir: Option<PathBuf>) -> Assets {
+        Assets { override_dir }
+    }
+
+    /// Uses the directory from `CHESS_ASSETS_DIR` as override, when set.
+    pub fn from_env() -> Assets {
+        Assets::new(std::env::var_os(ASSETS_DIR_ENV_VAR).map(PathBuf::from))
+    }
+
+    pub fn read_bytes(&self, name: &str) -> Result<Cow<'static, [u8]>, AssetError> {
+        if let Some(override_dir) = &self.override_dir {
+            let path = override_dir.join(name);
+            match fs::read(&path) {
+                Ok(bytes) => return Ok(Cow::Owned(bytes)),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+                Err(source) => return Err(AssetError::Io { path, source }),
+            }
+        }
+        EMBEDDED_ASSETS
+            .iter()
+            .find(|(embedded_name, _)| *embedded_name == name)
+            .map(|(_, bytes)| Cow::Borrowed(*bytes))
+            .ok_or_else(|| AssetError::NotFound(name.to_owned()))
+    }
+
+    pub fn read_text(&self, name: &str) -> Result<String, AssetError> {
+        let bytes = self.read_bytes(name)?;
+        String::from_utf8(bytes.into_owned()).map_err(|_| AssetError::NotUtf8(name.to_owned()))
+    }
+
+    pub fn load_image(&self, name: &str) -> Result<RgbaImage, AssetError> {
+        let bytes = self.read_bytes(name)?;
+        image::load_from_memory(&bytes)
+            .map(|image| image.to_rgba8())
+            .map_err(|source| AssetError::Image {
+                name: name.to_owned(),
+                source,
+            })
+    }
+
+    /// Rasterises an SVG into a `size` x `size` image, keeping its aspect ratio.
+    pub fn load_svg(&self, name: &str, size: u32) -> Result<RgbaImage, AssetError> {
+        let bytes = self.read_bytes(name)?;
+        let tree = usvg::Tree::from_data(&bytes, &usvg::Options::default()).map_err(|source| {
+            AssetError::Svg {
+                name: name.to_owned(),
+                source,
+            }
+        })?;
+        let size = size.max(1);
+        let mut pixmap = tiny_skia::Pixmap::new(size, size).expect("size is not zero");
+        let tree_size = tree.size();
+        let scale = size as f32 / tree_size.width().max(tree_size.height());
+        let offset = (
+            (size as f32 - tree_size.width() * scale) / 2.0,
+            (size as f32 - tree_size.height() * scale) / 2.0,
+        );
+        let transform =
+            tiny_skia::Transform::from_scale(scale, scale).post_translate(offset.0, offset.1);
+        resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+        let mut image = RgbaImage::new(size, size);
+        for (pixel, premultiplied) in image.pixels_mut().zip(pixmap.pixels()) {
+            let color = premultiplied.demultiply();
+            *pixel = image::Rgba([color.red(), color.green(), color.blue(), color.alpha()]);
+        }
+        Ok(image)
+    }
+
+    /// Names of assets directly inside `directory`, bundled and overridden.
+    pub fn list(&self, directory: &str) -> Vec<String> {
+        let prefix = format!("{}/", directory.trim_end_matches('/'));
+        let mut names: Vec<String> = EMBEDDED_ASSETS
+            .iter()
+            .map(|(name, _)| *name)
+            .filter(|name| name.starts_with(&prefix) && !name[prefix.len()..].contains('/'))
+            .map(str::to_owned)
+            .collect();
+        if let Some(override_dir) = &self.override_dir {
+            if let Ok(entries) = fs::read_dir(override_dir.join(directory)) {
+                for entry in entries.flatten() {
+                    if entry.path().is_file() {
+                        if let Some(file_name) = entry.file_name().to_str() {
+                            names.push(format!("{}{}", prefix, file_name));
+                        }
+                    }
+                }
+            }
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{Color, PieceKind};
+    use crate::ui::theme::{list_themes, PieceSet, Theme};
+
+    #[test]
+    fn bundled_svg_set_rasterises_at_cell_sizes() {
+        let assets = Assets::default();
+        let theme = Theme::load(&assets, "themes/flat.theme").unwrap();
+        assert_eq!(theme.pieces, PieceSet::Svg("pieces-flat".to_owned()));
+        for size in [37, 96] {
+            let images = theme.load_piece_images(&assets, size).unwrap();
+            assert_eq!(images.len(), 12);
+            for ((kind, color), image) in &images {
+                assert_eq!(image.dimensions(), (size, size), "{:?} {:?}", color, kind);
+                let opaque = image.pixels().filter(|pixel| pixel[3] == 255).count();
+                let area = (size * size) as usize;
+                // Drawn, but not filling the whole cell
+                assert!(opaque > area / 10, "{:?} {:?} at {}", color, kind, size);
+                assert!(opaque < area * 3 / 4, "{:?} {:?} at {}", color, kind, size);
+                assert_eq!(image.get_pixel(0, 0)[3], 0);
+            }
+            // The sides can be told apart by their fill
+            let brightness = |color| {
+                let image = &images[&(PieceKind::Queen, color)];
+                let (sum, count) = image
+                    .pixels()
+                    .filter(|pixel| pixel[3] == 255)
+                    .fold((0u64, 0u64), |(sum, count), pixel| {
+                        (sum + pixel[0] as u64, count + 1)
+                    });
+                sum / count
+            };
+            assert!(brightness(Color::Light) > brightness(Color::Dark) + 100);
+        }
+    }
+
+    #[test]
+    fn every_bundled_theme_loads_with_its_pieces() {
+        let assets = Assets::default();
+        let themes = list_themes(&assets);
+        assert!(themes.len() >= 3);
+        for name in themes {
+            let theme = Theme::load(&assets, &name).unwrap();
+            theme.load_piece_images(&assets, 64).unwrap();
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use imgui::Ui;
+
+use crate::backend::{Board, Square};
+use crate::ui::layout::BoardLayout;
+use crate::ui::theme::Theme;
+
+/// Writes file letters into the bottom row and rank digits into the left
+/// column of squares, as seen on screen, so they follow `board.is_reversed`.
+/// Uses the imgui fonts, on the background layer under the panels.
+pub fn draw_coordinates(
+    ui: &Ui,
+    board: &Board,
+    layout: &BoardLayout,
+    theme: &Theme,
+    scale_factor: f64,
+    framebuffer_height: u32,
+) {
+    let draw_list = ui.get_background_draw_list();
+    for index in 0..8 {
+        let file_square = board.cell_to_square(&(index, 0));
+        let text = file_square.file_char().to_string();
+        let text_size = ui.calc_text_size(&text);
+        let ([cell_left, cell_top], cell_size) =
+            layout.logical_cell_rect(&(index, 0), scale_factor, framebuffer_height);
+        let padding = (cell_size * 0.06).max(2.0);
+        draw_list.add_text(
+            [
+                cell_left + cell_size - padding - text_size[0],
+                cell_top + cell_size - padding - text_size[1],
+            ],
+            label_color(theme, file_square),
+            &text,
+        );
+
+        let rank_square = board.cell_to_square(&(0, index));
+        let ([cell_left, cell_top], _) =
+            layout.logical_cell_rect(&(0, index), scale_factor, framebuffer_height);
+        draw_list.add_text(
+            [cell_left + padding, cell_top + padding],
+            label_color(theme, rank_square),
+            rank_square.rank_char().to_string(),
+        );
+    }
+}
+
+/// The colour of the other square kind, so labels read on both.
+fn label_color(theme: &Theme, square: Square) -> [f32; 4] {
+    let color = if (square.file + square.rank) % 2 == 1 {
+        theme.dark_square
+    } else {
+        theme.light_square
+    };
+    [color[0], color[1], color[2], 1.0]
+}
@@ -0,0 +1,279 @@
+use imgui::{Condition, MouseButton, StyleVar, Ui};
+
+use crate::backend::{Board, Color, Piece, PieceKind, Square};
+
+type Cell = (usize, usize);
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum EditorTool {
+    /// Drag pieces around, dropping them off the board removes them.
+    Move,
+    /// Click a square to put the piece there.
+    Place(Color, PieceKind),
+    Delete,
+}
+
+pub enum EditorAction {
+    /// The edited position is valid and a game should start from it.
+    StartGame,
+    Cancel,
+    /// A piece dragged from the palette was let go.
+    DropPiece(Piece),
+}
+
+/// Position setup: a palette of pieces, side to move, castling rights and
+/// the en passant square, shown in place of the settings panel.
+pub struct PositionEditor {
+    pub board: Board,
+    tool: EditorTool,
+    /// Piece picked from the palette and not yet dropped.
+    palette_piece: Option<Piece>,
+    /// Piece taken from the board with the move tool.
+    taken_cell: Option<Cell>,
+    status: Option<String>,
+}
+
+impl PositionEditor {
+    pub fn new(board: Board) -> PositionEditor {
+        PositionEditor {
+            board,
+            tool: EditorTool::Move,
+            palette_piece: None,
+            taken_cell: None,
+            status: None,
+        }
+    }
+
+    /// Piece following the cursor and the cell it was taken from, if any.
+    pub fn dragged_piece(&self) -> Option<(Piece, Option<Cell>)> {
+        match (self.palette_piece, self.taken_cell) {
+            (Some(piece), _) => Some((piece, None)),
+            (None, Some(cell)) => {
+                self.board.state[7 - cell.1][cell.0].map(|piece| (piece, Some(cell)))
+            }
+            (None, None) => None,
+        }
+    }
+
+    /// Left button went down over `cell` of the board.
+    pub fn press(&mut self, cell: Cell) {
+        match self.tool {
+            EditorTool::Move => {
+                if self.board.state[7 - cell.1][cell.0].is_some() {
+                    self.taken_cell = Some(cell);
+                }
+            }
+            EditorTool::Place(color, kind) => {
+                self.board.put_piece_at_cell(&cell, Piece::new(color, kind))
+            }
+            EditorTool::Delete => self.board.clear_cell(&cell),
+        }
+    }
+
+    /// Left button went up over `cell`, or off the board for `None`.
+    pub fn release(&mut self, cell: Option<Cell>) {
+        if let Some(taken_cell) = self.taken_cell.take() {
+            let piece = self.board.state[7 - taken_cell.1][taken_cell.0];
+            match (cell, piece) {
+                (Some(cell), Some(piece)) if cell != taken_cell => {
+                    self.board.clear_cell(&taken_cell);
+                    self.board.put_piece_at_cell(&cell, piece);
+                }
+                (Some(_), _) => (),
+                (None, _) => self.board.clear_cell(&taken_cell),
+            }
+        }
+    }
+
+    /// Puts a piece let go from the palette at `cell`.
+    pub fn drop_piece(&mut self, piece: Piece, cell: Cell) {
+        self.board.put_piece_at_cell(&cell, piece);
+    }
+
+    /// Draws the editor panel into the left side area.
+    pub fn draw(&mut self, ui: &Ui, side_panel_width: f32, margin: f32) -> Option<EditorAction> {
+        let display_size = ui.io().display_size;
+        let mut action = None;
+        ui.window("Position editor")
+            .position([margin, margin], Condition::Always)
+            .size(
+                [
+                    (side_panel_width - margin).max(0.0),
+                    (display_size[1] - 2.0 * margin).max(0.0),
+                ],
+                Condition::Always,
+            )
+            .movable(false)
+            .resizable(false)
+            .collapsible(false)
+            .build(|| {
+                self.draw_palette(ui);
+                if ui
+                    .selectable_config("Move")
+                    .selected(self.tool == EditorTool::Move)
+                    .build()
+                {
+                    self.tool = EditorTool::Move;
+                }
+                if ui
+                    .selectable_config("Delete")
+                    .selected(self.tool == EditorTool::Delete)
+                    .build()
+                {
+                    self.tool = EditorTool::Delete;
+                }
+                if ui.button("Clear") {
+                    self.board = Board::empty();
+                }
+                ui.same_line();
+                if ui.button("Reset") {
+                    self.board = Board::new(Color::Light);
+                }
+                ui.separator();
+
+                let mut turn = self.board.turn;
+                ui.radio_button("White to move", &mut turn, Color::Light);
+                ui.radio_button("Black to move", &mut turn, Color::Dark);
+                if turn != self.board.turn {
+                    self.board.turn = turn;
+                    self.board.set_en_passant_square(None);
+                }
+                ui.text("Castling");
+                let castling_rights = &mut self.board.castling_rights;
+                ui.checkbox("White O-O", &mut castling_rights.light_king_side);
+                ui.checkbox("White O-O-O", &mut castling_rights.light_queen_side);
+                ui.checkbox("Black O-O", &mut castling_rights.dark_king_side);
+                ui.checkbox("Black O-O-O", &mut castling_rights.dark_queen_side);
+                self.draw_en_passant(ui);
+                ui.separator();
+
+                if ui.button("Copy FEN") {
+                    ui.set_clipboard_text(self.board.to_fen());
+                }
+                ui.same_line();
+                if ui.button("Paste FEN") {
+                    let fen = ui.clipboard_text().unwrap_or_default();
+                    match Board::from_fen(&fen) {
+                        Ok(board) => {
+                            self.board = board;
+                            self.status = None;
+                        }
+                        Err(error) => self.status = Some(error.to_string()),
+                    }
+                }
+
+                let problems = self.board.validate();
+                ui.disabled(!problems.is_empty(), || {
+                    if ui.button("Start game") {
+                        action = Some(EditorAction::StartGame);
+                    }
+                });
+                ui.same_line();
+                if ui.button("Cancel") {
+                    action = Some(EditorAction::Cancel);
+                }
+                for problem in &problems {
+                    ui.text_wrapped(problem.to_string());
+                }
+                if let Some(status) = &self.status {
+                    ui.text_wrapped(status);
+                }
+            });
+
+        // Pieces dragged from the palette are let go wherever the button goes up
+        if self.palette_piece.is_some() && ui.is_mouse_released(MouseButton::Left) {
+            if let Some(piece) = self.palette_piece.take() {
+                action = Some(EditorAction::DropPiece(piece));
+            }
+        }
+        action
+    }
+
+    /// A row of selectable pieces per side. Clicking one makes it the tool,
+    /// dragging one out puts a single piece where it is dropped.
+    fn draw_palette(&mut self, ui: &Ui) {
+        let spacing = 2.0;
+        let _spacing = ui.push_style_var(StyleVar::ItemSpacing([spacing, spacing]));
+        let width = ((ui.content_region_avail()[0] - 5.0 * spacing) / 6.0).max(1.0);
+        for (color, side) in [(Color::Light, "white"), (Color::Dark, "black")] {
+            for (index, kind) in PALETTE_KINDS.iter().enumerate() {
+                if index > 0 {
+                    ui.same_line();
+                }
+                let label = format!("{}##{}", piece_letter(color, *kind), side);
+                let tool = EditorTool::Place(color, *kind);
+                if ui
+                    .selectable_config(&label)
+                    .selected(self.tool == tool)
+                    .size([width, 0.0])
+                    .build()
+                {
+                    self.tool = tool;
+                }
+                if ui.is_item_activated() {
+                    self.palette_piece = Some(Piece::new(color, *kind));
+                }
+            }
+        }
+    }
+
+    /// Files a pawn could have just leapt on, for the side to move to take.
+    fn draw_en_passant(&mut self, ui: &Ui) {
+        let en_passant_rank = match self.board.turn {
+            Color::Light => 5,
+            Color::Dark => 2,
+        };
+        let mut labels = vec!["-".to_owned()];
+        labels.extend((0..8).map(|file| {
+            Square {
+                file,
+                rank: en_passant_rank,
+            }
+            .to_string()
+        }));
+        let mut selected = self
+            .board
+            .en_passant_square()
+            .map_or(0, |square| square.file + 1);
+        ui.text("En passant");
+        ui.set_next_item_width(-1.0);
+        if ui.combo_simple_string("##en_passant", &mut selected, &labels) {
+            let square = selected.checked_sub(1).map(|file| Square {
+                file,
+                rank: en_passant_rank,
+            });
+            self.status = if self.board.set_en_passant_square(square) {
+                None
+            } else {
+                Some(format!(
+                    "no pawn can be taken en passant on {}",
+                    labels[selected]
+                ))
+            };
+        }
+    }
+}
+
+const PALETTE_KINDS: [PieceKind; 6] = [
+    PieceKind::King,
+    PieceKind::Queen,
+    PieceKind::Rook,
+    PieceKind::Bishop,
+    PieceKind::Knight,
+    PieceKind::Pawn,
+];
+
+fn piece_letter(color: Color, kind: PieceKind) -> char {
+    let letter = match kind {
+        PieceKind::Pawn => 'P',
+        PieceKind::Rook => 'R',
+        PieceKind::Bishop => 'B',
+        PieceKind::Knight => 'N',
+        PieceKind::Queen => 'Q',
+        PieceKind::King => 'K',
+    };
+    match color {
+        Color::Light => letter,
+        Color::Dark => letter.to_ascii_lowercase(),
+    }
+}
@@ -0,0 +1,191 @@
+use glium::Rect;
+
+/// Square region of the framebuffer the board is drawn into. Physical pixels,
+/// origin at the bottom-left corner, as glium expects for viewports.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoardLayout {
+    pub left: u32,
+    pub bottom: u32,
+    pub size: u32,
+}
+
+impl BoardLayout {
+    /// Fits the largest square board into the framebuffer, centred, leaving
+    /// `side_panel_width` free on both sides and `margin` around the board.
+    /// Panel width and margin are logical pixels, scaled by `scale_factor`.
+    pub fn fit(
+        framebuffer_dimensions: (u32, u32),
+        scale_factor: f64,
+        side_panel_width: f64,
+        margin: f64,
+    ) -> BoardLayout {
+        let (width, height) = framebuffer_dimensions;
+        let side_panel_width = (side_panel_width * scale_factor).round() as u32;
+        let margin = (margin * scale_factor).round() as u32;
+        let available_width = width.saturating_sub(2 * (side_panel_width + margin));
+        let available_height = height.saturating_sub(2 * margin);
+        let size = available_width.min(available_height).max(1);
+        BoardLayout {
+            left: width.saturating_sub(size) / 2,
+            bottom: height.saturating_sub(size) / 2,
+            size,
+        }
+    }
+
+    pub fn viewport(&self) -> Rect {
+        Rect {
+            left: self.left,
+            bottom: self.bottom,
+            width: self.size,
+            height: self.size,
+        }
+    }
+
+    /// Physical pixels covered by a length given in board NDC.
+    pub fn ndc_length_to_pixels(&self, length: f32) -> u32 {
+        (self.size as f32 * length / 2.0).round().max(1.0) as u32
+    }
+
+    /// Cursor position (logical pixels, origin at the top-left corner of the
+    /// window) to board NDC. Not clamped: values outside [-1, 1] are off the board.
+    pub fn cursor_to_ndc(
+        &self,
+        cursor_position: &(f64, f64),
+        scale_factor: f64,
+        framebuffer_height: u32,
+    ) -> (f64, f64) {
+        let physical_x = cursor_position.0 * scale_factor;
+        let physical_y = framebuffer_height as f64 - cursor_position.1 * scale_factor;
+        let size = self.size as f64;
+        let x = (physical_x - self.left as f64) / size;
+        let y = (physical_y - self.bottom as f64) / size;
+        (x * 2.0 - 1.0, y * 2.0 - 1.0)
+    }
+
+    /// Top-left corner and side of a cell in logical pixels from the top-left
+    /// corner of the window, where imgui draws.
+    pub fn logical_cell_rect(
+        &self,
+        cell_position: &(usize, usize),
+        scale_factor: f64,
+        framebuffer_height: u32,
+    ) -> ([f32; 2], f32) {
+        let scale_factor = scale_factor as f32;
+        let cell_size = self.size as f32 / 8.0 / scale_factor;
+        let board_top = framebuffer_height as f32 - (self.bottom + self.size) as f32;
+        (
+            [
+                self.left as f32 / scale_factor + cell_position.0 as f32 * cell_size,
+                board_top / scale_factor + (7 - cell_position.1) as f32 * cell_size,
+            ],
+            cell_size,
+        )
+    }
+}
+
+pub fn is_on_board(ndc_position: &(f64, f64)) -> bool {
+    (-1.0..=1.0).contains(&ndc_position.0) && (-1.0..=1.0).contains(&ndc_position.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::ndc_cursor_position_to_cell_position;
+
+    const SCALE_FACTORS: [f64; 4] = [1.0, 1.5, 2.0, 1.25];
+    /// Window sizes in logical pixels: square, wide and tall.
+    const WINDOW_SIZES: [(f64, f64); 3] = [(1000.0, 1000.0), (1280.0, 720.0), (900.0, 1100.0)];
+    const SIDE_PANEL_WIDTH: f64 = 200.0;
+    const MARGIN: f64 = 20.0;
+
+    fn layouts() -> impl Iterator<Item = (BoardLayout, (u32, u32), f64)> {
+        SCALE_FACTORS.into_iter().flat_map(|scale_factor| {
+            WINDOW_SIZES.into_iter().map(move |(width, height)| {
+                let framebuffer_dimensions = (
+                    (width * scale_factor).round() as u32,
+                    (height * scale_factor).round() as u32,
+                );
+                let layout = BoardLayout::fit(
+                    framebuffer_dimensions,
+                    scale_factor,
+                    SIDE_PANEL_WIDTH,
+                    MARGIN,
+                );
+                (layout, framebuffer_dimensions, scale_factor)
+            })
+        })
+    }
+
+    #[test]
+    fn board_fits_between_the_side_panels() {
+        for (layout, (width, height), scale_factor) in layouts() {
+            let panel = ((SIDE_PANEL_WIDTH + MARGIN) * scale_factor).round() as u32;
+            let margin = (MARGIN * scale_factor).round() as u32;
+            assert!(layout.left >= panel, "{:?} at {}", layout, scale_factor);
+            assert!(layout.left + layout.size <= width - panel);
+            assert!(layout.bottom >= margin);
+            assert!(layout.bottom + layout.size <= height - margin);
+            // Centred, give or take the pixel lost to rounding
+            assert!((width - layout.size - 2 * layout.left) <= 1);
+            assert!((height - layout.size - 2 * layout.bottom) <= 1);
+        }
+    }
+
+    #[test]
+    fn cursor_maps_to_the_cell_under_it() {
+        for (layout, (_, height), scale_factor) in layouts() {
+            for x in 0..8 {
+                for y in 0..8 {
+                    let ([left, top], size) =
+                        layout.logical_cell_rect(&(x, y), scale_factor, height);
+                    // Centre and points just inside the corners of the cell
+                    for (dx, dy) in [(0.5, 0.5), (0.05, 0.05), (0.95, 0.95), (0.05, 0.95)] {
+                        let cursor = ((left + size * dx) as f64, (top + size * dy) as f64);
+                        let ndc = layout.cursor_to_ndc(&cursor, scale_factor, height);
+                        assert!(is_on_board(&ndc), "{:?} at {}", cursor, scale_factor);
+                        assert_eq!(
+                            ndc_cursor_position_to_cell_position(&ndc),
+                            (x, y),
+                            "{:?} in {:?} at {}",
+                            cursor,
+                            layout,
+                            scale_factor
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cursor_beside_the_board_is_off_it() {
+        for (layout, (_, height), scale_factor) in layouts() {
+            let ([left, top], size) = layout.logical_cell_rect(&(0, 7), scale_factor, height);
+            let board_size = 8.0 * size as f64;
+            let (left, top) = (left as f64, top as f64);
+            for cursor in [
+                (left - 2.0, top + 1.0),
+                (left + board_size + 2.0, top + 1.0),
+                (left + 1.0, top - 2.0),
+                (left + 1.0, top + board_size + 2.0),
+            ] {
+                let ndc = layout.cursor_to_ndc(&cursor, scale_factor, height);
+                assert!(!is_on_board(&ndc), "{:?} at {}", cursor, scale_factor);
+            }
+        }
+    }
+
+    #[test]
+    fn bottom_left_cell_is_at_the_bottom_left_corner() {
+        for (layout, (_, height), scale_factor) in layouts() {
+            // Physical pixel just inside the bottom-left corner of the board
+            let cursor = (
+                (layout.left as f64 + 1.0) / scale_factor,
+                (height as f64 - layout.bottom as f64 - 1.0) / scale_factor,
+            );
+            let ndc = layout.cursor_to_ndc(&cursor, scale_factor, height);
+            assert!(ndc.0 > -1.0 && ndc.0 < -0.99 && ndc.1 > -1.0 && ndc.1 < -0.99);
+            assert_eq!(ndc_cursor_position_to_cell_position(&ndc), (0, 0));
+        }
+    }
+}
@@ -1,4 +1,14 @@
+pub mod animation;
+pub mod annotations;
+pub mod assets;
+pub mod coordinates;
+pub mod editor;
+pub mod layout;
+pub mod offscreen;
+pub mod panels;
+pub mod renderer;
 pub mod shaders;
+pub mod theme;
 pub mod ui_support;
 
 pub fn ndc_cursor_position_to_cell_position(ndc_cursor_position: &(f64, f64)) -> (usize, usize) {
@@ -0,0 +1,273 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use glium::backend::Facade;
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{MipmapsOption, RawImage2d, SrgbFormat, SrgbTexture2d};
+use glium::{DrawParameters, HeadlessRenderer, Surface};
+use image::RgbaImage;
+
+use crate::backend::Board;
+use crate::ui::assets::{AssetError, Assets};
+use crate::ui::renderer::{piece_instances, BoardRenderer, BoardStyle, PIECE_CELL_SIZE};
+use crate::ui::shaders::PieceAtlas;
+
+#[derive(Debug)]
+pub enum RenderError {
+    Asset(AssetError),
+    Context(String),
+    Framebuffer(String),
+    Save {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::Asset(error) => error.fmt(f),
+            RenderError::Context(message) => {
+                write!(f, "failed to create an OpenGL context: {}", message)
+            }
+            RenderError::Framebuffer(message) => {
+                write!(f, "failed to create an offscreen framebuffer: {}", message)
+            }
+            RenderError::Save { path, source } => {
+                write!(f, "failed to save `{}`: {}", path.display(), source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RenderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RenderError::Asset(error) => Some(error),
+            RenderError::Save { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<AssetError> for RenderError {
+    fn from(error: AssetError) -> Self {
+        RenderError::Asset(error)
+    }
+}
+
+/// Renders `board` into a `size` x `size` image through an offscreen
+/// framebuffer, with the same shaders as the window.
+pub fn render_board<F: Facade>(
+    facade: &F,
+    renderer: &mut BoardRenderer,
+    atlas: &mut PieceAtlas,
+    assets: &Assets,
+    board: &Board,
+    style: &BoardStyle,
+    size: u32,
+) -> Result<RgbaImage, RenderError> {
+    let size = size.max(1);
+    let piece_pixel_size = (size as f32 * PIECE_CELL_SIZE / 2.0).round() as u32;
+    atlas.prepare(facade, assets, &[piece_pixel_size])?;
+
+    let texture = SrgbTexture2d::empty_with_format(
+        facade,
+        SrgbFormat::U8U8U8U8,
+        MipmapsOption::NoMipmap,
+        size,
+        size,
+    )
+    .map_err(|error| RenderError::Framebuffer(error.to_string()))?;
+    let mut framebuffer = SimpleFrameBuffer::new(facade, &texture)
+        .map_err(|error| RenderError::Framebuffer(error.to_string()))?;
+    framebuffer.clear_color_srgb(0.0, 0.0, 0.0, 0.0);
+
+    let pieces = piece_instances(board, atlas, piece_pixel_size, None);
+    renderer.draw(
+        facade,
+        &mut framebuffer,
+        style,
+        atlas,
+        &pieces,
+        &DrawParameters::default(),
+    );
+
+    let raw: RawImage2d<u8> = texture.read();
+    let image = RgbaImage::from_raw(raw.width, raw.height, raw.data.into_owned())
+        .expect("Texture read back as RGBA8");
+    // OpenGL rows go bottom-up
+    Ok(image::imageops::flip_vertical(&image))
+}
+
+/// An OpenGL context without a window. Uses OSMesa on Linux and the BSDs, so
+/// it works without a GPU or display server as long as libOSMesa is installed.
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn create_headless_renderer(size: u32) -> Result<HeadlessRenderer, RenderError> {
+    use glium::glutin;
+    use glium::glutin::platform::unix::HeadlessContextExt;
+
+    let context = glutin::ContextBuilder::new()
+        .build_osmesa(glutin::dpi::PhysicalSize::new(size, size))
+        .map_err(|error| RenderError::Context(error.to_string()))?;
+    HeadlessRenderer::new(context).map_err(|error| RenderError::Context(error.to_string()))
+}
+
+/// An OpenGL context without a window, backed by the platform's default driver.
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+pub fn create_headless_renderer(size: u32) -> Result<HeadlessRenderer, RenderError> {
+    use glium::glutin;
+
+    let event_loop = glutin::event_loop::EventLoop::new();
+    let context = glutin::ContextBuilder::new()
+        .build_headless(&event_loop, glutin::dpi::PhysicalSize::new(size, size))
+        .map_err(|error| RenderError::Context(error.to_string()))?;
+    HeadlessRenderer::new(context).map_err(|error| RenderError::Context(error.to_string()))
+}
+
+pub fn save_image(image: &RgbaImage, path: &Path) -> Result<(), RenderError> {
+    image.save(path).map_err(|source| RenderError::Save {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use glium::backend::Facade;
+    use image::RgbaImage;
+
+    use super::{create_headless_renderer, render_board};
+    use crate::backend::{Board, Color};
+    use crate::ui::assets::Assets;
+    use crate::ui::renderer::{BoardRenderer, BoardStyle, HighlightLayer, SquareHighlights};
+    use crate::ui::shaders::PieceAtlas;
+    use crate::ui::theme::Theme;
+
+    const SIZE: u32 = 256;
+    /// Set to write the rendered images as the new references.
+    const BLESS_ENV_VAR: &str = "CHESS_BLESS_GOLDEN";
+    /// Per channel difference still counted as the same pixel, and the share
+    /// of pixels allowed to differ beyond it: drivers rasterise and blend
+    /// edges slightly differently.
+    const CHANNEL_TOLERANCE: u8 = 24;
+    const MISMATCH_TOLERANCE: f64 = 0.005;
+
+    fn golden_path(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(format!("{}.png", name))
+    }
+
+    fn check_golden(name: &str, image: &RgbaImage) {
+        let path = golden_path(name);
+        if std::env::var_os(BLESS_ENV_VAR).is_some() {
+            image.save(&path).unwrap();
+            return;
+        }
+        let golden = image::open(&path)
+            .unwrap_or_else(|error| panic!("no reference `{}`: {}", path.display(), error))
+            .to_rgba8();
+        assert_eq!(image.dimensions(), golden.dimensions(), "{}", name);
+        let mismatched = image
+            .pixels()
+            .zip(golden.pixels())
+            .filter(|(pixel, golden_pixel)| {
+                pixel
+                    .0
+                    .iter()
+                    .zip(golden_pixel.0)
+                    .any(|(channel, golden_channel)| {
+                        channel.abs_diff(golden_channel) > CHANNEL_TOLERANCE
+                    })
+            })
+            .count();
+        let share = mismatched as f64 / (SIZE * SIZE) as f64;
+        if share > MISMATCH_TOLERANCE {
+            let actual_path = std::env::temp_dir().join(format!("{}.actual.png", name));
+            let _ = image.save(&actual_path);
+            panic!(
+                "{}: {:.2}% of the pixels differ from `{}`, rendered image in `{}`",
+                name,
+                share * 100.0,
+                path.display(),
+                actual_path.display()
+            );
+        }
+    }
+
+    fn render<F: Facade>(
+        facade: &F,
+        theme: &str,
+        fen: &str,
+        highlights: SquareHighlights,
+    ) -> RgbaImage {
+        let assets = Assets::default();
+        let theme = Theme::load(&assets, theme).unwrap();
+        let mut atlas = PieceAtlas::load(facade, &assets, &theme).unwrap();
+        let mut renderer = BoardRenderer::new(facade, &assets).unwrap();
+        let style = BoardStyle {
+            theme: &theme,
+            time: 0.0,
+            highlights,
+        };
+        let board = Board::from_fen(fen).unwrap();
+        render_board(
+            facade,
+            &mut renderer,
+            &mut atlas,
+            &assets,
+            &board,
+            &style,
+            SIZE,
+        )
+        .unwrap()
+    }
+
+    fn check_positions<F: Facade>(facade: &F) {
+        let start = Board::new(Color::Light).to_fen();
+        check_golden(
+            "start_classic",
+            &render(
+                facade,
+                "themes/classic.theme",
+                &start,
+                SquareHighlights::default(),
+            ),
+        );
+
+        let mut highlights = SquareHighlights::default();
+        highlights.add(HighlightLayer::LastMove, &(3, 7));
+        highlights.add(HighlightLayer::LastMove, &(7, 3));
+        highlights.add(HighlightLayer::Check, &(4, 0));
+        highlights.add(HighlightLayer::Selection, &(5, 1));
+        highlights.add(HighlightLayer::LegalTarget, &(5, 2));
+        highlights.add(HighlightLayer::Premove, &(2, 5));
+        check_golden(
+            "check_flat",
+            &render(
+                facade,
+                "themes/flat.theme",
+                "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+                highlights,
+            ),
+        );
+    }
+
+    #[test]
+    fn board_matches_golden_images() {
+        let facade = match create_headless_renderer(SIZE) {
+            Ok(facade) => facade,
+            Err(error) => {
+                eprintln!(
+                    "skipping golden-image tests, no headless OpenGL (is libOSMesa installed?): {}",
+                    error
+                );
+                return;
+            }
+        };
+        check_positions(&facade);
+    }
+}
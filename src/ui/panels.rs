@@ -0,0 +1,326 @@
+use std::time::Duration;
+
+use imgui::{Condition, Ui};
+
+use crate::backend::TimeControl;
+use crate::player::{EnginePlayer, PlayerSpec};
+
+pub enum SettingsAction {
+    PickTheme(usize),
+    SaveScreenshot,
+    SavePgn,
+    Undo,
+    Redo,
+    EditPosition,
+    /// Start over from the standard position with the picked time control.
+    NewGame,
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
+    ClaimDraw,
+}
+
+/// One player's clock as shown in the panel.
+pub struct ClockReadout {
+    pub player: &'static str,
+    pub remaining: Duration,
+    pub is_running: bool,
+}
+
+/// An entry of the player lists. Engines over UCI and network games take
+/// the command or address typed in below the lists.
+#[derive(PartialEq)]
+enum PlayerChoice {
+    Spec(PlayerSpec),
+    Uci,
+    Host,
+    Join,
+}
+
+/// Left-hand panel with display settings.
+pub struct SettingsPanel {
+    theme_labels: Vec<String>,
+    selected_theme: usize,
+    /// Rank and file labels on the edge squares.
+    pub show_coordinates: bool,
+    /// Seconds a move animates for, 0 to disable.
+    pub animation_duration: f32,
+    time_controls: Vec<(&'static str, Option<TimeControl>)>,
+    selected_time_control: usize,
+    /// Who can play a side.
+    player_choices: Vec<(String, PlayerChoice)>,
+    /// Choice for White and Black.
+    selected_players: [usize; 2],
+    uci_command: String,
+    /// Port to host a network game on.
+    host_port: String,
+    /// Address of the host to join, like `192.168.1.5:7878`.
+    join_address: String,
+    /// Clocks of the game in progress, if it has any.
+    pub clocks: Option<[ClockReadout; 2]>,
+    /// Disables the actions of a game in progress.
+    pub is_game_over: bool,
+    /// Player with a standing draw offer, to be accepted or declined.
+    pub draw_offered_by: Option<&'static str>,
+    /// Whether a threefold repetition or the fifty-move rule applies.
+    pub can_claim_draw: bool,
+    /// Outcome of the last action, e.g. an error or where a screenshot went.
+    pub status: Option<String>,
+}
+
+impl SettingsPanel {
+    pub fn new(theme_labels: Vec<String>, selected_theme: usize) -> SettingsPanel {
+        SettingsPanel {
+            theme_labels,
+            selected_theme,
+            show_coordinates: true,
+            animation_duration: 0.25,
+            time_controls: time_control_presets(),
+            selected_time_control: 0,
+            player_choices: vec![
+                ("Human".to_owned(), PlayerChoice::Spec(PlayerSpec::Human)),
+                (
+                    "Engine, easy".to_owned(),
+                    PlayerChoice::Spec(PlayerSpec::Engine(1)),
+                ),
+                (
+                    "Engine".to_owned(),
+                    PlayerChoice::Spec(PlayerSpec::Engine(EnginePlayer::DEFAULT_DEPTH)),
+                ),
+                ("UCI engine".to_owned(), PlayerChoice::Uci),
+                ("Network, host".to_owned(), PlayerChoice::Host),
+                ("Network, join".to_owned(), PlayerChoice::Join),
+            ],
+            selected_players: [0; 2],
+            uci_command: "stockfish".to_owned(),
+            host_port: "7878".to_owned(),
+            join_address: "127.0.0.1:7878".to_owned(),
+            clocks: None,
+            is_game_over: false,
+            draw_offered_by: None,
+            can_claim_draw: false,
+            status: None,
+        }
+    }
+
+    /// Draws the panel into the left side area.
+    pub fn draw(&mut self, ui: &Ui, side_panel_width: f32, margin: f32) -> Option<SettingsAction> {
+        let display_size = ui.io().display_size;
+        let mut action = None;
+        ui.window("Settings")
+            .position([margin, margin], Condition::Always)
+            .size(
+                [
+                    (side_panel_width - margin).max(0.0),
+                    (display_size[1] - 2.0 * margin).max(0.0),
+                ],
+                Condition::Always,
+            )
+            .movable(false)
+            .resizable(false)
+            .collapsible(false)
+            .build(|| {
+                if let Some(clocks) = &self.clocks {
+                    for clock in clocks {
+                        ui.text(format!(
+                            "{} {:>5} {}",
+                            if clock.is_running { ">" } else { " " },
+                            format_clock(clock.remaining),
+                            clock.player
+                        ));
+                    }
+                    ui.separator();
+                }
+                ui.text("Theme");
+                ui.set_next_item_width(-1.0);
+                if ui.combo_simple_string("##theme", &mut self.selected_theme, &self.theme_labels) {
+                    action = Some(SettingsAction::PickTheme(self.selected_theme));
+                }
+                ui.checkbox("Coordinates", &mut self.show_coordinates);
+                ui.text("Animation, s");
+                ui.set_next_item_width(-1.0);
+                ui.slider("##animation", 0.0, 1.0, &mut self.animation_duration);
+                ui.separator();
+                for (side, label) in ["White", "Black"].iter().enumerate() {
+                    ui.text(label);
+                    ui.set_next_item_width(-1.0);
+                    let player_labels: Vec<&str> = self
+                        .player_choices
+                        .iter()
+                        .map(|(label, _)| label.as_str())
+                        .collect();
+                    ui.combo_simple_string(
+                        format!("##player_{}", side),
+                        &mut self.selected_players[side],
+                        &player_labels,
+                    );
+                }
+                if self.is_chosen(PlayerChoice::Uci) {
+                    ui.text("UCI command");
+                    ui.set_next_item_width(-1.0);
+                    ui.input_text("##uci_command", &mut self.uci_command)
+                        .build();
+                }
+                if self.is_chosen(PlayerChoice::Host) {
+                    ui.text("Host on port");
+                    ui.set_next_item_width(-1.0);
+                    ui.input_text("##host_port", &mut self.host_port).build();
+                }
+                if self.is_chosen(PlayerChoice::Join) {
+                    ui.text("Join address");
+                    ui.set_next_item_width(-1.0);
+                    ui.input_text("##join_address", &mut self.join_address)
+                        .build();
+                }
+                ui.text("Time control");
+                ui.set_next_item_width(-1.0);
+                let time_control_labels: Vec<&str> =
+                    self.time_controls.iter().map(|(label, _)| *label).collect();
+                ui.combo_simple_string(
+                    "##time_control",
+                    &mut self.selected_time_control,
+                    &time_control_labels,
+                );
+                if ui.button("New game") {
+                    action = Some(SettingsAction::NewGame);
+                }
+                ui.disabled(self.is_game_over, || {
+                    if ui.button("Resign") {
+                        action = Some(SettingsAction::Resign);
+                    }
+                    ui.same_line();
+                    if ui.button("Offer draw") {
+                        action = Some(SettingsAction::OfferDraw);
+                    }
+                    if let Some(player) = self.draw_offered_by {
+                        ui.text_wrapped(format!("{} offers a draw", player));
+                        if ui.button("Accept") {
+                            action = Some(SettingsAction::AcceptDraw);
+                        }
+                        ui.same_line();
+                        if ui.button("Decline") {
+                            action = Some(SettingsAction::DeclineDraw);
+                        }
+                    }
+                    if self.can_claim_draw && ui.button("Claim draw") {
+                        action = Some(SettingsAction::ClaimDraw);
+                    }
+                });
+                if ui.button("Undo") {
+                    action = Some(SettingsAction::Undo);
+                }
+                ui.same_line();
+                if ui.button("Redo") {
+                    action = Some(SettingsAction::Redo);
+                }
+                if ui.button("Edit position") {
+                    action = Some(SettingsAction::EditPosition);
+                }
+                if ui.button("Save screenshot") {
+                    action = Some(SettingsAction::SaveScreenshot);
+                }
+                if ui.button("Save PGN") {
+                    action = Some(SettingsAction::SavePgn);
+                }
+                if let Some(status) = &self.status {
+                    ui.text_wrapped(status);
+                }
+            });
+        action
+    }
+
+    /// Players picked for White and Black, for the next new game.
+    pub fn player_specs(&self) -> [PlayerSpec; 2] {
+        self.selected_players
+            .map(|choice| match &self.player_choices[choice].1 {
+                PlayerChoice::Spec(spec) => spec.clone(),
+                PlayerChoice::Uci => PlayerSpec::Uci(self.uci_command.clone()),
+                PlayerChoice::Host => PlayerSpec::Listen(self.host_port.clone()),
+                PlayerChoice::Join => PlayerSpec::Connect(self.join_address.clone()),
+            })
+    }
+
+    fn is_chosen(&self, kind: PlayerChoice) -> bool {
+        self.selected_players
+            .iter()
+            .any(|choice| self.player_choices[*choice].1 == kind)
+    }
+
+    /// Shows `specs` as picked, adding choices for ones not listed, like a
+    /// script from the command line.
+    pub fn set_player_specs(&mut self, specs: &[PlayerSpec; 2]) {
+        for (side, spec) in specs.iter().enumerate() {
+            let wanted = match spec {
+                PlayerSpec::Uci(command) => {
+                    self.uci_command = command.clone();
+                    PlayerChoice::Uci
+                }
+                PlayerSpec::Listen(port) => {
+                    self.host_port = port.clone();
+                    PlayerChoice::Host
+                }
+                PlayerSpec::Connect(address) => {
+                    self.join_address = address.clone();
+                    PlayerChoice::Join
+                }
+                spec => PlayerChoice::Spec(spec.clone()),
+            };
+            let choice = match self
+                .player_choices
+                .iter()
+                .position(|(_, choice)| *choice == wanted)
+            {
+                Some(choice) => choice,
+                None => {
+                    self.player_choices
+                        .push((spec.to_string(), PlayerChoice::Spec(spec.clone())));
+                    self.player_choices.len() - 1
+                }
+            };
+            self.selected_players[side] = choice;
+        }
+    }
+
+    /// Time control picked for new games, `None` for untimed ones.
+    pub fn time_control(&self) -> Option<TimeControl> {
+        self.time_controls[self.selected_time_control].1.clone()
+    }
+}
+
+fn time_control_presets() -> Vec<(&'static str, Option<TimeControl>)> {
+    let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+    let seconds = Duration::from_secs;
+    vec![
+        ("Untimed", None),
+        ("1+0 bullet", Some(TimeControl::sudden_death(minutes(1)))),
+        (
+            "3+2 blitz",
+            Some(TimeControl::fischer(minutes(3), seconds(2))),
+        ),
+        ("5+0 blitz", Some(TimeControl::sudden_death(minutes(5)))),
+        (
+            "5 delay 3",
+            Some(TimeControl::simple_delay(minutes(5), seconds(3))),
+        ),
+        (
+            "5 Bronstein 3",
+            Some(TimeControl::bronstein(minutes(5), seconds(3))),
+        ),
+        (
+            "10+5 rapid",
+            Some(TimeControl::fischer(minutes(10), seconds(5))),
+        ),
+        ("40/90+30, 30+30", TimeControl::parse("40/5400+30:1800+30")),
+    ]
+}
+
+/// `m:ss`, with tenths of a second in the last 20 seconds.
+fn format_clock(remaining: Duration) -> String {
+    if remaining < Duration::from_secs(20) {
+        format!("{:.1}", remaining.as_secs_f32())
+    } else {
+        let seconds = remaining.as_secs();
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
@@ -0,0 +1,236 @@
+use glium::backend::Facade;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::texture::{MipmapsOption, Texture2d, UncompressedFloatFormat};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter};
+use glium::{uniform, Blend, DrawParameters, Rect, Surface, VertexBuffer};
+
+use crate::backend::Board;
+use crate::ui::assets::{AssetError, Assets};
+use crate::ui::shaders::primitives::{PieceInstance, Vertex};
+use crate::ui::shaders::{get_board_shader, get_piece_shader, PieceAtlas, Shader};
+use crate::ui::theme::Theme;
+
+/// Side of a piece quad in board NDC (the board spans 2.0).
+pub const PIECE_CELL_SIZE: f32 = 0.25;
+
+/// Square highlights drawn by `board.frag`, each with its own theme colour
+/// and effect. Values are the bits in the per-cell layer texture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HighlightLayer {
+    /// Pulsing outline on squares the taken piece may go to.
+    LegalTarget = 1,
+    LastMove = 2,
+    /// Glow around a king in check.
+    Check = 4,
+    Selection = 8,
+    Premove = 16,
+}
+
+/// Highlight layers of every cell, addressed by cell position like the board.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SquareHighlights {
+    cells: [[u8; 8]; 8],
+}
+
+impl SquareHighlights {
+    pub fn add(&mut self, layer: HighlightLayer, cell_position: &(usize, usize)) {
+        self.cells[cell_position.0][cell_position.1] |= layer as u8;
+    }
+
+    /// Texture rows, bottom row first.
+    fn texture_rows(&self) -> Vec<Vec<u8>> {
+        (0..8)
+            .map(|pos_y| (0..8).map(|pos_x| self.cells[pos_x][pos_y]).collect())
+            .collect()
+    }
+}
+
+/// Everything `board.frag` needs besides geometry.
+pub struct BoardStyle<'a> {
+    pub theme: &'a Theme,
+    /// Seconds, drives the highlight pulse.
+    pub time: f32,
+    pub highlights: SquareHighlights,
+}
+
+/// Instances for every piece standing on the board, except at `skip_cell`.
+pub fn piece_instances(
+    board: &Board,
+    atlas: &PieceAtlas,
+    pixel_size: u32,
+    skip_cell: Option<(usize, usize)>,
+) -> Vec<PieceInstance> {
+    let mut instances = Vec::with_capacity(33);
+    for pos_x in 0..8 {
+        for pos_y in 0..8 {
+            if skip_cell == Some((pos_x, pos_y)) {
+                continue;
+            }
+            if let Some(piece) = &board.state[7 - pos_y][pos_x] {
+                if let Some(region) = atlas.region(piece.kind, piece.color, pixel_size) {
+                    instances.push(PieceInstance::new(
+                        cell_lower_left_position(pos_x as f32, pos_y as f32),
+                        PIECE_CELL_SIZE,
+                        region,
+                    ));
+                }
+            }
+        }
+    }
+    instances
+}
+
+/// Lower-left corner of a cell in board NDC. Fractional cell positions are
+/// in between cells, for pieces in motion.
+pub fn cell_lower_left_position(pos_x: f32, pos_y: f32) -> [f32; 2] {
+    [
+        -1.0 + pos_x * PIECE_CELL_SIZE,
+        -1.0 + pos_y * PIECE_CELL_SIZE,
+    ]
+}
+
+/// Draws the board and its pieces. Geometry is uploaded once, piece
+/// instances are rewritten into a persistent buffer every frame and the
+/// highlight layers whenever they change.
+pub struct BoardRenderer {
+    board_shader: Shader,
+    piece_shader: Shader,
+    board_quad: VertexBuffer<Vertex>,
+    piece_quad: VertexBuffer<Vertex>,
+    piece_instances: VertexBuffer<PieceInstance>,
+    /// `SquareHighlights` as a normalised 8x8 texture, read back to bits by
+    /// `board.frag`. Glium cannot write into integer textures.
+    highlight_layers: Texture2d,
+    /// What `highlight_layers` holds.
+    highlights: SquareHighlights,
+}
+
+impl BoardRenderer {
+    /// 32 pieces plus the one being dragged.
+    const INITIAL_INSTANCE_CAPACITY: usize = 33;
+
+    pub fn new<F: Facade>(facade: &F, assets: &Assets) -> Result<BoardRenderer, AssetError> {
+        let board_quad = VertexBuffer::immutable(
+            facade,
+            &[
+                Vertex::from_position([-1.0, -1.0]),
+                Vertex::from_position([1.0, -1.0]),
+                Vertex::from_position([-1.0, 1.0]),
+                Vertex::from_position([1.0, 1.0]),
+            ],
+        )
+        .expect("Failed to create board vertex buffer");
+        let piece_quad = VertexBuffer::immutable(
+            facade,
+            &[
+                Vertex::from_position_and_texture_coordinates([0.0, 0.0], [0.0, 0.0]),
+                Vertex::from_position_and_texture_coordinates([0.0, 1.0], [0.0, 1.0]),
+                Vertex::from_position_and_texture_coordinates([1.0, 0.0], [1.0, 0.0]),
+                Vertex::from_position_and_texture_coordinates([1.0, 1.0], [1.0, 1.0]),
+            ],
+        )
+        .expect("Failed to create piece vertex buffer");
+        let piece_instances = VertexBuffer::empty_dynamic(facade, Self::INITIAL_INSTANCE_CAPACITY)
+            .expect("Failed to create piece instance buffer");
+        let highlights = SquareHighlights::default();
+        let highlight_layers = Texture2d::with_format(
+            facade,
+            highlights.texture_rows(),
+            UncompressedFloatFormat::U8,
+            MipmapsOption::NoMipmap,
+        )
+        .expect("Failed to create highlight layer texture");
+
+        Ok(BoardRenderer {
+            board_shader: get_board_shader(facade, assets)?,
+            piece_shader: get_piece_shader(facade, assets)?,
+            board_quad,
+            piece_quad,
+            piece_instances,
+            highlight_layers,
+            highlights,
+        })
+    }
+
+    /// Draws the board, then all `pieces` in order, in one call.
+    pub fn draw<F: Facade, S: Surface>(
+        &mut self,
+        facade: &F,
+        target: &mut S,
+        style: &BoardStyle,
+        atlas: &PieceAtlas,
+        pieces: &[PieceInstance],
+        draw_parameters: &DrawParameters,
+    ) {
+        let indices = NoIndices(PrimitiveType::TriangleStrip);
+        if style.highlights != self.highlights {
+            self.highlight_layers.write(
+                Rect {
+                    left: 0,
+                    bottom: 0,
+                    width: 8,
+                    height: 8,
+                },
+                style.highlights.texture_rows(),
+            );
+            self.highlights = style.highlights;
+        }
+        // Layers are bits, never to be blended between cells
+        let highlight_layers = self
+            .highlight_layers
+            .sampled()
+            .magnify_filter(MagnifySamplerFilter::Nearest)
+            .minify_filter(MinifySamplerFilter::Nearest);
+        self.board_shader.draw(
+            target,
+            &self.board_quad,
+            indices,
+            &uniform! {
+                time: style.time,
+                highlight_layers: highlight_layers,
+                light_square_color: style.theme.light_square,
+                dark_square_color: style.theme.dark_square,
+                highlight_color: style.theme.highlight,
+                last_move_color: style.theme.last_move,
+                check_color: style.theme.check,
+                selection_color: style.theme.selection,
+                premove_color: style.theme.premove,
+                border_color: style.theme.border,
+            },
+            draw_parameters,
+        );
+
+        if pieces.is_empty() {
+            return;
+        }
+        if pieces.len() > self.piece_instances.len() {
+            self.piece_instances = VertexBuffer::empty_dynamic(facade, pieces.len())
+                .expect("Failed to grow piece instance buffer");
+        }
+        let instances = self
+            .piece_instances
+            .slice_mut(0..pieces.len())
+            .expect("Slice is within the buffer");
+        instances.write(pieces);
+
+        let instances = self
+            .piece_instances
+            .slice(0..pieces.len())
+            .expect("Slice is within the buffer");
+        let instances = instances
+            .per_instance()
+            .expect("Instancing is not supported");
+        // Pieces fade in and out while animated
+        let piece_draw_parameters = DrawParameters {
+            blend: Blend::alpha_blending(),
+            ..draw_parameters.clone()
+        };
+        self.piece_shader.draw(
+            target,
+            (&self.piece_quad, instances),
+            indices,
+            &uniform! { tex: atlas.texture() },
+            &piece_draw_parameters,
+        );
+    }
+}
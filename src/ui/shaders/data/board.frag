@@ -4,69 +4,81 @@ in vec2 uv_position;
 in vec2 world_position;
 
 uniform float time;
-uniform ivec2 cells_to_highlight;
+// Bit flags per cell as normalised bytes, texel (x, y) is cell (x, y) from the
+// lower-left corner.
+// See `HighlightLayer` for the bits.
+uniform sampler2D highlight_layers;
+uniform vec3 light_square_color;
+uniform vec3 dark_square_color;
+uniform vec3 highlight_color;
+uniform vec3 last_move_color;
+uniform vec3 check_color;
+uniform vec3 selection_color;
+uniform vec3 premove_color;
+uniform vec3 border_color;
 
 out vec4 color;
 
-float getNthBit(int v, int bit) {
-    int shifted = v >> bit;
-    return float(shifted & 1);
-    // if (mod(shifted, 2) <= 0) {
-    //     return 0.0;
-    // } else {
-    //     return 1.0;
-    // }
+const uint LEGAL_TARGET = 1u;
+const uint LAST_MOVE = 2u;
+const uint CHECK = 4u;
+const uint SELECTION = 8u;
+const uint PREMOVE = 16u;
+
+bool hasLayer(uint layers, uint layer) {
+    return (layers & layer) != 0u;
 }
 
 void main() {
 
     vec3 color_;
     float border_width = 0.003;
-    float to_highlight = 1.0;
     if (uv_position.x < border_width || uv_position.x > 1.0 - border_width) {
-        color_ = vec3(0.10, 0.05, 0.05); 
+        color_ = border_color;
     } else if (uv_position.y < border_width || uv_position.y > 1.0 - border_width) {
-        color_ = vec3(0.10, 0.05, 0.05);
+        color_ = border_color;
     } else {
-        
-        vec2 cell = floor(uv_position * 8.0);
-        int cell_x = int(cell.x);
-        int cell_y = int(cell.y);
 
-        bool cell_to_highlight = false;
-        int to_check;
-        if (cell_y > 3) {
-            cell_y -= 4;
-            to_check = cells_to_highlight.y;
-        } else {
-            to_check = cells_to_highlight.x;
-        }
-        int bit = 31 - cell_x * 4 - cell_y;
-        to_highlight = getNthBit(to_check, bit);
-        if (to_highlight > 0.0) {
-            cell_to_highlight = true;
-        }
+        ivec2 cell = ivec2(floor(uv_position * 8.0));
+        uint layers = uint(texelFetch(highlight_layers, cell, 0).r * 255.0 + 0.5);
 
         vec3 cell_color;
-        
+
         vec2 grid = fract(uv_position * 4.0) - 0.5;
         float sign_ = sign(grid.x * grid.y);
         if (sign_ > 0.0) {
-            cell_color = vec3(0.14, 0.086, 0.034);
+            cell_color = dark_square_color;
         } else {
-            cell_color = vec3(0.76, 0.69, 0.55);
+            cell_color = light_square_color;
         };
 
-        vec3 highlight_color = cell_color;
-        if (cell_to_highlight == true) {
+        // Flat tints first, they replace part of the square colour
+        if (hasLayer(layers, LAST_MOVE)) {
+            cell_color = mix(cell_color, last_move_color, 0.5);
+        }
+        if (hasLayer(layers, PREMOVE)) {
+            cell_color = mix(cell_color, premove_color, 0.6);
+        }
+        if (hasLayer(layers, SELECTION)) {
+            cell_color = mix(cell_color, selection_color, 0.6);
+        }
+        // Check glows from the centre of the king's square
+        if (hasLayer(layers, CHECK)) {
+            float distance_to_centre = length(fract(uv_position * 8.0) - 0.5);
+            float glow = 1.0 - smoothstep(0.0, 0.7, distance_to_centre);
+            cell_color = mix(cell_color, check_color, glow);
+        }
+
+        vec3 highlighted_cell_color = cell_color;
+        if (hasLayer(layers, LEGAL_TARGET)) {
             vec2 distances_to_cell_border = (0.25 - abs(abs(grid) - 0.25)) * 4.0;
             float distance_to_border = min(distances_to_cell_border.x, distances_to_cell_border.y);
             if (distance_to_border < 0.3 * abs(sin(time * 2.0))) {
-                highlight_color = vec3(0.0, 1.0 - pow(distance_to_border + 0.5, 4.0), 0.0); 
-            }      
+                highlighted_cell_color = highlight_color * (1.0 - pow(distance_to_border + 0.5, 4.0));
+            }
         }
 
-        color_ = (cell_color + highlight_color) / 2.0;
+        color_ = (cell_color + highlighted_cell_color) / 2.0;
 
 
     };
@@ -1,6 +1,7 @@
 #version 140
 
 in vec2 v_texture_coords;
+in float v_alpha;
 
 uniform sampler2D tex;
 
@@ -8,6 +9,7 @@ out vec4 color;
 
 void main() {
     vec4 texture_color = texture(tex, v_texture_coords);
+    texture_color.a *= v_alpha;
     if (texture_color.a < 0.01) {
         discard;
     }
@@ -1,260 +1,233 @@
 use std::collections::HashMap;
-use std::io::Cursor;
 
+use glium::backend::Facade;
+use glium::index::IndicesSource;
 use glium::texture::{RawImage2d, SrgbTexture2d};
-use glium::{index::*, uniform, VertexBuffer};
-use glium::{Display, Frame, Surface};
+use glium::uniforms::Uniforms;
+use glium::vertex::MultiVerticesSource;
+use glium::{DrawParameters, Surface};
+use image::RgbaImage;
 
 use crate::backend::{Color, PieceKind};
+use crate::ui::assets::{AssetError, Assets};
+use crate::ui::theme::Theme;
 
 pub mod primitives;
-pub mod utils;
 
 pub struct Shader {
     program: glium::Program,
 }
 
 impl Shader {
-    // TODO: move vertex_buffer and shape, index outside:
-    // shader is a program, shape and indices are inputs
-    pub fn compile(
-        vertex_shader_src_code: String,
-        fragment_shader_src_code: String,
-        display: &Display,
-    ) -> Shader {
+    pub fn compile<F: Facade>(
+        vertex_shader_src_code: &str,
+        fragment_shader_src_code: &str,
+        facade: &F,
+    ) -> Result<Shader, glium::ProgramCreationError> {
         let program = glium::Program::from_source(
-            display,
-            &vertex_shader_src_code,
-            &fragment_shader_src_code,
+            facade,
+            vertex_shader_src_code,
+            fragment_shader_src_code,
             None,
+        )?;
+        Ok(Self { program })
+    }
+
+    pub fn compile_assets<F: Facade>(
+        vertex_shader_name: &str,
+        fragment_shader_name: &str,
+        facade: &F,
+        assets: &Assets,
+    ) -> Result<Shader, AssetError> {
+        let vertex_shader_src_code = assets.read_text(vertex_shader_name)?;
+        let fragment_shader_src_code = assets.read_text(fragment_shader_name)?;
+        Shader::compile(&vertex_shader_src_code, &fragment_shader_src_code, facade).map_err(
+            |error| AssetError::Shader {
+                name: format!("{} + {}", vertex_shader_name, fragment_shader_name),
+                message: error.to_string(),
+            },
         )
-        .unwrap();
-        Self { program }
     }
 
-    pub fn draw(
+    /// Draws with caller-built uniforms, see `uniform!`. Each shader picks
+    /// the ones it declares.
+    pub fn draw<'a, S, V, I, U>(
         &self,
-        target: &mut Frame,
-        vertex_buffer: VertexBuffer<primitives::Vertex>,
-        indices: NoIndices,
-        ndc_cursor_position: &(f64, f64),
-        texture: &glium::texture::SrgbTexture2d,
-        time: f32,
-        cells_to_highlight: (i32, i32),
-    ) -> () {
-        let uniforms = uniform! {
-            ndc_cursor_x: ndc_cursor_position.0 as f32,
-            ndc_cursor_y: ndc_cursor_position.1 as f32,
-            time: time,
-            tex: texture,
-            cells_to_highlight: cells_to_highlight
-        };
+        target: &mut S,
+        vertices: V,
+        indices: I,
+        uniforms: &U,
+        draw_parameters: &DrawParameters,
+    ) where
+        S: Surface,
+        V: MultiVerticesSource<'a>,
+        I: Into<IndicesSource<'a>>,
+        U: Uniforms,
+    {
         target
-            .draw(
-                &vertex_buffer,
-                indices,
-                &self.program,
-                &uniforms,
-                &Default::default(),
-            )
+            .draw(vertices, indices, &self.program, uniforms, draw_parameters)
             .unwrap();
     }
 }
 
-pub fn get_board_shader(display: &Display) -> Shader {
-    Shader::compile(
-        utils::read_text_from_file("src/ui/shaders/data/board.vert"),
-        utils::read_text_from_file("src/ui/shaders/data/board.frag"),
-        display,
-    )
-}
-
-pub fn get_piece_shader(display: &Display) -> Shader {
-    Shader::compile(
-        utils::read_text_from_file("src/ui/shaders/data/piece.vert"),
-        utils::read_text_from_file("src/ui/shaders/data/piece.frag"),
-        display,
-    )
+pub fn get_board_shader<F: Facade>(facade: &F, assets: &Assets) -> Result<Shader, AssetError> {
+    Shader::compile_assets("shaders/board.vert", "shaders/board.frag", facade, assets)
 }
 
-pub fn get_texture(image: RawImage2d<u8>, display: &Display) -> glium::texture::SrgbTexture2d {
-    glium::texture::SrgbTexture2d::new(display, image).unwrap()
+pub fn get_piece_shader<F: Facade>(facade: &F, assets: &Assets) -> Result<Shader, AssetError> {
+    Shader::compile_assets("shaders/piece.vert", "shaders/piece.frag", facade, assets)
 }
 
-pub fn get_textures(display: &Display) -> HashMap<(PieceKind, Color), SrgbTexture2d> {
-    let mut mapping: HashMap<(PieceKind, Color), SrgbTexture2d> = HashMap::new();
-    // TODO image::open("src/ui/resources/pieces/light/king.png")
-
-    let image = image::load(
-        Cursor::new(&include_bytes!(
-            "/Users/egortimofeev/Documents/rust/chess/src/ui/resources/pieces/light/pawn.png"
-        )),
-        image::ImageFormat::Png,
-    )
-    .unwrap()
-    .to_rgba8();
-    let image_dimensions = image.dimensions();
-    let image =
-        glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    mapping.insert((PieceKind::Pawn, Color::Light), get_texture(image, display));
-
-    let image = image::load(
-        Cursor::new(&include_bytes!(
-            "/Users/egortimofeev/Documents/rust/chess/src/ui/resources/pieces/dark/pawn.png"
-        )),
-        image::ImageFormat::Png,
-    )
-    .unwrap()
-    .to_rgba8();
+pub fn get_texture<F: Facade>(
+    image: RgbaImage,
+    name: &str,
+    facade: &F,
+) -> Result<SrgbTexture2d, AssetError> {
     let image_dimensions = image.dimensions();
-    let image =
-        glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    mapping.insert((PieceKind::Pawn, Color::Dark), get_texture(image, display));
-
-    let image = image::load(
-        Cursor::new(&include_bytes!(
-            "/Users/egortimofeev/Documents/rust/chess/src/ui/resources/pieces/light/rook.png"
-        )),
-        image::ImageFormat::Png,
-    )
-    .unwrap()
-    .to_rgba8();
-    let image_dimensions = image.dimensions();
-    let image =
-        glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    mapping.insert((PieceKind::Rook, Color::Light), get_texture(image, display));
-
-    let image = image::load(
-        Cursor::new(&include_bytes!(
-            "/Users/egortimofeev/Documents/rust/chess/src/ui/resources/pieces/dark/rook.png"
-        )),
-        image::ImageFormat::Png,
-    )
-    .unwrap()
-    .to_rgba8();
-    let image_dimensions = image.dimensions();
-    let image =
-        glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    mapping.insert((PieceKind::Rook, Color::Dark), get_texture(image, display));
+    let image = RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
+    SrgbTexture2d::new(facade, image).map_err(|error| AssetError::Texture {
+        name: name.to_owned(),
+        message: error.to_string(),
+    })
+}
 
-    let image = image::load(
-        Cursor::new(&include_bytes!(
-            "/Users/egortimofeev/Documents/rust/chess/src/ui/resources/pieces/light/knight.png"
-        )),
-        image::ImageFormat::Png,
-    )
-    .unwrap()
-    .to_rgba8();
-    let image_dimensions = image.dimensions();
-    let image =
-        glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    mapping.insert(
-        (PieceKind::Knight, Color::Light),
-        get_texture(image, display),
-    );
+/// All piece images of a theme packed into one texture, so every piece can
+/// be drawn by a single instanced call. PNG sets are packed once, SVG sets
+/// are rasterised for each requested on-screen pixel size.
+pub struct PieceAtlas {
+    theme: Theme,
+    keys: Vec<u32>,
+    texture: SrgbTexture2d,
+    regions: HashMap<(PieceKind, Color, u32), [f32; 4]>,
+}
 
-    let image = image::load(
-        Cursor::new(&include_bytes!(
-            "/Users/egortimofeev/Documents/rust/chess/src/ui/resources/pieces/dark/knight.png"
-        )),
-        image::ImageFormat::Png,
-    )
-    .unwrap()
-    .to_rgba8();
-    let image_dimensions = image.dimensions();
-    let image =
-        glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    mapping.insert(
-        (PieceKind::Knight, Color::Dark),
-        get_texture(image, display),
-    );
+impl PieceAtlas {
+    /// Pixel size SVG pieces are rasterised to before the first `prepare`.
+    const INITIAL_PIXEL_SIZE: u32 = 128;
+    /// Transparent gap around every image, keeps mipmaps from bleeding.
+    const PADDING: u32 = 4;
 
-    let image = image::load(
-        Cursor::new(&include_bytes!(
-            "/Users/egortimofeev/Documents/rust/chess/src/ui/resources/pieces/light/bishop.png"
-        )),
-        image::ImageFormat::Png,
-    )
-    .unwrap()
-    .to_rgba8();
-    let image_dimensions = image.dimensions();
-    let image =
-        glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    mapping.insert(
-        (PieceKind::Bishop, Color::Light),
-        get_texture(image, display),
-    );
+    pub fn load<F: Facade>(facade: &F, assets: &Assets, theme: &Theme) -> Result<Self, AssetError> {
+        Self::build(facade, assets, theme, &[Self::INITIAL_PIXEL_SIZE])
+    }
 
-    let image = image::load(
-        Cursor::new(&include_bytes!(
-            "/Users/egortimofeev/Documents/rust/chess/src/ui/resources/pieces/dark/bishop.png"
-        )),
-        image::ImageFormat::Png,
-    )
-    .unwrap()
-    .to_rgba8();
-    let image_dimensions = image.dimensions();
-    let image =
-        glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    mapping.insert(
-        (PieceKind::Bishop, Color::Dark),
-        get_texture(image, display),
-    );
+    /// Makes sure images for `pixel_sizes` are packed, repacking the atlas
+    /// (and dropping other sizes) only when the set of sizes changed.
+    pub fn prepare<F: Facade>(
+        &mut self,
+        facade: &F,
+        assets: &Assets,
+        pixel_sizes: &[u32],
+    ) -> Result<(), AssetError> {
+        if Self::keys(&self.theme, pixel_sizes) != self.keys {
+            *self = Self::build(facade, assets, &self.theme, pixel_sizes)?;
+        }
+        Ok(())
+    }
 
-    let image = image::load(
-        Cursor::new(&include_bytes!(
-            "/Users/egortimofeev/Documents/rust/chess/src/ui/resources/pieces/light/queen.png"
-        )),
-        image::ImageFormat::Png,
-    )
-    .unwrap()
-    .to_rgba8();
-    let image_dimensions = image.dimensions();
-    let image =
-        glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    mapping.insert(
-        (PieceKind::Queen, Color::Light),
-        get_texture(image, display),
-    );
+    pub fn texture(&self) -> &SrgbTexture2d {
+        &self.texture
+    }
 
-    let image = image::load(
-        Cursor::new(&include_bytes!(
-            "/Users/egortimofeev/Documents/rust/chess/src/ui/resources/pieces/dark/queen.png"
-        )),
-        image::ImageFormat::Png,
-    )
-    .unwrap()
-    .to_rgba8();
-    let image_dimensions = image.dimensions();
-    let image =
-        glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    mapping.insert((PieceKind::Queen, Color::Dark), get_texture(image, display));
+    /// Texture coordinates of a piece image as `[u, v, width, height]`.
+    pub fn region(&self, kind: PieceKind, color: Color, pixel_size: u32) -> Option<[f32; 4]> {
+        let key = Self::key(&self.theme, pixel_size);
+        self.regions.get(&(kind, color, key)).copied()
+    }
 
-    let image = image::load(
-        Cursor::new(&include_bytes!(
-            "/Users/egortimofeev/Documents/rust/chess/src/ui/resources/pieces/light/king.png"
-        )),
-        image::ImageFormat::Png,
-    )
-    .unwrap()
-    .to_rgba8();
-    let image_dimensions = image.dimensions();
-    let image =
-        glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    mapping.insert((PieceKind::King, Color::Light), get_texture(image, display));
+    fn build<F: Facade>(
+        facade: &F,
+        assets: &Assets,
+        theme: &Theme,
+        pixel_sizes: &[u32],
+    ) -> Result<Self, AssetError> {
+        let keys = Self::keys(theme, pixel_sizes);
+
+        // One shelf per (size, colour), six kinds side by side.
+        let mut shelves = Vec::new();
+        for key in &keys {
+            let mut images = theme.load_piece_images(assets, *key)?;
+            for color in Color::ALL {
+                let shelf: Vec<(PieceKind, RgbaImage)> = PieceKind::ALL
+                    .iter()
+                    .filter_map(|kind| images.remove(&(*kind, color)).map(|image| (*kind, image)))
+                    .collect();
+                shelves.push((*key, color, shelf));
+            }
+        }
+
+        let shelf_width = |shelf: &Vec<(PieceKind, RgbaImage)>| {
+            shelf
+                .iter()
+                .map(|(_, image)| image.width() + Self::PADDING)
+                .sum::<u32>()
+                + Self::PADDING
+        };
+        let shelf_height = |shelf: &Vec<(PieceKind, RgbaImage)>| {
+            shelf
+                .iter()
+                .map(|(_, image)| image.height())
+                .max()
+                .unwrap_or(0)
+                + Self::PADDING
+        };
+        let width = shelves
+            .iter()
+            .map(|(_, _, shelf)| shelf_width(shelf))
+            .max()
+            .unwrap_or(1);
+        let height = shelves
+            .iter()
+            .map(|(_, _, shelf)| shelf_height(shelf))
+            .sum::<u32>()
+            + Self::PADDING;
+
+        let mut atlas = RgbaImage::new(width, height);
+        let mut regions = HashMap::new();
+        let mut top = Self::PADDING;
+        for (key, color, shelf) in &shelves {
+            let mut left = Self::PADDING;
+            for (kind, image) in shelf {
+                image::imageops::replace(&mut atlas, image, left, top);
+                // The atlas is uploaded bottom-up, so v grows from the last row.
+                regions.insert(
+                    (*kind, *color, *key),
+                    [
+                        left as f32 / width as f32,
+                        (height - top - image.height()) as f32 / height as f32,
+                        image.width() as f32 / width as f32,
+                        image.height() as f32 / height as f32,
+                    ],
+                );
+                left += image.width() + Self::PADDING;
+            }
+            top += shelf_height(shelf);
+        }
+
+        let name = format!("{} piece atlas", theme.name);
+        Ok(PieceAtlas {
+            theme: theme.clone(),
+            keys,
+            texture: get_texture(atlas, &name, facade)?,
+            regions,
+        })
+    }
 
-    let image = image::load(
-        Cursor::new(&include_bytes!(
-            "/Users/egortimofeev/Documents/rust/chess/src/ui/resources/pieces/dark/king.png"
-        )),
-        image::ImageFormat::Png,
-    )
-    .unwrap()
-    .to_rgba8();
-    let image_dimensions = image.dimensions();
-    let image =
-        glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-    mapping.insert((PieceKind::King, Color::Dark), get_texture(image, display));
+    fn keys(theme: &Theme, pixel_sizes: &[u32]) -> Vec<u32> {
+        let mut keys: Vec<u32> = pixel_sizes
+            .iter()
+            .map(|size| Self::key(theme, *size))
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+        keys
+    }
 
-    mapping
+    fn key(theme: &Theme, pixel_size: u32) -> u32 {
+        if theme.is_resolution_independent() {
+            pixel_size.max(1)
+        } else {
+            0
+        }
+    }
 }
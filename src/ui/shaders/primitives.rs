@@ -9,7 +9,7 @@ pub struct Vertex {
 impl Vertex {
     pub fn from_position(position: [f32; 2]) -> Vertex {
         Vertex {
-            position: position,
+            position,
             texture_coords: [0.0, 0.0],
         }
     }
@@ -19,10 +19,51 @@ impl Vertex {
         texture_coords: [f32; 2],
     ) -> Vertex {
         Vertex {
-            position: position,
-            texture_coords: texture_coords,
+            position,
+            texture_coords,
         }
     }
 }
 
 implement_vertex!(Vertex, position, texture_coords);
+
+/// Per-instance attributes of a piece quad.
+#[derive(Copy, Clone)]
+pub struct PieceInstance {
+    /// Lower-left corner in board NDC.
+    pub instance_offset: [f32; 2],
+    /// Side of the quad in board NDC.
+    pub instance_size: f32,
+    pub atlas_offset: [f32; 2],
+    pub atlas_size: [f32; 2],
+    /// Opacity, below 1 while a piece fades in or out.
+    pub instance_alpha: f32,
+}
+
+impl PieceInstance {
+    pub fn new(lower_left_position: [f32; 2], size: f32, atlas_region: [f32; 4]) -> PieceInstance {
+        PieceInstance {
+            instance_offset: lower_left_position,
+            instance_size: size,
+            atlas_offset: [atlas_region[0], atlas_region[1]],
+            atlas_size: [atlas_region[2], atlas_region[3]],
+            instance_alpha: 1.0,
+        }
+    }
+
+    pub fn with_alpha(self, alpha: f32) -> PieceInstance {
+        PieceInstance {
+            instance_alpha: alpha,
+            ..self
+        }
+    }
+}
+
+implement_vertex!(
+    PieceInstance,
+    instance_offset,
+    instance_size,
+    atlas_offset,
+    atlas_size,
+    instance_alpha
+);
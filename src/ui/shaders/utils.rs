@@ -1,6 +0,0 @@
-use std::fs;
-
-pub fn read_text_from_file(file_path: &str) -> String {
-    let contents = fs::read_to_string(file_path).expect("Should have been able to read the file");
-    contents
-}
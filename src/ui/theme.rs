@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+
+use image::RgbaImage;
+
+use crate::backend::{Color, PieceKind};
+use crate::ui::assets::{AssetError, Assets};
+
+/// Directory, relative to the assets root, that is scanned for `*.theme` files.
+pub const THEMES_DIR: &str = "themes";
+pub const DEFAULT_THEME: &str = "themes/classic.theme";
+
+const DEFAULT_LAST_MOVE_COLOR: [f32; 3] = [0.84, 0.77, 0.2];
+const DEFAULT_CHECK_COLOR: [f32; 3] = [0.88, 0.13, 0.13];
+const DEFAULT_SELECTION_COLOR: [f32; 3] = [0.24, 0.55, 0.24];
+const DEFAULT_PREMOVE_COLOR: [f32; 3] = [0.24, 0.39, 0.71];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PieceSet {
+    /// Directory with `light/<kind>.png` and `dark/<kind>.png` images.
+    Png(String),
+    /// Same layout as `Png`, with `.svg` files, rasterised at the size pieces are drawn.
+    Svg(String),
+}
+
+/// Board look: piece images and the colours fed to `board.frag`.
+///
+/// Described by a `key = value` file, e.g.
+///
+/// ```text
+/// name = Classic
+/// pieces = png:pieces
+/// light_square = #c2b08c
+/// dark_square = #241609
+/// highlight = #00ff00
+/// last_move = #d6c534
+/// check = #e02020
+/// selection = #3c8c3c
+/// premove = #3c64b4
+/// border = #1a0d0d
+/// ```
+///
+/// `last_move`, `check`, `selection` and `premove` are optional.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    pub pieces: PieceSet,
+    pub light_square: [f32; 3],
+    pub dark_square: [f32; 3],
+    /// Legal target outline.
+    pub highlight: [f32; 3],
+    pub last_move: [f32; 3],
+    pub check: [f32; 3],
+    pub selection: [f32; 3],
+    pub premove: [f32; 3],
+    pub border: [f32; 3],
+}
+
+impl Theme {
+    pub fn parse(asset_name: &str, text: &str) -> Result<Theme, AssetError> {
+        let error = |message: String| AssetError::Config {
+            name: asset_name.to_owned(),
+            message,
+        };
+
+        let mut values: HashMap<&str, &str> = HashMap::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once('=') {
+                Some((key, value)) => {
+                    values.insert(key.trim(), value.trim());
+                }
+                None => {
+                    return Err(error(format!(
+                        "line {}: expected `key = value`",
+                        line_number + 1
+                    )))
+                }
+            }
+        }
+
+        let value = |key: &str| {
+            values
+                .get(key)
+                .copied()
+                .ok_or_else(|| error(format!("missing `{}`", key)))
+        };
+        let color = |key: &str| {
+            value(key).and_then(|hex| {
+                parse_hex_color(hex).ok_or_else(|| {
+                    error(format!(
+                        "`{}` should be a #rrggbb colour, got `{}`",
+                        key, hex
+                    ))
+                })
+            })
+        };
+
+        let optional_color = |key: &str, default: [f32; 3]| {
+            if values.contains_key(key) {
+                color(key)
+            } else {
+                Ok(default)
+            }
+        };
+
+        let pieces = match value("pieces")?.split_once(':') {
+            Some(("png", directory)) => PieceSet::Png(directory.trim().to_owned()),
+            Some(("svg", directory)) => PieceSet::Svg(directory.trim().to_owned()),
+            _ => {
+                return Err(error(
+                    "`pieces` should be `png:<directory>` or `svg:<directory>`".to_owned(),
+                ))
+            }
+        };
+
+        Ok(Theme {
+            name: value("name")?.to_owned(),
+            pieces,
+            light_square: color("light_square")?,
+            dark_square: color("dark_square")?,
+            highlight: color("highlight")?,
+            last_move: optional_color("last_move", DEFAULT_LAST_MOVE_COLOR)?,
+            check: optional_color("check", DEFAULT_CHECK_COLOR)?,
+            selection: optional_color("selection", DEFAULT_SELECTION_COLOR)?,
+            premove: optional_color("premove", DEFAULT_PREMOVE_COLOR)?,
+            border: color("border")?,
+        })
+    }
+
+    pub fn is_resolution_independent(&self) -> bool {
+        matches!(self.pieces, PieceSet::Svg(_))
+    }
+
+    pub fn load(assets: &Assets, asset_name: &str) -> Result<Theme, AssetError> {
+        Theme::parse(asset_name, &assets.read_text(asset_name)?)
+    }
+
+    /// Asset name of a piece image and its MIME type.
+    pub fn piece_image_asset(&self, kind: PieceKind, color: Color) -> (String, &'static str) {
+        match &self.pieces {
+            PieceSet::Png(directory) => {
+                (piece_image_name(directory, kind, color, "png"), "image/png")
+            }
+            PieceSet::Svg(directory) => (
+                piece_image_name(directory, kind, color, "svg"),
+                "image/svg+xml",
+            ),
+        }
+    }
+
+    /// Piece images of the theme. PNG sets come at their native size, SVG sets
+    /// are rasterised to `svg_pixel_size` squares.
+    pub fn load_piece_images(
+        &self,
+        assets: &Assets,
+        svg_pixel_size: u32,
+    ) -> Result<HashMap<(PieceKind, Color), RgbaImage>, AssetError> {
+        let mut images = HashMap::new();
+        for color in Color::ALL {
+            for kind in PieceKind::ALL {
+                let (name, _) = self.piece_image_asset(kind, color);
+                let image = match &self.pieces {
+                    PieceSet::Png(_) => assets.load_image(&name)?,
+                    PieceSet::Svg(_) => assets.load_svg(&name, svg_pixel_size)?,
+                };
+                images.insert((kind, color), image);
+            }
+        }
+        Ok(images)
+    }
+}
+
+/// Names of all theme files, bundled and from the override directory.
+pub fn list_themes(assets: &Assets) -> Vec<String> {
+    assets
+        .list(THEMES_DIR)
+        .into_iter()
+        .filter(|name| name.ends_with(".theme"))
+        .collect()
+}
+
+pub fn piece_image_name(directory: &str, kind: PieceKind, color: Color, extension: &str) -> String {
+    let color = match color {
+        Color::Light => "light",
+        Color::Dark => "dark",
+    };
+    let kind = match kind {
+        PieceKind::Pawn => "pawn",
+        PieceKind::Rook => "rook",
+        PieceKind::Bishop => "bishop",
+        PieceKind::Knight => "knight",
+        PieceKind::Queen => "queen",
+        PieceKind::King => "king",
+    };
+    format!("{}/{}/{}.{}", directory, color, kind, extension)
+}
+
+fn parse_hex_color(hex: &str) -> Option<[f32; 3]> {
+    let hex = hex.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |index: usize| {
+        u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16)
+            .ok()
+            .map(|value| value as f32 / 255.0)
+    };
+    Some([channel(0)?, channel(1)?, channel(2)?])
+}
+
+/// Loads every listed theme. The ones that fail are left out, their errors
+/// are returned alongside for the caller to show.
+pub fn load_themes(assets: &Assets) -> (Vec<(String, Theme)>, Vec<AssetError>) {
+    let mut themes = Vec::new();
+    let mut errors = Vec::new();
+    for name in list_themes(assets) {
+        match Theme::load(assets, &name) {
+            Ok(theme) => themes.push((name, theme)),
+            Err(error) => errors.push(error),
+        }
+    }
+    (themes, errors)
+}
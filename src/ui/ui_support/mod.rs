@@ -1,9 +1,9 @@
 use glium::glutin;
-use glium::glutin::event::{ElementState, Event, MouseButton, WindowEvent};
+use glium::glutin::event::{ElementState, Event, ModifiersState, MouseButton, WindowEvent};
 use glium::glutin::event_loop::{ControlFlow, EventLoop};
 use glium::glutin::window::WindowBuilder;
-use glium::Display;
-use imgui::{Context, FontConfig, FontGlyphRanges, FontSource};
+use glium::{Display, Frame};
+use imgui::{Context, FontConfig, FontGlyphRanges, FontSource, Ui};
 use imgui_glium_renderer::Renderer;
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
 use std::path::Path;
@@ -11,11 +11,28 @@ use std::time::Instant;
 
 mod clipboard;
 
+const MIN_BOARD_SIZE: f64 = 240.0;
+
 pub struct AppSettings {
     pub window_width: f64,
     pub window_height: f64,
     pub window_name: String,
     pub max_fps: f32,
+    /// Space kept free on both sides of the board for panels.
+    pub side_panel_width: f64,
+    /// Gap between the board and the window edges or side panels.
+    pub board_margin: f64,
+}
+
+/// Input snapshot handed to the frame callback.
+pub struct FrameInput {
+    /// Cursor position in logical pixels, origin at the top-left corner of the window.
+    pub cursor_position: (f64, f64),
+    pub left_mouse_button: ElementState,
+    pub right_mouse_button: ElementState,
+    pub modifiers: ModifiersState,
+    /// Physical pixels per logical pixel, as reported by winit for the current monitor.
+    pub scale_factor: f64,
 }
 
 pub struct System {
@@ -24,7 +41,6 @@ pub struct System {
     pub imgui: Context,
     pub platform: WinitPlatform,
     pub renderer: Renderer,
-    pub font_size: f32,
     max_fps: f32,
 }
 
@@ -41,7 +57,11 @@ pub fn init(settings: &AppSettings) -> System {
             settings.window_width,
             settings.window_height,
         ))
-        .with_resizable(false);
+        .with_min_inner_size(glutin::dpi::LogicalSize::new(
+            2.0 * (settings.side_panel_width + settings.board_margin) + MIN_BOARD_SIZE,
+            2.0 * settings.board_margin + MIN_BOARD_SIZE,
+        ))
+        .with_resizable(true);
     let display =
         Display::new(builder, context, &event_loop).expect("Failed to initialize display");
 
@@ -119,13 +139,12 @@ pub fn init(settings: &AppSettings) -> System {
         imgui,
         platform,
         renderer,
-        font_size,
         max_fps: settings.max_fps,
     }
 }
 
 impl System {
-    pub fn main_loop<F: FnMut(&mut bool, &Display, &(f64, f64), &ElementState) + 'static>(
+    pub fn main_loop<F: FnMut(&mut bool, &Display, &mut Frame, &mut Ui, &FrameInput) + 'static>(
         self,
         mut run_ui: F,
     ) {
@@ -134,16 +153,23 @@ impl System {
             display,
             mut imgui,
             mut platform,
+            mut renderer,
             ..
         } = self;
         let mut last_frame = Instant::now();
         let mut prev_frame_t = Instant::now();
 
-        let mut cursor_position: (f64, f64) = (0.0, 0.0);
-        let mut mouse_input_state: ElementState = ElementState::Released;
+        let mut frame_input = FrameInput {
+            cursor_position: (0.0, 0.0),
+            left_mouse_button: ElementState::Released,
+            right_mouse_button: ElementState::Released,
+            modifiers: ModifiersState::empty(),
+            scale_factor: display.gl_window().window().scale_factor(),
+        };
 
         event_loop.run(move |event, _, control_flow| {
             *control_flow = ControlFlow::Wait;
+            platform.handle_event(imgui.io_mut(), display.gl_window().window(), &event);
 
             match event {
                 Event::NewEvents(_) => {
@@ -160,12 +186,21 @@ impl System {
                 }
                 Event::RedrawRequested(_) => {
                     let redraw_request_t = Instant::now();
-                    if (redraw_request_t - prev_frame_t).as_secs_f32() > 1. / self.max_fps as f32 {
+                    if (redraw_request_t - prev_frame_t).as_secs_f32() > 1. / self.max_fps {
+                        let ui = imgui.frame();
+                        let mut target = display.draw();
                         let mut run = true;
-                        run_ui(&mut run, &display, &cursor_position, &mouse_input_state);
+                        run_ui(&mut run, &display, &mut target, ui, &frame_input);
                         if !run {
                             *control_flow = ControlFlow::Exit;
                         }
+
+                        platform.prepare_render(ui, display.gl_window().window());
+                        let draw_data = imgui.render();
+                        renderer
+                            .render(&mut target, draw_data)
+                            .expect("Rendering failed");
+                        target.finish().expect("Failed to swap buffers");
                         prev_frame_t = Instant::now();
                     }
                 }
@@ -173,27 +208,38 @@ impl System {
                     WindowEvent::CloseRequested => {
                         println!("Received termination signal.");
                         *control_flow = glutin::event_loop::ControlFlow::Exit;
-                        return;
                     }
-                    WindowEvent::CursorMoved { position, .. } => {
-                        cursor_position = (position.x, position.y);
+                    WindowEvent::Resized(size) => {
+                        display.gl_window().resize(size);
                     }
-                    WindowEvent::MouseInput {
-                        device_id: _,
-                        state,
-                        button,
-                        modifiers: _,
+                    WindowEvent::ScaleFactorChanged {
+                        scale_factor,
+                        new_inner_size,
                     } => {
-                        if button == MouseButton::Left {
-                            mouse_input_state = state;
+                        frame_input.scale_factor = scale_factor;
+                        display.gl_window().resize(*new_inner_size);
+                    }
+                    WindowEvent::CursorMoved { position, .. } => {
+                        let position = position.to_logical::<f64>(frame_input.scale_factor);
+                        frame_input.cursor_position = (position.x, position.y);
+                    }
+                    WindowEvent::ModifiersChanged(modifiers) => {
+                        frame_input.modifiers = modifiers;
+                    }
+                    // Presses over panels belong to imgui, releases always reach the board
+                    // so a drag started on it can finish anywhere.
+                    WindowEvent::MouseInput { state, button, .. }
+                        if state == ElementState::Released || !imgui.io().want_capture_mouse =>
+                    {
+                        match button {
+                            MouseButton::Left => frame_input.left_mouse_button = state,
+                            MouseButton::Right => frame_input.right_mouse_button = state,
+                            _ => (),
                         }
                     }
-                    _ => return,
+                    _ => (),
                 },
-                event => {
-                    let gl_window = display.gl_window();
-                    platform.handle_event(imgui.io_mut(), gl_window.window(), &event);
-                }
+                _ => (),
             }
         })
     }